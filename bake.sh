@@ -32,6 +32,10 @@ cp "${input}/LICENSE-CHROMIUM" "${output}/"
 cp "${input}/README.md" "${output}/"
 mkdir "${output}/src/"
 cp "${input}/src/lib.rs" "${output}/src/"
+cp "${input}/src/client.rs" "${output}/src/"
+cp "${input}/src/fmt.rs" "${output}/src/"
+cp "${input}/src/limits.rs" "${output}/src/"
+cp "${input}/src/server.rs" "${output}/src/"
 cp "${input}/src/generated.rs" "${output}/src/"
 cp -r "${input}/tests/" "${output}/"
 
@@ -0,0 +1,470 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Domain, Field, Method, Type};
+use visit::split_reference;
+
+#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "change")]
+pub enum ChangeKind {
+    #[serde(rename = "versionChanged")]
+    VersionChanged { old: String, new: String },
+    #[serde(rename = "domainAdded")]
+    DomainAdded,
+    #[serde(rename = "domainRemoved")]
+    DomainRemoved,
+    #[serde(rename = "typeAdded")]
+    TypeDefAdded,
+    #[serde(rename = "typeRemoved")]
+    TypeDefRemoved,
+    #[serde(rename = "commandAdded")]
+    CommandAdded,
+    #[serde(rename = "commandRemoved")]
+    CommandRemoved,
+    #[serde(rename = "eventAdded")]
+    EventAdded,
+    #[serde(rename = "eventRemoved")]
+    EventRemoved,
+    #[serde(rename = "fieldAdded")]
+    FieldAdded { optional: bool },
+    #[serde(rename = "fieldRemoved")]
+    FieldRemoved,
+    #[serde(rename = "fieldBecameOptional")]
+    FieldBecameOptional,
+    #[serde(rename = "fieldBecameRequired")]
+    FieldBecameRequired,
+    #[serde(rename = "enumValueAdded")]
+    EnumValueAdded { value: String },
+    #[serde(rename = "enumValueRemoved")]
+    EnumValueRemoved { value: String },
+    #[serde(rename = "typeChanged")]
+    TypeChanged { old: String, new: String },
+    #[serde(rename = "experimentalChanged")]
+    ExperimentalChanged { experimental: bool },
+    #[serde(rename = "deprecatedChanged")]
+    DeprecatedChanged { deprecated: bool },
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ChangeKind::VersionChanged { ref old, ref new } => {
+                write!(f, "version changed from {} to {}", old, new)
+            }
+            ChangeKind::DomainAdded => write!(f, "domain added"),
+            ChangeKind::DomainRemoved => write!(f, "domain removed"),
+            ChangeKind::TypeDefAdded => write!(f, "type added"),
+            ChangeKind::TypeDefRemoved => write!(f, "type removed"),
+            ChangeKind::CommandAdded => write!(f, "command added"),
+            ChangeKind::CommandRemoved => write!(f, "command removed"),
+            ChangeKind::EventAdded => write!(f, "event added"),
+            ChangeKind::EventRemoved => write!(f, "event removed"),
+            ChangeKind::FieldAdded { optional: true } => write!(f, "optional field added"),
+            ChangeKind::FieldAdded { optional: false } => write!(f, "required field added"),
+            ChangeKind::FieldRemoved => write!(f, "field removed"),
+            ChangeKind::FieldBecameOptional => write!(f, "field became optional"),
+            ChangeKind::FieldBecameRequired => write!(f, "field became required"),
+            ChangeKind::EnumValueAdded { ref value } => write!(f, "enum value '{}' added", value),
+            ChangeKind::EnumValueRemoved { ref value } => {
+                write!(f, "enum value '{}' removed", value)
+            }
+            ChangeKind::TypeChanged { ref old, ref new } => {
+                write!(f, "type changed from {} to {}", old, new)
+            }
+            ChangeKind::ExperimentalChanged { experimental: true } => {
+                write!(f, "became experimental")
+            }
+            ChangeKind::ExperimentalChanged { experimental: false } => {
+                write!(f, "no longer experimental")
+            }
+            ChangeKind::DeprecatedChanged { deprecated: true } => write!(f, "became deprecated"),
+            ChangeKind::DeprecatedChanged { deprecated: false } => {
+                write!(f, "no longer deprecated")
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub kind: ChangeKind,
+    pub breaking: bool,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let label = if self.breaking {
+            "breaking"
+        } else {
+            "non-breaking"
+        };
+        write!(f, "{}: {}: {}", label, self.path, self.kind)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|change| change.breaking)
+    }
+
+    pub fn breaking_changes(&self) -> Vec<&Change> {
+        self.changes.iter().filter(|change| change.breaking).collect()
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+// Which way the data in a field flows, which decides whether a change can
+// break existing clients. Type definitions can be used either way, so they're
+// held to both standards.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Direction {
+    Input,
+    Output,
+    Both,
+}
+
+impl Direction {
+    fn is_input(self) -> bool {
+        self != Direction::Output
+    }
+
+    fn is_output(self) -> bool {
+        self != Direction::Input
+    }
+}
+
+pub fn diff(old: &Definition, new: &Definition) -> Diff {
+    let mut differ = Differ {
+        changes: Vec::new(),
+    };
+
+    if old.version != new.version {
+        differ.push(
+            "version",
+            ChangeKind::VersionChanged {
+                old: old.version.to_string(),
+                new: new.version.to_string(),
+            },
+            false,
+        );
+    }
+
+    differ.diff_named(
+        "",
+        &old.domains,
+        &new.domains,
+        |domain| &domain.name,
+        (ChangeKind::DomainAdded, ChangeKind::DomainRemoved),
+        |differ, path, old, new| differ.diff_domain(path, old, new),
+    );
+
+    Diff {
+        changes: differ.changes,
+    }
+}
+
+struct Differ {
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn push(&mut self, path: &str, kind: ChangeKind, breaking: bool) {
+        self.changes.push(Change {
+            path: path.into(),
+            kind,
+            breaking,
+        });
+    }
+
+    // Pairs up items by name, reporting the ones that only appear on one side
+    // and handing the rest to `diff_common`.
+    fn diff_named<'a, T, N, F>(
+        &mut self,
+        parent_path: &str,
+        old: &'a [T],
+        new: &'a [T],
+        name: N,
+        kinds: (ChangeKind, ChangeKind),
+        mut diff_common: F,
+    ) where
+        N: Fn(&'a T) -> &'a String,
+        F: FnMut(&mut Self, &str, &'a T, &'a T),
+    {
+        let (added, removed) = kinds;
+        for old_item in old {
+            let path = join_path(parent_path, name(old_item));
+            match new.iter().find(|new_item| name(new_item) == name(old_item)) {
+                None => self.push(&path, removed.clone(), true),
+                Some(new_item) => diff_common(self, &path, old_item, new_item),
+            }
+        }
+        for new_item in new {
+            if !old.iter().any(|old_item| name(old_item) == name(new_item)) {
+                let path = join_path(parent_path, name(new_item));
+                self.push(&path, added.clone(), false);
+            }
+        }
+    }
+
+    fn diff_flags(&mut self, path: &str, old: (bool, bool), new: (bool, bool)) {
+        if old.0 != new.0 {
+            self.push(path, ChangeKind::ExperimentalChanged { experimental: new.0 }, false);
+        }
+        if old.1 != new.1 {
+            self.push(path, ChangeKind::DeprecatedChanged { deprecated: new.1 }, false);
+        }
+    }
+
+    fn diff_domain(&mut self, path: &str, old: &Domain, new: &Domain) {
+        self.diff_flags(
+            path,
+            (old.experimental, old.deprecated),
+            (new.experimental, new.deprecated),
+        );
+        self.diff_named(
+            path,
+            &old.type_defs,
+            &new.type_defs,
+            |type_def| &type_def.name,
+            (ChangeKind::TypeDefAdded, ChangeKind::TypeDefRemoved),
+            |differ, path, old_type_def, new_type_def| {
+                differ.diff_flags(
+                    path,
+                    (old_type_def.experimental, old_type_def.deprecated),
+                    (new_type_def.experimental, new_type_def.deprecated),
+                );
+                differ.diff_type(
+                    path,
+                    (&old.name, &old_type_def.ty),
+                    (&new.name, &new_type_def.ty),
+                    Direction::Both,
+                );
+            },
+        );
+        self.diff_named(
+            path,
+            &old.commands,
+            &new.commands,
+            |command| &command.name,
+            (ChangeKind::CommandAdded, ChangeKind::CommandRemoved),
+            |differ, path, old_command, new_command| {
+                differ.diff_method(
+                    path,
+                    (&old.name, old_command),
+                    (&new.name, new_command),
+                    Direction::Input,
+                );
+            },
+        );
+        self.diff_named(
+            path,
+            &old.events,
+            &new.events,
+            |event| &event.name,
+            (ChangeKind::EventAdded, ChangeKind::EventRemoved),
+            |differ, path, old_event, new_event| {
+                // Event parameters flow from the browser to the client, like
+                // command return values.
+                differ.diff_method(
+                    path,
+                    (&old.name, old_event),
+                    (&new.name, new_event),
+                    Direction::Output,
+                );
+            },
+        );
+    }
+
+    fn diff_method(
+        &mut self,
+        path: &str,
+        old: (&str, &Method),
+        new: (&str, &Method),
+        parameters_direction: Direction,
+    ) {
+        let (old_domain, old_method) = old;
+        let (new_domain, new_method) = new;
+        self.diff_flags(
+            path,
+            (old_method.experimental, old_method.deprecated),
+            (new_method.experimental, new_method.deprecated),
+        );
+        self.diff_fields(
+            path,
+            (old_domain, &old_method.parameters),
+            (new_domain, &new_method.parameters),
+            parameters_direction,
+        );
+        self.diff_fields(
+            path,
+            (old_domain, &old_method.returns),
+            (new_domain, &new_method.returns),
+            Direction::Output,
+        );
+    }
+
+    fn diff_fields(
+        &mut self,
+        path: &str,
+        old: (&str, &[Field]),
+        new: (&str, &[Field]),
+        direction: Direction,
+    ) {
+        let (old_domain, old_fields) = old;
+        let (new_domain, new_fields) = new;
+
+        for old_field in old_fields {
+            let field_path = join_path(path, &old_field.name);
+            let new_field = match new_fields.iter().find(|f| f.name == old_field.name) {
+                Some(new_field) => new_field,
+                None => {
+                    self.push(&field_path, ChangeKind::FieldRemoved, true);
+                    continue;
+                }
+            };
+
+            self.diff_flags(
+                &field_path,
+                (old_field.experimental, old_field.deprecated),
+                (new_field.experimental, new_field.deprecated),
+            );
+            match (old_field.optional, new_field.optional) {
+                (false, true) => {
+                    let breaking = direction.is_output();
+                    self.push(&field_path, ChangeKind::FieldBecameOptional, breaking);
+                }
+                (true, false) => {
+                    let breaking = direction.is_input();
+                    self.push(&field_path, ChangeKind::FieldBecameRequired, breaking);
+                }
+                _ => (),
+            }
+            self.diff_type(
+                &field_path,
+                (old_domain, &old_field.ty),
+                (new_domain, &new_field.ty),
+                direction,
+            );
+        }
+
+        for new_field in new_fields {
+            if !old_fields.iter().any(|f| f.name == new_field.name) {
+                let field_path = join_path(path, &new_field.name);
+                let kind = ChangeKind::FieldAdded {
+                    optional: new_field.optional,
+                };
+                let breaking = !new_field.optional && direction.is_input();
+                self.push(&field_path, kind, breaking);
+            }
+        }
+    }
+
+    fn diff_type(
+        &mut self,
+        path: &str,
+        old: (&str, &Type),
+        new: (&str, &Type),
+        direction: Direction,
+    ) {
+        let (old_domain, old_ty) = old;
+        let (new_domain, new_ty) = new;
+        match (old_ty, new_ty) {
+            (Type::Enum(old_values), Type::Enum(new_values)) => {
+                for value in old_values {
+                    if !new_values.contains(value) {
+                        let kind = ChangeKind::EnumValueRemoved {
+                            value: value.clone(),
+                        };
+                        self.push(path, kind, direction.is_input());
+                    }
+                }
+                for value in new_values {
+                    if !old_values.contains(value) {
+                        let kind = ChangeKind::EnumValueAdded {
+                            value: value.clone(),
+                        };
+                        self.push(path, kind, direction.is_output());
+                    }
+                }
+            }
+            (Type::Object(old_fields), Type::Object(new_fields)) => self.diff_fields(
+                path,
+                (old_domain, old_fields),
+                (new_domain, new_fields),
+                direction,
+            ),
+            (
+                Type::Array {
+                    item: old_item, ..
+                },
+                Type::Array {
+                    item: new_item, ..
+                },
+            ) => self.diff_type(
+                path,
+                (old_domain, &old_item.ty),
+                (new_domain, &new_item.ty),
+                direction,
+            ),
+            _ => {
+                let old_description = describe_type(old_domain, old_ty);
+                let new_description = describe_type(new_domain, new_ty);
+                if old_description != new_description {
+                    let kind = ChangeKind::TypeChanged {
+                        old: old_description,
+                        new: new_description,
+                    };
+                    self.push(path, kind, true);
+                }
+            }
+        }
+    }
+}
+
+fn describe_type(domain_name: &str, ty: &Type) -> String {
+    match *ty {
+        Type::Reference(ref target) => {
+            let (domain_name, type_name) = split_reference(domain_name, target);
+            format!("{}.{}", domain_name, type_name)
+        }
+        Type::Boolean => "boolean".into(),
+        Type::Integer => "integer".into(),
+        Type::Number => "number".into(),
+        Type::String => "string".into(),
+        Type::Enum(_) => "enum".into(),
+        Type::Array { ref item, .. } => {
+            format!("array of {}", describe_type(domain_name, &item.ty))
+        }
+        Type::Object(_) => "object".into(),
+        Type::Any => "any".into(),
+    }
+}
+
+fn join_path(parent_path: &str, name: &str) -> String {
+    if parent_path.is_empty() {
+        name.into()
+    } else {
+        format!("{}.{}", parent_path, name)
+    }
+}
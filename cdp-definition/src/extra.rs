@@ -0,0 +1,152 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+// Unknown keys picked up by `lenient::from_str` are kept in each node's
+// `extra` map. There's no way to splice them into the derived `*Impl`
+// serializers, so nodes that carry any go through `serde_json::Value` instead.
+
+use serde::{Serialize, Serializer};
+use serde::ser;
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+
+use {Definition, Domain, Field, Item, Method, Type, TypeDef};
+
+pub type Extra = BTreeMap<String, Value>;
+
+pub trait HasExtra {
+    fn has_extra(&self) -> bool;
+    fn insert_extra(&self, value: &mut Value);
+}
+
+pub fn serialize<N, T, S>(node: &N, node_impl: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    N: HasExtra,
+    T: Serialize,
+    S: Serializer,
+{
+    if !node.has_extra() {
+        return node_impl.serialize(serializer);
+    }
+
+    let mut value = serde_json::to_value(node_impl).map_err(ser::Error::custom)?;
+    node.insert_extra(&mut value);
+    value.serialize(serializer)
+}
+
+impl HasExtra for Definition {
+    fn has_extra(&self) -> bool {
+        !self.extra.is_empty() || self.domains.iter().any(HasExtra::has_extra)
+    }
+
+    fn insert_extra(&self, value: &mut Value) {
+        insert_own(&self.extra, value);
+        insert_children(&self.domains, "domains", value);
+    }
+}
+
+impl HasExtra for Domain {
+    fn has_extra(&self) -> bool {
+        !self.extra.is_empty() || self.type_defs.iter().any(HasExtra::has_extra)
+            || self.commands.iter().any(HasExtra::has_extra)
+            || self.events.iter().any(HasExtra::has_extra)
+    }
+
+    fn insert_extra(&self, value: &mut Value) {
+        insert_own(&self.extra, value);
+        insert_children(&self.type_defs, "types", value);
+        insert_children(&self.commands, "commands", value);
+        insert_children(&self.events, "events", value);
+    }
+}
+
+impl HasExtra for TypeDef {
+    fn has_extra(&self) -> bool {
+        !self.extra.is_empty() || type_has_extra(&self.ty)
+    }
+
+    fn insert_extra(&self, value: &mut Value) {
+        insert_own(&self.extra, value);
+        insert_type(&self.ty, value);
+    }
+}
+
+impl HasExtra for Method {
+    fn has_extra(&self) -> bool {
+        !self.extra.is_empty() || self.parameters.iter().any(HasExtra::has_extra)
+            || self.returns.iter().any(HasExtra::has_extra)
+    }
+
+    fn insert_extra(&self, value: &mut Value) {
+        insert_own(&self.extra, value);
+        insert_children(&self.parameters, "parameters", value);
+        insert_children(&self.returns, "returns", value);
+    }
+}
+
+impl HasExtra for Field {
+    fn has_extra(&self) -> bool {
+        !self.extra.is_empty() || type_has_extra(&self.ty)
+    }
+
+    fn insert_extra(&self, value: &mut Value) {
+        insert_own(&self.extra, value);
+        insert_type(&self.ty, value);
+    }
+}
+
+impl HasExtra for Item {
+    fn has_extra(&self) -> bool {
+        !self.extra.is_empty() || type_has_extra(&self.ty)
+    }
+
+    fn insert_extra(&self, value: &mut Value) {
+        insert_own(&self.extra, value);
+        insert_type(&self.ty, value);
+    }
+}
+
+fn type_has_extra(ty: &Type) -> bool {
+    match *ty {
+        Type::Array { ref item, .. } => item.has_extra(),
+        Type::Object(ref properties) => properties.iter().any(HasExtra::has_extra),
+        _ => false,
+    }
+}
+
+fn insert_type(ty: &Type, value: &mut Value) {
+    match *ty {
+        Type::Array { ref item, .. } => if let Value::Object(ref mut map) = *value {
+            if let Some(item_value) = map.get_mut("items") {
+                item.insert_extra(item_value);
+            }
+        },
+        Type::Object(ref properties) => insert_children(properties, "properties", value),
+        _ => (),
+    }
+}
+
+// Keys the definition format knows about always win over extra ones.
+fn insert_own(extra: &Extra, value: &mut Value) {
+    if let Value::Object(ref mut map) = *value {
+        for (key, extra_value) in extra {
+            if !map.contains_key(key) {
+                map.insert(key.clone(), extra_value.clone());
+            }
+        }
+    }
+}
+
+fn insert_children<N>(nodes: &[N], key: &str, value: &mut Value)
+where
+    N: HasExtra,
+{
+    if let Value::Object(ref mut map) = *value {
+        if let Some(&mut Value::Array(ref mut values)) = map.get_mut(key) {
+            for (node, value) in nodes.iter().zip(values.iter_mut()) {
+                node.insert_extra(value);
+            }
+        }
+    }
+}
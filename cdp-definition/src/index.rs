@@ -0,0 +1,239 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use {Definition, Domain, Method, Type, TypeDef};
+use resolve::{ResolvedType, Resolver};
+use visit::{walk_fields, walk_type};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ItemRef<'a> {
+    TypeDef(&'a Domain, &'a TypeDef),
+    Command(&'a Domain, &'a Method),
+    Event(&'a Domain, &'a Method),
+}
+
+impl<'a> ItemRef<'a> {
+    pub fn domain(&self) -> &'a Domain {
+        match *self {
+            ItemRef::TypeDef(domain, _) => domain,
+            ItemRef::Command(domain, _) | ItemRef::Event(domain, _) => domain,
+        }
+    }
+
+    pub fn name(&self) -> &'a str {
+        match *self {
+            ItemRef::TypeDef(_, type_def) => &type_def.name,
+            ItemRef::Command(_, method) | ItemRef::Event(_, method) => &method.name,
+        }
+    }
+
+    pub fn path(&self) -> String {
+        format!("{}.{}", self.domain().name, self.name())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldReference<'a> {
+    pub path: String,
+    pub item: ItemRef<'a>,
+    pub target: ResolvedType<'a>,
+}
+
+// Lookups by qualified name ("Page.navigate", "Network.RequestId") along with
+// a reverse-reference graph from each type to the items that refer to it.
+// Unresolved references are left out; `Definition::validate` reports them.
+#[derive(Clone, Debug)]
+pub struct DefinitionIndex<'a> {
+    resolver: Resolver<'a>,
+    items: Vec<ItemRef<'a>>,
+    commands: HashMap<String, usize>,
+    events: HashMap<String, usize>,
+    type_defs: HashMap<String, usize>,
+    references: Vec<FieldReference<'a>>,
+    referenced_by: HashMap<usize, Vec<(usize, usize)>>,
+}
+
+impl<'a> DefinitionIndex<'a> {
+    pub fn new(def: &'a Definition) -> Self {
+        let resolver = Resolver::new(def);
+
+        let mut items = Vec::new();
+        let mut commands = HashMap::new();
+        let mut events = HashMap::new();
+        let mut type_defs = HashMap::new();
+        for domain in &def.domains {
+            for type_def in &domain.type_defs {
+                let item = ItemRef::TypeDef(domain, type_def);
+                type_defs.entry(item.path()).or_insert_with(|| items.len());
+                items.push(item);
+            }
+            for command in &domain.commands {
+                let item = ItemRef::Command(domain, command);
+                commands.entry(item.path()).or_insert_with(|| items.len());
+                items.push(item);
+            }
+            for event in &domain.events {
+                let item = ItemRef::Event(domain, event);
+                events.entry(item.path()).or_insert_with(|| items.len());
+                items.push(item);
+            }
+        }
+
+        let mut references = Vec::new();
+        let mut referenced_by = HashMap::new();
+        for (item_index, &item) in items.iter().enumerate() {
+            let domain = item.domain();
+            let mut on_type = |_: &'a Domain, path: &str, ty: &'a Type| {
+                let target = match *ty {
+                    Type::Reference(ref target) => target,
+                    _ => return,
+                };
+                if let Some(resolved) = resolver.resolve(&domain.name, target) {
+                    let target_path =
+                        format!("{}.{}", resolved.domain.name, resolved.type_def.name);
+                    referenced_by
+                        .entry(type_defs[&target_path])
+                        .or_insert_with(Vec::new)
+                        .push((item_index, references.len()));
+                    references.push(FieldReference {
+                        path: path.into(),
+                        item,
+                        target: resolved,
+                    });
+                }
+            };
+
+            let path = item.path();
+            match item {
+                ItemRef::TypeDef(_, type_def) => {
+                    walk_type(domain, &path, &type_def.ty, &mut on_type)
+                }
+                ItemRef::Command(_, method) | ItemRef::Event(_, method) => {
+                    walk_fields(domain, &path, &method.parameters, &mut on_type);
+                    walk_fields(domain, &path, &method.returns, &mut on_type);
+                }
+            }
+        }
+
+        DefinitionIndex {
+            resolver,
+            items,
+            commands,
+            events,
+            type_defs,
+            references,
+            referenced_by,
+        }
+    }
+
+    pub fn resolver(&self) -> &Resolver<'a> {
+        &self.resolver
+    }
+
+    pub fn items(&self) -> &[ItemRef<'a>] {
+        &self.items
+    }
+
+    pub fn command(&self, name: &str) -> Option<(&'a Domain, &'a Method)> {
+        self.commands.get(name).and_then(|&i| match self.items[i] {
+            ItemRef::Command(domain, method) => Some((domain, method)),
+            _ => None,
+        })
+    }
+
+    pub fn event(&self, name: &str) -> Option<(&'a Domain, &'a Method)> {
+        self.events.get(name).and_then(|&i| match self.items[i] {
+            ItemRef::Event(domain, method) => Some((domain, method)),
+            _ => None,
+        })
+    }
+
+    pub fn type_def(&self, name: &str) -> Option<ResolvedType<'a>> {
+        self.type_defs.get(name).and_then(|&i| match self.items[i] {
+            ItemRef::TypeDef(domain, type_def) => Some(ResolvedType {
+                domain,
+                type_def,
+            }),
+            _ => None,
+        })
+    }
+
+    pub fn commands(&self, domain_name: &str) -> &'a [Method] {
+        self.resolver
+            .domain(domain_name)
+            .map(|domain| &domain.commands[..])
+            .unwrap_or(&[])
+    }
+
+    pub fn events(&self, domain_name: &str) -> &'a [Method] {
+        self.resolver
+            .domain(domain_name)
+            .map(|domain| &domain.events[..])
+            .unwrap_or(&[])
+    }
+
+    // Every place that refers to the type directly, e.g. each field whose
+    // `$ref` is "Network.RequestId".
+    pub fn references_to(&self, type_name: &str) -> Vec<&FieldReference<'a>> {
+        self.incoming(type_name)
+            .iter()
+            .map(|&(_, reference)| &self.references[reference])
+            .collect()
+    }
+
+    // The items that refer to the type directly, each listed once.
+    pub fn referrers(&self, type_name: &str) -> Vec<ItemRef<'a>> {
+        let mut seen = HashSet::new();
+        self.incoming(type_name)
+            .iter()
+            .filter(|&&(item, _)| seen.insert(item))
+            .map(|&(item, _)| self.items[item])
+            .collect()
+    }
+
+    // The items that refer to the type either directly or through other
+    // types, nearest first.
+    pub fn dependents(&self, type_name: &str) -> Vec<ItemRef<'a>> {
+        let start = match self.type_defs.get(type_name) {
+            Some(&start) => start,
+            None => return Vec::new(),
+        };
+
+        let mut dependents = Vec::new();
+        let mut seen = HashSet::new();
+        seen.insert(start);
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            let incoming = self.referenced_by.get(&index).map(|v| &v[..]).unwrap_or(&[]);
+            for &(item, _) in incoming {
+                if seen.insert(item) {
+                    dependents.push(self.items[item]);
+                    queue.push_back(item);
+                }
+            }
+        }
+        dependents
+    }
+
+    pub fn commands_using(&self, type_name: &str) -> Vec<(&'a Domain, &'a Method)> {
+        self.dependents(type_name)
+            .into_iter()
+            .filter_map(|item| match item {
+                ItemRef::Command(domain, method) => Some((domain, method)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn incoming(&self, type_name: &str) -> &[(usize, usize)] {
+        self.type_defs
+            .get(type_name)
+            .and_then(|index| self.referenced_by.get(index))
+            .map(|incoming| &incoming[..])
+            .unwrap_or(&[])
+    }
+}
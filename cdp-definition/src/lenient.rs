@@ -0,0 +1,656 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+// A forgiving alternative to deserializing a `Definition` with serde, which
+// rejects any key it doesn't know. Here unknown keys are kept in each node's
+// `extra` map instead (except under "version", where they're dropped), and
+// every domain, type, command, event and field gets a source span keyed by its
+// JSON pointer (e.g. "/domains/3/commands/0/parameters/1"). Spans can also be
+// looked up by dotted path (e.g. "Page.navigate.url"), the same paths that
+// validation and lint results use.
+
+use serde::Deserialize;
+use serde_json::{self, Map, Number, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Domain, Extra, Field, Item, Method, Type, TypeDef};
+
+// Lines and columns count from 1; columns count characters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceMap {
+    spans: BTreeMap<String, Span>,
+    pointers: BTreeMap<String, Vec<String>>,
+}
+
+impl SourceMap {
+    // A dotted path can be shared, by a command and an event or a parameter
+    // and a return value with the same name, in which case this returns the
+    // first in the document.
+    pub fn get(&self, path: &str) -> Option<Span> {
+        self.get_all(path).into_iter().next()
+    }
+
+    pub fn get_all(&self, path: &str) -> Vec<Span> {
+        match self.pointers.get(path) {
+            Some(pointers) => pointers.iter().map(|pointer| self.spans[pointer]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_pointer(&self, pointer: &str) -> Option<Span> {
+        self.spans.get(pointer).cloned()
+    }
+
+    // Iterates by JSON pointer.
+    pub fn iter(&self) -> ::std::collections::btree_map::Iter<'_, String, Span> {
+        self.spans.iter()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        "protocol definition parse error"
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+pub fn from_str(src: &str) -> Result<(Definition, SourceMap), ParseError> {
+    let mut reader = Reader {
+        src,
+        pos: 0,
+        line: 1,
+        line_start: 0,
+        depth: 0,
+    };
+    let root = reader.read_document()?;
+
+    let mut stripper = Stripper {
+        extras: HashMap::new(),
+        node_spans: HashMap::new(),
+        source_map: SourceMap::default(),
+    };
+    let value = stripper.strip(&root, Kind::Definition, "", "");
+
+    let mut def = match serde_json::from_value::<Definition>(value.clone()) {
+        Ok(def) => def,
+        Err(err) => {
+            let pointer = locate_error(&value, Kind::Definition, "");
+            let span = stripper.node_spans[&pointer];
+            return Err(ParseError {
+                line: span.line,
+                column: span.column,
+                message: err.to_string(),
+            });
+        }
+    };
+    stripper.attach_definition(&mut def);
+
+    Ok((def, stripper.source_map))
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Definition,
+    Version,
+    Domain,
+    TypeDef,
+    Method,
+    Field,
+    Item,
+}
+
+const TYPE_KEYS: &[&str] = &[
+    "$ref",
+    "type",
+    "enum",
+    "items",
+    "minItems",
+    "maxItems",
+    "properties",
+];
+
+impl Kind {
+    fn known_keys(&self) -> &'static [&'static str] {
+        match *self {
+            Kind::Definition => &["version", "domains"],
+            Kind::Version => &["major", "minor"],
+            Kind::Domain => &[
+                "domain",
+                "description",
+                "experimental",
+                "deprecated",
+                "dependencies",
+                "types",
+                "commands",
+                "events",
+            ],
+            Kind::TypeDef => &["id", "description", "experimental", "deprecated"],
+            Kind::Method => &[
+                "name",
+                "description",
+                "experimental",
+                "deprecated",
+                "handlers",
+                "parameters",
+                "returns",
+                "redirect",
+            ],
+            Kind::Field => &["name", "description", "experimental", "deprecated", "optional"],
+            Kind::Item => &["description"],
+        }
+    }
+
+    fn has_type(&self) -> bool {
+        matches!(*self, Kind::TypeDef | Kind::Field | Kind::Item)
+    }
+
+    fn is_known(&self, key: &str) -> bool {
+        self.known_keys().contains(&key) || (self.has_type() && TYPE_KEYS.contains(&key))
+    }
+
+    // Keys holding child nodes, and whether they hold a list of them.
+    fn children(&self) -> &'static [(&'static str, Kind, bool)] {
+        match *self {
+            Kind::Definition => &[
+                ("version", Kind::Version, false),
+                ("domains", Kind::Domain, true),
+            ],
+            Kind::Domain => &[
+                ("types", Kind::TypeDef, true),
+                ("commands", Kind::Method, true),
+                ("events", Kind::Method, true),
+            ],
+            Kind::Method => &[("parameters", Kind::Field, true), ("returns", Kind::Field, true)],
+            Kind::TypeDef | Kind::Field | Kind::Item => {
+                &[("items", Kind::Item, false), ("properties", Kind::Field, true)]
+            }
+            Kind::Version => &[],
+        }
+    }
+
+    fn name_key(&self) -> Option<&'static str> {
+        match *self {
+            Kind::Domain => Some("domain"),
+            Kind::TypeDef => Some("id"),
+            Kind::Method | Kind::Field => Some("name"),
+            _ => None,
+        }
+    }
+
+    fn check(&self, value: &Value) -> Result<(), serde_json::Error> {
+        let value = value.clone();
+        match *self {
+            Kind::Definition => Definition::deserialize(value).map(|_| ()),
+            Kind::Version => ::Version::deserialize(value).map(|_| ()),
+            Kind::Domain => Domain::deserialize(value).map(|_| ()),
+            Kind::TypeDef => TypeDef::deserialize(value).map(|_| ()),
+            Kind::Method => Method::deserialize(value).map(|_| ()),
+            Kind::Field => Field::deserialize(value).map(|_| ()),
+            Kind::Item => Item::deserialize(value).map(|_| ()),
+        }
+    }
+}
+
+struct Stripper {
+    extras: HashMap<String, Extra>,
+    node_spans: HashMap<String, Span>,
+    source_map: SourceMap,
+}
+
+impl Stripper {
+    // Returns the node with its unknown keys taken out, recording them (and
+    // the node's span) under its JSON pointer.
+    fn strip(&mut self, node: &Node, kind: Kind, pointer: &str, path: &str) -> Value {
+        self.node_spans.insert(pointer.into(), node.span);
+        let members = match node.value {
+            NodeValue::Object(ref members) => members,
+            _ => return node.to_value(),
+        };
+
+        let path = match kind.name_key().and_then(|key| find_str(members, key)) {
+            Some(name) if path.is_empty() => name.into(),
+            Some(name) => format!("{}.{}", path, name),
+            None => path.into(),
+        };
+        if kind.name_key().is_some() {
+            self.source_map.spans.insert(pointer.into(), node.span);
+            self.source_map
+                .pointers
+                .entry(path.clone())
+                .or_default()
+                .push(pointer.into());
+        }
+
+        let mut known = Map::new();
+        let mut extra = Extra::new();
+        for (key, member) in members {
+            if !kind.is_known(key) {
+                extra.insert(key.clone(), member.to_value());
+                continue;
+            }
+
+            let child = kind.children().iter().find(|&&(child_key, _, _)| child_key == key);
+            let value = match (child, &member.value) {
+                (Some(&(_, child_kind, true)), NodeValue::Array(elements)) => {
+                    let values = elements
+                        .iter()
+                        .enumerate()
+                        .map(|(i, element)| {
+                            let pointer = format!("{}/{}/{}", pointer, key, i);
+                            self.strip(element, child_kind, &pointer, &path)
+                        })
+                        .collect();
+                    Value::Array(values)
+                }
+                (Some(&(_, child_kind, false)), _) => {
+                    let pointer = format!("{}/{}", pointer, key);
+                    self.strip(member, child_kind, &pointer, &path)
+                }
+                _ => member.to_value(),
+            };
+            known.insert(key.clone(), value);
+        }
+
+        if !extra.is_empty() && kind != Kind::Version {
+            self.extras.insert(pointer.into(), extra);
+        }
+        Value::Object(known)
+    }
+
+    fn take_extra(&mut self, pointer: &str) -> Extra {
+        self.extras.remove(pointer).unwrap_or_default()
+    }
+
+    fn attach_definition(&mut self, def: &mut Definition) {
+        def.extra = self.take_extra("");
+        for (i, domain) in def.domains.iter_mut().enumerate() {
+            let pointer = format!("/domains/{}", i);
+            domain.extra = self.take_extra(&pointer);
+            for (j, type_def) in domain.type_defs.iter_mut().enumerate() {
+                let pointer = format!("{}/types/{}", pointer, j);
+                type_def.extra = self.take_extra(&pointer);
+                self.attach_type(&mut type_def.ty, &pointer);
+            }
+            for &mut (key, ref mut methods) in &mut [
+                ("commands", &mut domain.commands),
+                ("events", &mut domain.events),
+            ] {
+                for (j, method) in methods.iter_mut().enumerate() {
+                    let pointer = format!("{}/{}/{}", pointer, key, j);
+                    method.extra = self.take_extra(&pointer);
+                    self.attach_fields(&mut method.parameters, &format!("{}/parameters", pointer));
+                    self.attach_fields(&mut method.returns, &format!("{}/returns", pointer));
+                }
+            }
+        }
+    }
+
+    fn attach_fields(&mut self, fields: &mut [Field], pointer: &str) {
+        for (i, field) in fields.iter_mut().enumerate() {
+            let pointer = format!("{}/{}", pointer, i);
+            field.extra = self.take_extra(&pointer);
+            self.attach_type(&mut field.ty, &pointer);
+        }
+    }
+
+    fn attach_type(&mut self, ty: &mut Type, pointer: &str) {
+        match *ty {
+            Type::Array { ref mut item, .. } => {
+                let pointer = format!("{}/items", pointer);
+                item.extra = self.take_extra(&pointer);
+                self.attach_type(&mut item.ty, &pointer);
+            }
+            Type::Object(ref mut properties) => {
+                self.attach_fields(properties, &format!("{}/properties", pointer))
+            }
+            _ => (),
+        }
+    }
+}
+
+// Narrows a deserialization failure down to the innermost node that fails on
+// its own, so the error can point at it.
+fn locate_error(value: &Value, kind: Kind, pointer: &str) -> String {
+    if let Value::Object(ref map) = *value {
+        for &(key, child_kind, is_list) in kind.children() {
+            let children: Vec<(String, &Value)> = match (map.get(key), is_list) {
+                (Some(Value::Array(elements)), true) => elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| (format!("{}/{}/{}", pointer, key, i), element))
+                    .collect(),
+                (Some(child), false) => vec![(format!("{}/{}", pointer, key), child)],
+                _ => vec![],
+            };
+            for (child_pointer, child) in children {
+                if child_kind.check(child).is_err() {
+                    return locate_error(child, child_kind, &child_pointer);
+                }
+            }
+        }
+    }
+    pointer.into()
+}
+
+fn find_str<'a>(members: &'a [(String, Node)], key: &str) -> Option<&'a str> {
+    members
+        .iter()
+        .rev()
+        .find(|&(k, _)| k == key)
+        .and_then(|(_, node)| match node.value {
+            NodeValue::String(ref s) => Some(s.as_str()),
+            _ => None,
+        })
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    span: Span,
+    value: NodeValue,
+}
+
+#[derive(Clone, Debug)]
+enum NodeValue {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<(String, Node)>),
+}
+
+impl Node {
+    fn to_value(&self) -> Value {
+        match self.value {
+            NodeValue::Null => Value::Null,
+            NodeValue::Bool(b) => Value::Bool(b),
+            NodeValue::Number(ref n) => Value::Number(n.clone()),
+            NodeValue::String(ref s) => Value::String(s.clone()),
+            NodeValue::Array(ref elements) => {
+                Value::Array(elements.iter().map(Node::to_value).collect())
+            }
+            NodeValue::Object(ref members) => {
+                let mut map = Map::new();
+                for (key, member) in members {
+                    map.insert(key.clone(), member.to_value());
+                }
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+// The same nesting limit as serde_json's, which keeps deeply nested input from
+// overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+// A small JSON reader that remembers where each value came from.
+struct Reader<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+    line_start: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_document(&mut self) -> Result<Node, ParseError> {
+        let node = self.read_value()?;
+        self.skip_whitespace();
+        if self.pos < self.src.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(node)
+    }
+
+    fn read_value(&mut self) -> Result<Node, ParseError> {
+        self.skip_whitespace();
+        let (start, line, column) = (self.pos, self.line, self.column());
+        let value = match self.peek() {
+            Some(b'{') => self.read_nested(Reader::read_object)?,
+            Some(b'[') => self.read_nested(Reader::read_array)?,
+            Some(b'"') => NodeValue::String(self.read_string()?),
+            Some(b't') => self.read_literal("true", NodeValue::Bool(true))?,
+            Some(b'f') => self.read_literal("false", NodeValue::Bool(false))?,
+            Some(b'n') => self.read_literal("null", NodeValue::Null)?,
+            Some(b) if b == b'-' || (b as char).is_ascii_digit() => self.read_number()?,
+            Some(_) => return Err(self.error("expected value")),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        Ok(Node {
+            span: Span {
+                start,
+                end: self.pos,
+                line,
+                column,
+            },
+            value,
+        })
+    }
+
+    fn read_nested<F>(&mut self, read: F) -> Result<NodeValue, ParseError>
+    where
+        F: FnOnce(&mut Self) -> Result<NodeValue, ParseError>,
+    {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("recursion limit exceeded"));
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn read_object(&mut self) -> Result<NodeValue, ParseError> {
+        self.pos += 1;
+        let mut members: Vec<(String, Node)> = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(NodeValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected object key"));
+            }
+            let key = self.read_string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return Err(self.error("expected ':'"));
+            }
+            let member = self.read_value()?;
+            // Later duplicates win, as they do with serde_json.
+            members.retain(|(k, _)| *k != key);
+            members.push((key, member));
+
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Ok(NodeValue::Object(members));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected ',' or '}'"));
+            }
+        }
+    }
+
+    fn read_array(&mut self) -> Result<NodeValue, ParseError> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Ok(NodeValue::Array(elements));
+        }
+        loop {
+            elements.push(self.read_value()?);
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Ok(NodeValue::Array(elements));
+            }
+            if !self.eat(b',') {
+                return Err(self.error("expected ',' or ']'"));
+            }
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut s = String::new();
+        let mut run_start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    s.push_str(&self.src[run_start..self.pos]);
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    s.push_str(&self.src[run_start..self.pos]);
+                    self.pos += 1;
+                    s.push(self.read_escape()?);
+                    run_start = self.pos;
+                }
+                Some(b) if b < 0x20 => return Err(self.error("control character in string")),
+                Some(_) => self.pos += 1,
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn read_escape(&mut self) -> Result<char, ParseError> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high = self.read_hex4()?;
+                if !(0xd800..=0xdbff).contains(&high) {
+                    return ::std::char::from_u32(high)
+                        .ok_or_else(|| self.error("invalid unicode escape"));
+                }
+                if !self.src[self.pos..].starts_with("\\u") {
+                    return Err(self.error("unpaired surrogate in unicode escape"));
+                }
+                self.pos += 2;
+                let low = self.read_hex4()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(self.error("unpaired surrogate in unicode escape"));
+                }
+                let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                return ::std::char::from_u32(c)
+                    .ok_or_else(|| self.error("invalid unicode escape"));
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, ParseError> {
+        // `from_str_radix` alone would also take a sign, as in "\u+12a".
+        let digits = self.src
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.bytes().all(|b| (b as char).is_ascii_hexdigit()));
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(n) => {
+                self.pos += 4;
+                Ok(n)
+            }
+            None => Err(self.error("invalid unicode escape")),
+        }
+    }
+
+    fn read_number(&mut self) -> Result<NodeValue, ParseError> {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            match b {
+                b'-' | b'+' | b'.' | b'e' | b'E' => self.pos += 1,
+                _ if (b as char).is_ascii_digit() => self.pos += 1,
+                _ => break,
+            }
+        }
+        match serde_json::from_str::<Number>(&self.src[start..self.pos]) {
+            Ok(n) => Ok(NodeValue::Number(n)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("invalid number"))
+            }
+        }
+    }
+
+    fn read_literal(&mut self, literal: &str, value: NodeValue) -> Result<NodeValue, ParseError> {
+        if self.src[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected value"))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            match b {
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                b'\n' => {
+                    self.pos += 1;
+                    self.line += 1;
+                    self.line_start = self.pos;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).cloned()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn column(&self) -> usize {
+        self.src[self.line_start..self.pos].chars().count() + 1
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column(),
+            message: message.into(),
+        }
+    }
+}
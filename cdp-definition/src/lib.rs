@@ -11,15 +11,67 @@
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde::de;
 use std::fmt::{self, Display, Formatter};
 
+mod diff;
+mod extra;
+mod index;
+mod lint;
+mod merge;
+mod message;
+mod resolve;
+mod validate;
+mod visit;
+
+pub mod lenient;
+pub mod pdl;
+pub mod reference;
+pub mod schema;
+
+pub use diff::{diff, Change, ChangeKind, Diff};
+pub use extra::Extra;
+pub use index::{DefinitionIndex, FieldReference, ItemRef};
+pub use lint::{LintViolation, Linter, Rule, ALL_RULES};
+pub use merge::{MergeError, Overlay, Patch};
+pub use message::{MessageValidator, Mismatch, MismatchKind};
+pub use resolve::{Reference, ResolvedType, Resolver};
+pub use validate::{Diagnostic, DiagnosticKind, Severity};
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Definition {
     pub version: Version,
     pub domains: Vec<Domain>,
+    pub extra: Extra,
+}
+
+impl Definition {
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        validate::validate(self)
+    }
+
+    pub fn resolver(&self) -> Resolver<'_> {
+        Resolver::new(self)
+    }
+
+    pub fn index(&self) -> DefinitionIndex<'_> {
+        DefinitionIndex::new(self)
+    }
+
+    pub fn lint(&self) -> Vec<LintViolation> {
+        Linter::chromium().lint(self)
+    }
+
+    pub fn merge(&mut self, other: Definition) -> Result<(), MergeError> {
+        merge::merge(self, other)
+    }
+
+    pub fn apply_overlay(&mut self, overlay: &Overlay) -> Result<(), MergeError> {
+        merge::apply_overlay(self, overlay)
+    }
 }
 
 impl Serialize for Definition {
@@ -27,8 +79,7 @@ impl Serialize for Definition {
     where
         S: Serializer,
     {
-        let def = DefinitionImpl::from(self);
-        def.serialize(serializer)
+        extra::serialize(self, &DefinitionImpl::from(self), serializer)
     }
 }
 
@@ -65,6 +116,7 @@ pub struct Domain {
     pub type_defs: Vec<TypeDef>,
     pub commands: Vec<Method>,
     pub events: Vec<Method>,
+    pub extra: Extra,
 }
 
 impl Serialize for Domain {
@@ -72,8 +124,7 @@ impl Serialize for Domain {
     where
         S: Serializer,
     {
-        let domain = DomainImpl::from(self);
-        domain.serialize(serializer)
+        extra::serialize(self, &DomainImpl::from(self), serializer)
     }
 }
 
@@ -111,6 +162,7 @@ pub struct TypeDef {
     pub experimental: bool,
     pub deprecated: bool,
     pub ty: Type,
+    pub extra: Extra,
 }
 
 impl Serialize for TypeDef {
@@ -118,8 +170,7 @@ impl Serialize for TypeDef {
     where
         S: Serializer,
     {
-        let type_def = TypeDefImpl::from(self);
-        type_def.serialize(serializer)
+        extra::serialize(self, &TypeDefImpl::from(self), serializer)
     }
 }
 
@@ -143,6 +194,7 @@ pub struct Method {
     pub parameters: Vec<Field>,
     pub returns: Vec<Field>,
     pub redirect: Option<String>,
+    pub extra: Extra,
 }
 
 impl Serialize for Method {
@@ -150,8 +202,7 @@ impl Serialize for Method {
     where
         S: Serializer,
     {
-        let method = MethodImpl::from(self);
-        method.serialize(serializer)
+        extra::serialize(self, &MethodImpl::from(self), serializer)
     }
 }
 
@@ -173,6 +224,7 @@ pub struct Field {
     pub deprecated: bool,
     pub optional: bool,
     pub ty: Type,
+    pub extra: Extra,
 }
 
 impl Serialize for Field {
@@ -180,8 +232,7 @@ impl Serialize for Field {
     where
         S: Serializer,
     {
-        let field = FieldImpl::from(self);
-        field.serialize(serializer)
+        extra::serialize(self, &FieldImpl::from(self), serializer)
     }
 }
 
@@ -199,6 +250,7 @@ impl<'de> Deserialize<'de> for Field {
 pub struct Item {
     pub description: Option<String>,
     pub ty: Type,
+    pub extra: Extra,
 }
 
 impl Serialize for Item {
@@ -206,8 +258,7 @@ impl Serialize for Item {
     where
         S: Serializer,
     {
-        let item = ItemImpl::from(self);
-        item.serialize(serializer)
+        extra::serialize(self, &ItemImpl::from(self), serializer)
     }
 }
 
@@ -239,6 +290,7 @@ impl DefinitionImpl {
                 .into_iter()
                 .map(DomainImpl::into_domain)
                 .collect::<Result<_, _>>()?,
+            extra: Extra::new(),
         })
     }
 }
@@ -296,6 +348,7 @@ impl DomainImpl {
                 .into_iter()
                 .map(MethodImpl::into_method)
                 .collect::<Result<_, _>>()?,
+            extra: Extra::new(),
         })
     }
 }
@@ -363,6 +416,7 @@ impl TypeDefImpl {
             experimental: self.experimental,
             deprecated: self.deprecated,
             ty: ty,
+            extra: Extra::new(),
         })
     }
 }
@@ -426,6 +480,7 @@ impl MethodImpl {
                 .map(FieldImpl::into_field)
                 .collect::<Result<_, _>>()?,
             redirect: self.redirect,
+            extra: Extra::new(),
         })
     }
 }
@@ -495,6 +550,7 @@ impl FieldImpl {
             deprecated: self.deprecated,
             optional: self.optional,
             ty: ty,
+            extra: Extra::new(),
         })
     }
 }
@@ -558,6 +614,7 @@ impl ItemImpl {
         Ok(Item {
             description: self.description,
             ty: ty.into_type("array item")?,
+            extra: Extra::new(),
         })
     }
 }
@@ -0,0 +1,261 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Field, Type};
+use validate::Severity;
+use visit::walk_types;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Rule {
+    DomainName,
+    TypeName,
+    MethodName,
+    FieldName,
+    EnumValue,
+    EventNamedLikeCommand,
+    MissingDescription,
+    OptionalAny,
+}
+
+pub const ALL_RULES: &[Rule] = &[
+    Rule::DomainName,
+    Rule::TypeName,
+    Rule::MethodName,
+    Rule::FieldName,
+    Rule::EnumValue,
+    Rule::EventNamedLikeCommand,
+    Rule::MissingDescription,
+    Rule::OptionalAny,
+];
+
+// Leading words that make an event read like an instruction rather than a
+// notification. Chromium names events after what happened ("frameNavigated")
+// and commands after what to do ("navigate").
+const COMMAND_VERBS: &[&str] = &[
+    "add", "clear", "close", "create", "delete", "disable", "enable", "get", "remove", "set",
+    "start", "stop",
+];
+
+impl Rule {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Rule::DomainName => "domain-name",
+            Rule::TypeName => "type-name",
+            Rule::MethodName => "method-name",
+            Rule::FieldName => "field-name",
+            Rule::EnumValue => "enum-value",
+            Rule::EventNamedLikeCommand => "event-named-like-command",
+            Rule::MissingDescription => "missing-description",
+            Rule::OptionalAny => "optional-any",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        ALL_RULES.iter().cloned().find(|rule| rule.name() == name)
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LintViolation {
+    pub path: String,
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for LintViolation {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}: {} [{}]", self.path, self.message, self.rule)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Linter {
+    rules: HashMap<Rule, Severity>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter::chromium()
+    }
+}
+
+impl Linter {
+    // Same as `Linter::chromium`.
+    pub fn new() -> Self {
+        Linter::chromium()
+    }
+
+    // A linter with no rules enabled.
+    pub fn empty() -> Self {
+        Linter {
+            rules: HashMap::new(),
+        }
+    }
+
+    // Every rule, with naming problems as errors and the rest (which Chromium's
+    // own definitions don't always follow) as warnings.
+    pub fn chromium() -> Self {
+        Linter::empty()
+            .with_rule(Rule::DomainName, Severity::Error)
+            .with_rule(Rule::TypeName, Severity::Error)
+            .with_rule(Rule::MethodName, Severity::Error)
+            .with_rule(Rule::FieldName, Severity::Error)
+            .with_rule(Rule::EnumValue, Severity::Error)
+            .with_rule(Rule::EventNamedLikeCommand, Severity::Warning)
+            .with_rule(Rule::MissingDescription, Severity::Warning)
+            .with_rule(Rule::OptionalAny, Severity::Warning)
+    }
+
+    pub fn with_rule(mut self, rule: Rule, severity: Severity) -> Self {
+        self.set_rule(rule, Some(severity));
+        self
+    }
+
+    pub fn without_rule(mut self, rule: Rule) -> Self {
+        self.set_rule(rule, None);
+        self
+    }
+
+    pub fn rule(&self, rule: Rule) -> Option<Severity> {
+        self.rules.get(&rule).cloned()
+    }
+
+    pub fn set_rule(&mut self, rule: Rule, severity: Option<Severity>) {
+        match severity {
+            Some(severity) => self.rules.insert(rule, severity),
+            None => self.rules.remove(&rule),
+        };
+    }
+
+    pub fn lint(&self, def: &Definition) -> Vec<LintViolation> {
+        let mut lint = Lint {
+            linter: self,
+            violations: Vec::new(),
+        };
+
+        for domain in &def.domains {
+            if !is_pascal_case(&domain.name) {
+                lint.report(&domain.name, Rule::DomainName, "domain name isn't PascalCase");
+            }
+            lint.check_description(&domain.name, &domain.description, "domain");
+
+            for type_def in &domain.type_defs {
+                let path = format!("{}.{}", domain.name, type_def.name);
+                if !is_pascal_case(&type_def.name) {
+                    lint.report(&path, Rule::TypeName, "type name isn't PascalCase");
+                }
+                lint.check_description(&path, &type_def.description, "type");
+            }
+
+            for (methods, is_command) in &[(&domain.commands, true), (&domain.events, false)] {
+                let what = if *is_command { "command" } else { "event" };
+                for method in methods.iter() {
+                    let path = format!("{}.{}", domain.name, method.name);
+                    if !is_camel_case(&method.name) {
+                        let message = format!("{} name isn't camelCase", what);
+                        lint.report(&path, Rule::MethodName, &message);
+                    }
+                    lint.check_description(&path, &method.description, what);
+                    lint.check_fields(&path, &method.parameters);
+                    lint.check_fields(&path, &method.returns);
+                }
+            }
+
+            for event in &domain.events {
+                let path = format!("{}.{}", domain.name, event.name);
+                if domain.commands.iter().any(|c| c.name.eq_ignore_ascii_case(&event.name)) {
+                    let message = "event has the same name as a command";
+                    lint.report(&path, Rule::EventNamedLikeCommand, message);
+                } else if let Some(verb) = leading_command_verb(&event.name) {
+                    let message = format!("event name starts with the command verb '{}'", verb);
+                    lint.report(&path, Rule::EventNamedLikeCommand, &message);
+                }
+            }
+        }
+
+        walk_types(def, |_, path, ty| match *ty {
+            Type::Object(ref properties) => lint.check_fields(path, properties),
+            Type::Enum(ref values) => for value in values {
+                if value.is_empty() || value.chars().any(char::is_whitespace) {
+                    let message = format!("enum value '{}' is blank or has whitespace", value);
+                    lint.report(path, Rule::EnumValue, &message);
+                }
+            },
+            _ => (),
+        });
+
+        lint.violations
+    }
+}
+
+struct Lint<'a> {
+    linter: &'a Linter,
+    violations: Vec<LintViolation>,
+}
+
+impl<'a> Lint<'a> {
+    fn report(&mut self, path: &str, rule: Rule, message: &str) {
+        if let Some(severity) = self.linter.rule(rule) {
+            self.violations.push(LintViolation {
+                path: path.into(),
+                rule,
+                severity,
+                message: message.into(),
+            });
+        }
+    }
+
+    fn check_description(&mut self, path: &str, description: &Option<String>, what: &str) {
+        let missing = match *description {
+            Some(ref description) => description.trim().is_empty(),
+            None => true,
+        };
+        if missing {
+            let message = format!("{} has no description", what);
+            self.report(path, Rule::MissingDescription, &message);
+        }
+    }
+
+    fn check_fields(&mut self, path: &str, fields: &[Field]) {
+        for field in fields {
+            let path = format!("{}.{}", path, field.name);
+            if !is_camel_case(&field.name) {
+                self.report(&path, Rule::FieldName, "field name isn't camelCase");
+            }
+            if field.optional && field.ty == Type::Any {
+                let message = "optional field of type 'any' is ambiguous when null";
+                self.report(&path, Rule::OptionalAny, message);
+            }
+        }
+    }
+}
+
+fn is_pascal_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn is_camel_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn leading_command_verb(name: &str) -> Option<&'static str> {
+    COMMAND_VERBS.iter().cloned().find(|verb| {
+        name.starts_with(verb) && {
+            let rest = &name[verb.len()..];
+            rest.is_empty() || rest.starts_with(|c: char| c.is_ascii_uppercase())
+        }
+    })
+}
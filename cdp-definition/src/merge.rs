@@ -0,0 +1,212 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Domain, Field, Method, Type, TypeDef, Version};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MergeError {
+    VersionMismatch { ours: Version, theirs: Version },
+    DuplicateDomain(String),
+    DuplicateItem(String),
+    UnknownTarget(String),
+}
+
+impl Error for MergeError {
+    fn description(&self) -> &str {
+        "protocol definition merge error"
+    }
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            MergeError::VersionMismatch {
+                ref ours,
+                ref theirs,
+            } => write!(f, "protocol versions don't match ({} vs. {})", ours, theirs),
+            MergeError::DuplicateDomain(ref name) => {
+                write!(f, "domain '{}' is defined twice", name)
+            }
+            MergeError::DuplicateItem(ref path) => write!(f, "'{}' is already defined", path),
+            MergeError::UnknownTarget(ref path) => write!(f, "'{}' doesn't exist", path),
+        }
+    }
+}
+
+// A set of changes to layer on top of an upstream definition. Field targets
+// are written as "Domain.item.section", where the section is "parameters" or
+// "returns" for commands and events, or "properties" for object types.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Overlay {
+    pub patches: Vec<Patch>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "op", deny_unknown_fields)]
+pub enum Patch {
+    #[serde(rename = "addDomain")]
+    AddDomain { domain: Domain },
+    #[serde(rename = "addTypes")]
+    AddTypes { domain: String, types: Vec<TypeDef> },
+    #[serde(rename = "addCommands")]
+    AddCommands { domain: String, commands: Vec<Method> },
+    #[serde(rename = "addEvents")]
+    AddEvents { domain: String, events: Vec<Method> },
+    #[serde(rename = "addFields")]
+    AddFields { target: String, fields: Vec<Field> },
+    #[serde(rename = "setOptional")]
+    SetOptional {
+        target: String,
+        field: String,
+        optional: bool,
+    },
+}
+
+pub fn merge(def: &mut Definition, other: Definition) -> Result<(), MergeError> {
+    if def.version != other.version {
+        return Err(MergeError::VersionMismatch {
+            ours: def.version.clone(),
+            theirs: other.version,
+        });
+    }
+    for domain in &other.domains {
+        if def.domains.iter().any(|d| d.name == domain.name) {
+            return Err(MergeError::DuplicateDomain(domain.name.clone()));
+        }
+    }
+
+    def.domains.extend(other.domains);
+    Ok(())
+}
+
+pub fn apply_overlay(def: &mut Definition, overlay: &Overlay) -> Result<(), MergeError> {
+    // Work on a copy so that a failing patch doesn't leave the definition
+    // half-patched.
+    let mut patched = def.clone();
+    for patch in &overlay.patches {
+        apply_patch(&mut patched, patch)?;
+    }
+    *def = patched;
+    Ok(())
+}
+
+fn apply_patch(def: &mut Definition, patch: &Patch) -> Result<(), MergeError> {
+    match *patch {
+        Patch::AddDomain { ref domain } => {
+            if def.domains.iter().any(|d| d.name == domain.name) {
+                return Err(MergeError::DuplicateDomain(domain.name.clone()));
+            }
+            def.domains.push(domain.clone());
+            Ok(())
+        }
+        Patch::AddTypes {
+            domain: ref domain_name,
+            ref types,
+        } => {
+            let domain = find_domain(def, domain_name)?;
+            add_named(domain_name, &mut domain.type_defs, types, |t| &t.name)
+        }
+        Patch::AddCommands {
+            domain: ref domain_name,
+            ref commands,
+        } => {
+            let domain = find_domain(def, domain_name)?;
+            add_named(domain_name, &mut domain.commands, commands, |m| &m.name)
+        }
+        Patch::AddEvents {
+            domain: ref domain_name,
+            ref events,
+        } => {
+            let domain = find_domain(def, domain_name)?;
+            add_named(domain_name, &mut domain.events, events, |m| &m.name)
+        }
+        Patch::AddFields {
+            ref target,
+            ref fields,
+        } => add_named(target, find_fields(def, target)?, fields, |f| &f.name),
+        Patch::SetOptional {
+            ref target,
+            ref field,
+            optional,
+        } => {
+            let path = format!("{}.{}", target, field);
+            match find_fields(def, target)?.iter_mut().find(|f| &f.name == field) {
+                Some(field) => {
+                    field.optional = optional;
+                    Ok(())
+                }
+                None => Err(MergeError::UnknownTarget(path)),
+            }
+        }
+    }
+}
+
+fn add_named<T, N>(
+    parent_path: &str,
+    items: &mut Vec<T>,
+    new_items: &[T],
+    name: N,
+) -> Result<(), MergeError>
+where
+    T: Clone,
+    N: Fn(&T) -> &String,
+{
+    for new_item in new_items {
+        if items.iter().any(|item| name(item) == name(new_item)) {
+            let path = format!("{}.{}", parent_path, name(new_item));
+            return Err(MergeError::DuplicateItem(path));
+        }
+        items.push(new_item.clone());
+    }
+    Ok(())
+}
+
+fn find_domain<'a>(def: &'a mut Definition, name: &str) -> Result<&'a mut Domain, MergeError> {
+    def.domains
+        .iter_mut()
+        .find(|d| d.name == name)
+        .ok_or_else(|| MergeError::UnknownTarget(name.into()))
+}
+
+fn find_fields<'a>(
+    def: &'a mut Definition,
+    target: &str,
+) -> Result<&'a mut Vec<Field>, MergeError> {
+    let unknown_target = || MergeError::UnknownTarget(target.into());
+
+    let parts: Vec<&str> = target.split('.').collect();
+    if parts.len() != 3 {
+        return Err(unknown_target());
+    }
+    let domain = find_domain(def, parts[0]).map_err(|_| unknown_target())?;
+    let (item_name, section) = (parts[1], parts[2]);
+
+    let fields = match section {
+        "properties" => domain
+            .type_defs
+            .iter_mut()
+            .find(|t| t.name == item_name)
+            .and_then(|type_def| match type_def.ty {
+                Type::Object(ref mut properties) => Some(properties),
+                _ => None,
+            }),
+        "parameters" => domain
+            .commands
+            .iter_mut()
+            .chain(domain.events.iter_mut())
+            .find(|m| m.name == item_name)
+            .map(|method| &mut method.parameters),
+        "returns" => domain
+            .commands
+            .iter_mut()
+            .find(|m| m.name == item_name)
+            .map(|method| &mut method.returns),
+        _ => None,
+    };
+    fields.ok_or_else(unknown_target)
+}
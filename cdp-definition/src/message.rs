@@ -0,0 +1,301 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde_json::{Map, Value};
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Domain, Field, Type};
+use index::DefinitionIndex;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MismatchKind {
+    UnknownMethod(String),
+    UnknownField,
+    MissingField,
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    BadEnumValue(String),
+    TooFewItems { min: u64, len: u64 },
+    TooManyItems { max: u64, len: u64 },
+    UnresolvedReference(String),
+    ReferenceCycle(Vec<String>),
+}
+
+impl Display for MismatchKind {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            MismatchKind::UnknownMethod(ref method) => write!(f, "unknown method '{}'", method),
+            MismatchKind::UnknownField => write!(f, "unknown field"),
+            MismatchKind::MissingField => write!(f, "missing required field"),
+            MismatchKind::WrongType { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            MismatchKind::BadEnumValue(ref value) => write!(f, "invalid enum value '{}'", value),
+            MismatchKind::TooFewItems { min, len } => {
+                write!(f, "expected at least {} items, found {}", min, len)
+            }
+            MismatchKind::TooManyItems { max, len } => {
+                write!(f, "expected at most {} items, found {}", max, len)
+            }
+            MismatchKind::UnresolvedReference(ref target) => {
+                write!(f, "definition refers to unknown type '{}'", target)
+            }
+            MismatchKind::ReferenceCycle(ref cycle) => {
+                write!(f, "definition has a reference cycle: {}", cycle.join(" -> "))
+            }
+        }
+    }
+}
+
+// `pointer` is a JSON pointer (RFC 6901) into the params or result object;
+// the empty string refers to the object itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mismatch {
+    pub pointer: String,
+    pub kind: MismatchKind,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        if self.pointer.is_empty() {
+            write!(f, "(root): {}", self.kind)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.kind)
+        }
+    }
+}
+
+// Checks messages against a definition at runtime, without going through
+// generated types. Free-form object types (those declared without properties)
+// and `any` values aren't checked; params and results always are.
+#[derive(Clone, Debug)]
+pub struct MessageValidator<'a> {
+    index: DefinitionIndex<'a>,
+}
+
+impl<'a> MessageValidator<'a> {
+    pub fn new(def: &'a Definition) -> Self {
+        MessageValidator {
+            index: DefinitionIndex::new(def),
+        }
+    }
+
+    // `method` is a qualified command or event name such as "Page.navigate".
+    // Missing (`null`) params are treated as an empty object.
+    pub fn validate_params(&self, method: &str, params: &Value) -> Vec<Mismatch> {
+        match self.index.command(method).or_else(|| self.index.event(method)) {
+            Some((domain, method)) => {
+                let empty = Value::Object(Map::new());
+                let params = if params.is_null() { &empty } else { params };
+                self.check_root(domain, &method.parameters, params)
+            }
+            None => vec![unknown_method(method)],
+        }
+    }
+
+    pub fn validate_result(&self, method: &str, result: &Value) -> Vec<Mismatch> {
+        match self.index.command(method) {
+            Some((domain, method)) => self.check_root(domain, &method.returns, result),
+            None => vec![unknown_method(method)],
+        }
+    }
+
+    fn check_root(&self, domain: &Domain, fields: &[Field], value: &Value) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let pointer = &mut String::new();
+        self.check_object(domain, fields, false, value, pointer, &mut mismatches);
+        mismatches
+    }
+
+    fn check_object(
+        &self,
+        domain: &Domain,
+        fields: &[Field],
+        free_form: bool,
+        value: &Value,
+        pointer: &mut String,
+        mismatches: &mut Vec<Mismatch>,
+    ) {
+        let object = match *value {
+            Value::Object(ref object) => object,
+            _ => return push_wrong_type("object", value, pointer, mismatches),
+        };
+        if free_form {
+            return;
+        }
+
+        for field in fields {
+            let len = pointer.len();
+            push_pointer_token(pointer, &field.name);
+            match object.get(&field.name) {
+                Some(field_value) => {
+                    self.check_type(domain, &field.ty, field_value, pointer, mismatches)
+                }
+                None if !field.optional => push(mismatches, pointer, MismatchKind::MissingField),
+                None => (),
+            }
+            pointer.truncate(len);
+        }
+
+        for name in object.keys() {
+            if !fields.iter().any(|field| &field.name == name) {
+                let len = pointer.len();
+                push_pointer_token(pointer, name);
+                push(mismatches, pointer, MismatchKind::UnknownField);
+                pointer.truncate(len);
+            }
+        }
+    }
+
+    fn check_type(
+        &self,
+        domain: &Domain,
+        ty: &Type,
+        value: &Value,
+        pointer: &mut String,
+        mismatches: &mut Vec<Mismatch>,
+    ) {
+        match *ty {
+            Type::Reference(ref target) => {
+                self.check_reference(domain, target, value, pointer, mismatches)
+            }
+            Type::Boolean => if !value.is_boolean() {
+                push_wrong_type("boolean", value, pointer, mismatches)
+            },
+            Type::Integer => if !value.is_i64() && !value.is_u64() {
+                push_wrong_type("integer", value, pointer, mismatches)
+            },
+            Type::Number => if !value.is_number() {
+                push_wrong_type("number", value, pointer, mismatches)
+            },
+            Type::String => if !value.is_string() {
+                push_wrong_type("string", value, pointer, mismatches)
+            },
+            Type::Enum(ref values) => match *value {
+                Value::String(ref s) => if !values.contains(s) {
+                    push(mismatches, pointer, MismatchKind::BadEnumValue(s.clone()))
+                },
+                _ => push_wrong_type("string", value, pointer, mismatches),
+            },
+            Type::Array {
+                ref item,
+                min_items,
+                max_items,
+            } => {
+                let items = match *value {
+                    Value::Array(ref items) => items,
+                    _ => return push_wrong_type("array", value, pointer, mismatches),
+                };
+                let len = items.len() as u64;
+                let bounds_kind = match (min_items, max_items) {
+                    (Some(min), _) if len < min => Some(MismatchKind::TooFewItems {
+                        min,
+                        len,
+                    }),
+                    (_, Some(max)) if len > max => Some(MismatchKind::TooManyItems {
+                        max,
+                        len,
+                    }),
+                    _ => None,
+                };
+                if let Some(kind) = bounds_kind {
+                    push(mismatches, pointer, kind);
+                }
+                for (i, item_value) in items.iter().enumerate() {
+                    let len = pointer.len();
+                    push_pointer_token(pointer, &i.to_string());
+                    self.check_type(domain, &item.ty, item_value, pointer, mismatches);
+                    pointer.truncate(len);
+                }
+            }
+            Type::Object(ref properties) => {
+                let free_form = properties.is_empty();
+                self.check_object(domain, properties, free_form, value, pointer, mismatches)
+            }
+            Type::Any => (),
+        }
+    }
+
+    // Follows a chain of aliases to the type that describes the value. The
+    // chain doesn't consume any of the value, so a cycle in it would recurse
+    // forever; it's reported instead.
+    fn check_reference(
+        &self,
+        domain: &Domain,
+        target: &str,
+        value: &Value,
+        pointer: &mut String,
+        mismatches: &mut Vec<Mismatch>,
+    ) {
+        let resolver = self.index.resolver();
+        let mut resolved = resolver.resolve(&domain.name, target);
+        let mut chain = Vec::new();
+        loop {
+            let (domain, type_def) = match resolved {
+                Some(resolved) => (resolved.domain, resolved.type_def),
+                None => {
+                    let target = chain.last().map_or(target, |&(_, target)| target);
+                    let kind = MismatchKind::UnresolvedReference(target.into());
+                    return push(mismatches, pointer, kind);
+                }
+            };
+            let name = format!("{}.{}", domain.name, type_def.name);
+            if let Some(start) = chain.iter().position(|link| link.0 == name) {
+                let mut cycle: Vec<String> =
+                    chain.drain(start..).map(|(seen, _)| seen).collect();
+                cycle.push(name);
+                return push(mismatches, pointer, MismatchKind::ReferenceCycle(cycle));
+            }
+            match type_def.ty {
+                Type::Reference(ref next) => {
+                    resolved = resolver.resolve(&domain.name, next);
+                    chain.push((name, next.as_str()));
+                }
+                ref ty => return self.check_type(domain, ty, value, pointer, mismatches),
+            }
+        }
+    }
+}
+
+fn unknown_method(method: &str) -> Mismatch {
+    Mismatch {
+        pointer: String::new(),
+        kind: MismatchKind::UnknownMethod(method.into()),
+    }
+}
+
+fn push_pointer_token(pointer: &mut String, token: &str) {
+    pointer.push('/');
+    pointer.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+fn push_wrong_type(
+    expected: &'static str,
+    value: &Value,
+    pointer: &str,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let found = match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    let kind = MismatchKind::WrongType {
+        expected,
+        found,
+    };
+    push(mismatches, pointer, kind);
+}
+
+fn push(mismatches: &mut Vec<Mismatch>, pointer: &str, kind: MismatchKind) {
+    mismatches.push(Mismatch {
+        pointer: pointer.into(),
+        kind,
+    });
+}
@@ -0,0 +1,718 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+// Reader and writer for the line-based protocol definition language that
+// Chromium maintains the protocol in. The grammar follows
+// https://chromium.googlesource.com/deps/inspector_protocol/+/master/pdl.py
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Domain, Extra, Field, Item, Method, Type, TypeDef, Version};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        "pdl parse error"
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrintError {
+    pub path: String,
+    pub message: String,
+}
+
+impl Error for PrintError {
+    fn description(&self) -> &str {
+        "pdl print error"
+    }
+}
+
+impl Display for PrintError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+pub fn from_str(src: &str) -> Result<Definition, ParseError> {
+    let mut parser = Parser {
+        major: None,
+        minor: None,
+        in_version: false,
+        domains: Vec::new(),
+        item: None,
+        section: None,
+        enum_target: None,
+        description: None,
+    };
+
+    let mut line_count = 0;
+    for (i, line) in src.lines().enumerate() {
+        line_count = i + 1;
+        parser.parse_line(line).map_err(|message| {
+            ParseError {
+                line: i + 1,
+                message,
+            }
+        })?;
+    }
+
+    match (parser.major, parser.minor) {
+        (Some(major), Some(minor)) => Ok(Definition {
+            version: Version {
+                major,
+                minor,
+            },
+            domains: parser.domains,
+            extra: Extra::new(),
+        }),
+        _ => Err(ParseError {
+            line: line_count,
+            message: "missing protocol version".into(),
+        }),
+    }
+}
+
+pub fn to_string(def: &Definition) -> Result<String, PrintError> {
+    check_extra("", &def.extra)?;
+
+    let mut out = String::new();
+    out.push_str("version\n");
+    out.push_str(&format!("  major {}\n", def.version.major));
+    out.push_str(&format!("  minor {}\n", def.version.minor));
+    for domain in &def.domains {
+        print_domain(&mut out, domain)?;
+    }
+    Ok(out)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ItemKind {
+    TypeDef,
+    Command,
+    Event,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Section {
+    Parameters,
+    Returns,
+    Properties,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EnumTarget {
+    TypeDef,
+    Field,
+}
+
+struct Parser {
+    major: Option<String>,
+    minor: Option<String>,
+    in_version: bool,
+    domains: Vec<Domain>,
+    item: Option<ItemKind>,
+    section: Option<Section>,
+    enum_target: Option<EnumTarget>,
+    description: Option<String>,
+}
+
+impl Parser {
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+
+        if let Some(text) = trimmed.strip_prefix('#') {
+            let text = text.strip_prefix(' ').unwrap_or(text);
+            match self.description {
+                None => self.description = Some(text.into()),
+                Some(ref mut description) => {
+                    description.push('\n');
+                    description.push_str(text);
+                }
+            }
+            return Ok(());
+        }
+
+        // A description only applies to the line directly below it.
+        let description = self.description.take();
+
+        let tokens: Vec<&str> = trimmed.split_whitespace().collect();
+        if tokens.is_empty() {
+            return Ok(());
+        }
+        if trimmed.starts_with(char::is_whitespace) {
+            return Err("indentation must use spaces".into());
+        }
+
+        match indent {
+            0 => self.parse_top_level(&tokens, description),
+            2 => self.parse_domain_member(&tokens, description),
+            4 => self.parse_item_member(&tokens),
+            6 => match self.enum_target {
+                Some(EnumTarget::TypeDef) if tokens.len() == 1 => self.push_enum_value(tokens[0]),
+                _ => self.parse_field(&tokens, description),
+            },
+            8 if tokens.len() == 1 && self.enum_target == Some(EnumTarget::Field) => {
+                self.push_enum_value(tokens[0])
+            }
+            _ => Err(format!("unexpected indentation of {} spaces", indent)),
+        }
+    }
+
+    fn parse_top_level(
+        &mut self,
+        tokens: &[&str],
+        description: Option<String>,
+    ) -> Result<(), String> {
+        self.in_version = false;
+        self.item = None;
+        self.section = None;
+        self.enum_target = None;
+
+        if tokens.len() == 1 && tokens[0] == "version" {
+            self.in_version = true;
+            return Ok(());
+        }
+
+        let (experimental, deprecated, tokens) = split_flags(tokens, 2);
+        if tokens.len() != 2 || tokens[0] != "domain" {
+            return Err(format!("expected 'version' or 'domain', found '{}'", tokens.join(" ")));
+        }
+
+        self.domains.push(Domain {
+            name: tokens[1].into(),
+            description,
+            experimental,
+            deprecated,
+            dependencies: vec![],
+            type_defs: vec![],
+            commands: vec![],
+            events: vec![],
+            extra: Extra::new(),
+        });
+        Ok(())
+    }
+
+    fn parse_domain_member(
+        &mut self,
+        tokens: &[&str],
+        description: Option<String>,
+    ) -> Result<(), String> {
+        if self.in_version {
+            if tokens.len() == 2 && tokens[0] == "major" {
+                self.major = Some(tokens[1].into());
+                return Ok(());
+            }
+            if tokens.len() == 2 && tokens[0] == "minor" {
+                self.minor = Some(tokens[1].into());
+                return Ok(());
+            }
+            return Err(format!("expected 'major' or 'minor', found '{}'", tokens.join(" ")));
+        }
+
+        self.item = None;
+        self.section = None;
+        self.enum_target = None;
+
+        if tokens.len() == 3 && tokens[0] == "depends" && tokens[1] == "on" {
+            self.domain_mut()?.dependencies.push(tokens[2].into());
+            return Ok(());
+        }
+
+        let (experimental, deprecated, tokens) = split_flags(tokens, 2);
+        if tokens.len() >= 4 && tokens[0] == "type" && tokens[2] == "extends" {
+            let type_def = TypeDef {
+                name: tokens[1].into(),
+                description,
+                experimental,
+                deprecated,
+                ty: parse_type(&tokens[3..])?,
+                extra: Extra::new(),
+            };
+            self.domain_mut()?.type_defs.push(type_def);
+            self.item = Some(ItemKind::TypeDef);
+            return Ok(());
+        }
+
+        if tokens.len() == 2 && (tokens[0] == "command" || tokens[0] == "event") {
+            let method = Method {
+                name: tokens[1].into(),
+                description,
+                experimental,
+                deprecated,
+                handlers: vec![],
+                parameters: vec![],
+                returns: vec![],
+                redirect: None,
+                extra: Extra::new(),
+            };
+            if tokens[0] == "command" {
+                self.domain_mut()?.commands.push(method);
+                self.item = Some(ItemKind::Command);
+            } else {
+                self.domain_mut()?.events.push(method);
+                self.item = Some(ItemKind::Event);
+            }
+            return Ok(());
+        }
+
+        Err(format!(
+            "expected 'depends on', 'type', 'command' or 'event', found '{}'",
+            tokens.join(" ")
+        ))
+    }
+
+    fn parse_item_member(&mut self, tokens: &[&str]) -> Result<(), String> {
+        self.section = None;
+        self.enum_target = None;
+
+        let item = match self.item {
+            Some(item) => item,
+            None => return Err(format!("'{}' found outside of an item", tokens.join(" "))),
+        };
+
+        match (item, tokens[0], tokens.len()) {
+            (ItemKind::TypeDef, "properties", 1) => match self.type_def_mut()?.ty {
+                Type::Object(_) => {
+                    self.section = Some(Section::Properties);
+                    Ok(())
+                }
+                _ => Err("'properties' found in a type that doesn't extend object".into()),
+            },
+            (ItemKind::TypeDef, "enum", 1) => {
+                let type_def = self.type_def_mut()?;
+                match type_def.ty {
+                    Type::String => type_def.ty = Type::Enum(vec![]),
+                    Type::Array { ref mut item, .. } if item.ty == Type::String => {
+                        item.ty = Type::Enum(vec![])
+                    }
+                    Type::Enum(_) => (),
+                    _ => return Err("'enum' found in a type that doesn't extend string".into()),
+                }
+                self.enum_target = Some(EnumTarget::TypeDef);
+                Ok(())
+            }
+            (ItemKind::Command, "parameters", 1) | (ItemKind::Event, "parameters", 1) => {
+                self.section = Some(Section::Parameters);
+                Ok(())
+            }
+            (ItemKind::Command, "returns", 1) => {
+                self.section = Some(Section::Returns);
+                Ok(())
+            }
+            (ItemKind::Command, "redirect", 2) | (ItemKind::Event, "redirect", 2) => {
+                self.method_mut()?.redirect = Some(tokens[1].into());
+                Ok(())
+            }
+            _ => Err(format!("unexpected '{}'", tokens.join(" "))),
+        }
+    }
+
+    fn parse_field(&mut self, tokens: &[&str], description: Option<String>) -> Result<(), String> {
+        self.enum_target = None;
+
+        let (experimental, deprecated, optional, tokens) = split_field_flags(tokens);
+        let (name, ty) = match tokens.split_last() {
+            Some((name, ty)) if !ty.is_empty() => (name, ty),
+            _ => {
+                return Err(format!(
+                    "expected a field type and name, found '{}'",
+                    tokens.join(" ")
+                ))
+            }
+        };
+        let field = Field {
+            name: (*name).into(),
+            description,
+            experimental,
+            deprecated,
+            optional,
+            ty: parse_type(ty)?,
+            extra: Extra::new(),
+        };
+        let field_is_enum = is_enum(&field.ty);
+
+        self.fields_mut()?.push(field);
+        if field_is_enum {
+            self.enum_target = Some(EnumTarget::Field);
+        }
+        Ok(())
+    }
+
+    fn push_enum_value(&mut self, value: &str) -> Result<(), String> {
+        let ty = match self.enum_target {
+            Some(EnumTarget::TypeDef) => &mut self.type_def_mut()?.ty,
+            Some(EnumTarget::Field) => match self.fields_mut()?.last_mut() {
+                Some(field) => &mut field.ty,
+                None => return Err("enum value found outside of an enum".into()),
+            },
+            None => return Err("enum value found outside of an enum".into()),
+        };
+        match enum_values_mut(ty) {
+            Some(values) => {
+                values.push(value.into());
+                Ok(())
+            }
+            None => Err("enum value found outside of an enum".into()),
+        }
+    }
+
+    fn domain_mut(&mut self) -> Result<&mut Domain, String> {
+        self.domains
+            .last_mut()
+            .ok_or_else(|| "found outside of a domain".into())
+    }
+
+    fn type_def_mut(&mut self) -> Result<&mut TypeDef, String> {
+        self.domain_mut()?
+            .type_defs
+            .last_mut()
+            .ok_or_else(|| "found outside of a type".into())
+    }
+
+    fn method_mut(&mut self) -> Result<&mut Method, String> {
+        let item = self.item;
+        let domain = self.domain_mut()?;
+        let method = match item {
+            Some(ItemKind::Command) => domain.commands.last_mut(),
+            Some(ItemKind::Event) => domain.events.last_mut(),
+            _ => None,
+        };
+        method.ok_or_else(|| "found outside of a command or event".into())
+    }
+
+    fn fields_mut(&mut self) -> Result<&mut Vec<Field>, String> {
+        match self.section {
+            Some(Section::Parameters) => Ok(&mut self.method_mut()?.parameters),
+            Some(Section::Returns) => Ok(&mut self.method_mut()?.returns),
+            Some(Section::Properties) => match self.type_def_mut()?.ty {
+                Type::Object(ref mut properties) => Ok(properties),
+                _ => Err("'properties' found in a type that doesn't extend object".into()),
+            },
+            None => Err("field found outside of 'parameters', 'returns' or 'properties'".into()),
+        }
+    }
+}
+
+fn split_flags<'a, 'b>(mut tokens: &'b [&'a str], min_rest: usize) -> (bool, bool, &'b [&'a str]) {
+    let mut experimental = false;
+    let mut deprecated = false;
+    while tokens.len() > min_rest {
+        match tokens[0] {
+            "experimental" => experimental = true,
+            "deprecated" => deprecated = true,
+            _ => break,
+        }
+        tokens = &tokens[1..];
+    }
+    (experimental, deprecated, tokens)
+}
+
+fn split_field_flags<'a, 'b>(mut tokens: &'b [&'a str]) -> (bool, bool, bool, &'b [&'a str]) {
+    let mut experimental = false;
+    let mut deprecated = false;
+    let mut optional = false;
+    while tokens.len() > 2 {
+        match tokens[0] {
+            "experimental" => experimental = true,
+            "deprecated" => deprecated = true,
+            "optional" => optional = true,
+            _ => break,
+        }
+        tokens = &tokens[1..];
+    }
+    (experimental, deprecated, optional, tokens)
+}
+
+fn parse_type(tokens: &[&str]) -> Result<Type, String> {
+    if tokens.len() == 3 && tokens[0] == "array" && tokens[1] == "of" {
+        return Ok(Type::Array {
+            item: Box::new(Item {
+                description: None,
+                ty: parse_type(&tokens[2..])?,
+                extra: Extra::new(),
+            }),
+            min_items: None,
+            max_items: None,
+        });
+    }
+    if tokens.len() != 1 || tokens[0] == "array" {
+        return Err(format!("invalid type '{}'", tokens.join(" ")));
+    }
+
+    Ok(match tokens[0] {
+        "boolean" => Type::Boolean,
+        "integer" => Type::Integer,
+        "number" => Type::Number,
+        "string" => Type::String,
+        "enum" => Type::Enum(vec![]),
+        "object" => Type::Object(vec![]),
+        "any" => Type::Any,
+        name => Type::Reference(name.into()),
+    })
+}
+
+fn enum_values_mut(ty: &mut Type) -> Option<&mut Vec<String>> {
+    match *ty {
+        Type::Enum(ref mut values) => Some(values),
+        Type::Array { ref mut item, .. } => match item.ty {
+            Type::Enum(ref mut values) => Some(values),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "array",
+    "any",
+    "boolean",
+    "deprecated",
+    "enum",
+    "experimental",
+    "integer",
+    "number",
+    "object",
+    "optional",
+    "string",
+];
+
+fn print_domain(out: &mut String, domain: &Domain) -> Result<(), PrintError> {
+    let path = domain.name.as_str();
+    check_token(path, &domain.name)?;
+    check_extra(path, &domain.extra)?;
+
+    out.push('\n');
+    print_description(out, 0, &domain.description);
+    print_flags(out, domain.experimental, domain.deprecated);
+    out.push_str(&format!("domain {}\n", domain.name));
+    for dependency in &domain.dependencies {
+        check_token(path, dependency)?;
+        out.push_str(&format!("  depends on {}\n", dependency));
+    }
+
+    for type_def in &domain.type_defs {
+        let path = format!("{}.{}", domain.name, type_def.name);
+        check_token(&path, &type_def.name)?;
+        check_extra(&path, &type_def.extra)?;
+
+        out.push('\n');
+        print_description(out, 2, &type_def.description);
+        out.push_str("  ");
+        print_flags(out, type_def.experimental, type_def.deprecated);
+
+        // Enums and objects are spelled out in a block below the type itself.
+        let (ty, values, properties) = match type_def.ty {
+            Type::Enum(ref values) => ("string".into(), Some(values), None),
+            Type::Array { ref item, .. } if is_enum(&item.ty) => {
+                // Still reject anything else about the array that can't be
+                // written down.
+                print_type(&path, &type_def.ty)?;
+                ("array of string".into(), enum_values(&item.ty), None)
+            }
+            Type::Object(ref properties) => ("object".into(), None, Some(properties)),
+            ref ty => (print_type(&path, ty)?, None, None),
+        };
+        out.push_str(&format!("type {} extends {}\n", type_def.name, ty));
+        if let Some(values) = values {
+            out.push_str("    enum\n");
+            print_enum_values(out, &path, 6, values)?;
+        }
+        if let Some(properties) = properties {
+            if !properties.is_empty() {
+                out.push_str("    properties\n");
+                print_fields(out, &path, properties)?;
+            }
+        }
+    }
+
+    for command in &domain.commands {
+        print_method(out, domain, "command", command)?;
+    }
+    for event in &domain.events {
+        print_method(out, domain, "event", event)?;
+    }
+
+    Ok(())
+}
+
+fn print_method(
+    out: &mut String,
+    domain: &Domain,
+    kind: &str,
+    method: &Method,
+) -> Result<(), PrintError> {
+    let path = format!("{}.{}", domain.name, method.name);
+    check_token(&path, &method.name)?;
+    check_extra(&path, &method.extra)?;
+    if !method.handlers.is_empty() {
+        return Err(print_error(&path, "PDL has no syntax for 'handlers'"));
+    }
+
+    out.push('\n');
+    print_description(out, 2, &method.description);
+    out.push_str("  ");
+    print_flags(out, method.experimental, method.deprecated);
+    out.push_str(&format!("{} {}\n", kind, method.name));
+    if let Some(ref redirect) = method.redirect {
+        check_token(&path, redirect)?;
+        out.push_str(&format!("    redirect {}\n", redirect));
+    }
+    if !method.parameters.is_empty() {
+        out.push_str("    parameters\n");
+        print_fields(out, &path, &method.parameters)?;
+    }
+    if !method.returns.is_empty() {
+        out.push_str("    returns\n");
+        print_fields(out, &path, &method.returns)?;
+    }
+    Ok(())
+}
+
+fn print_fields(out: &mut String, path: &str, fields: &[Field]) -> Result<(), PrintError> {
+    for field in fields {
+        let path = format!("{}.{}", path, field.name);
+        check_token(&path, &field.name)?;
+        check_extra(&path, &field.extra)?;
+
+        print_description(out, 6, &field.description);
+        out.push_str("      ");
+        print_flags(out, field.experimental, field.deprecated);
+        if field.optional {
+            out.push_str("optional ");
+        }
+        out.push_str(&format!("{} {}\n", print_type(&path, &field.ty)?, field.name));
+        if let Some(values) = enum_values(&field.ty) {
+            print_enum_values(out, &path, 8, values)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_type(path: &str, ty: &Type) -> Result<String, PrintError> {
+    match *ty {
+        Type::Reference(ref target) => {
+            check_token(path, target)?;
+            if KEYWORDS.contains(&target.as_str()) {
+                return Err(print_error(path, "reference target is a PDL keyword"));
+            }
+            Ok(target.clone())
+        }
+        Type::Boolean => Ok("boolean".into()),
+        Type::Integer => Ok("integer".into()),
+        Type::Number => Ok("number".into()),
+        Type::String => Ok("string".into()),
+        Type::Enum(_) => Ok("enum".into()),
+        Type::Object(ref properties) => {
+            if !properties.is_empty() {
+                return Err(print_error(path, "PDL has no syntax for inline object properties"));
+            }
+            Ok("object".into())
+        }
+        Type::Array {
+            ref item,
+            min_items,
+            max_items,
+        } => {
+            if min_items.is_some() || max_items.is_some() {
+                return Err(print_error(path, "PDL has no syntax for array bounds"));
+            }
+            if item.description.is_some() {
+                return Err(print_error(path, "PDL has no syntax for array item descriptions"));
+            }
+            if let Type::Array { .. } = item.ty {
+                return Err(print_error(path, "PDL has no syntax for nested arrays"));
+            }
+            check_extra(path, &item.extra)?;
+            Ok(format!("array of {}", print_type(path, &item.ty)?))
+        }
+        Type::Any => Ok("any".into()),
+    }
+}
+
+fn print_enum_values(
+    out: &mut String,
+    path: &str,
+    indent: usize,
+    values: &[String],
+) -> Result<(), PrintError> {
+    for value in values {
+        check_token(path, value)?;
+        out.push_str(&format!("{:indent$}{}\n", "", value, indent = indent));
+    }
+    Ok(())
+}
+
+fn print_description(out: &mut String, indent: usize, description: &Option<String>) {
+    if let Some(ref description) = *description {
+        for line in description.split('\n') {
+            if line.is_empty() {
+                out.push_str(&format!("{:indent$}#\n", "", indent = indent));
+            } else {
+                out.push_str(&format!("{:indent$}# {}\n", "", line, indent = indent));
+            }
+        }
+    }
+}
+
+fn print_flags(out: &mut String, experimental: bool, deprecated: bool) {
+    if experimental {
+        out.push_str("experimental ");
+    }
+    if deprecated {
+        out.push_str("deprecated ");
+    }
+}
+
+fn check_token(path: &str, token: &str) -> Result<(), PrintError> {
+    if token.is_empty() || token.starts_with('#') || token.contains(char::is_whitespace) {
+        return Err(print_error(path, &format!("'{}' is not a valid PDL token", token)));
+    }
+    Ok(())
+}
+
+fn check_extra(path: &str, extra: &Extra) -> Result<(), PrintError> {
+    match extra.keys().next() {
+        Some(key) => {
+            let message = format!("PDL has no syntax for unknown key '{}'", key);
+            Err(print_error(path, &message))
+        }
+        None => Ok(()),
+    }
+}
+
+fn is_enum(ty: &Type) -> bool {
+    enum_values(ty).is_some()
+}
+
+fn enum_values(ty: &Type) -> Option<&Vec<String>> {
+    match *ty {
+        Type::Enum(ref values) => Some(values),
+        Type::Array { ref item, .. } => match item.ty {
+            Type::Enum(ref values) => Some(values),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn print_error(path: &str, message: &str) -> PrintError {
+    PrintError {
+        path: path.into(),
+        message: message.into(),
+    }
+}
@@ -0,0 +1,257 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+// Renders a protocol reference as a set of markdown pages: "index.md", which
+// lists the domains, and one "Domain.md" per domain. Each command, event and
+// type gets an HTML anchor ("command-name", "event-name" or "type-Name") so
+// that references to types link to their definition, in the same domain or
+// another one. Experimental and deprecated items are flagged with badges that
+// can be styled through the "badge", "experimental" and "deprecated" classes.
+
+use {Definition, Domain, Field, Method, Type};
+use resolve::Resolver;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Page {
+    pub file_name: String,
+    pub content: String,
+}
+
+pub fn render(def: &Definition) -> Vec<Page> {
+    let renderer = Renderer {
+        resolver: Resolver::new(def),
+    };
+
+    let mut pages = vec![
+        Page {
+            file_name: "index.md".into(),
+            content: renderer.index(def),
+        },
+    ];
+    for domain in &def.domains {
+        pages.push(Page {
+            file_name: page_name(&domain.name),
+            content: renderer.domain(domain),
+        });
+    }
+    pages
+}
+
+struct Renderer<'a> {
+    resolver: Resolver<'a>,
+}
+
+impl<'a> Renderer<'a> {
+    fn index(&self, def: &Definition) -> String {
+        let mut out = format!(
+            "# Protocol reference\n\nVersion {}.{}\n\n## Domains\n\n",
+            def.version.major,
+            def.version.minor
+        );
+        for domain in &def.domains {
+            out.push_str(&format!(
+                "- [{}]({}){}",
+                domain.name,
+                page_name(&domain.name),
+                badges(domain.experimental, domain.deprecated)
+            ));
+            if let Some(summary) = summary(&domain.description) {
+                out.push_str(&format!(": {}", summary));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn domain(&self, domain: &Domain) -> String {
+        let mut out = format!(
+            "# {}{}\n\n",
+            domain.name,
+            badges(domain.experimental, domain.deprecated)
+        );
+        push_description(&mut out, &domain.description);
+
+        if !domain.dependencies.is_empty() {
+            let dependencies: Vec<String> = domain
+                .dependencies
+                .iter()
+                .map(|name| match self.resolver.domain(name) {
+                    Some(_) => format!("[{}]({})", name, page_name(name)),
+                    None => name.clone(),
+                })
+                .collect();
+            out.push_str(&format!("Depends on: {}\n\n", dependencies.join(", ")));
+        }
+
+        if !domain.commands.is_empty() {
+            out.push_str("## Commands\n\n");
+            for command in &domain.commands {
+                self.method(&mut out, domain, command, "command");
+            }
+        }
+
+        if !domain.events.is_empty() {
+            out.push_str("## Events\n\n");
+            for event in &domain.events {
+                self.method(&mut out, domain, event, "event");
+            }
+        }
+
+        if !domain.type_defs.is_empty() {
+            out.push_str("## Types\n\n");
+            for type_def in &domain.type_defs {
+                out.push_str(&format!(
+                    "<a id=\"type-{}\"></a>\n\n### {}.{}{}\n\n",
+                    type_def.name,
+                    domain.name,
+                    type_def.name,
+                    badges(type_def.experimental, type_def.deprecated)
+                ));
+                push_description(&mut out, &type_def.description);
+                out.push_str(&format!("Type: {}\n\n", self.type_name(domain, &type_def.ty)));
+                match type_def.ty {
+                    Type::Enum(ref values) => {
+                        out.push_str("Allowed values:\n\n");
+                        for value in values {
+                            out.push_str(&format!("- `{}`\n", value));
+                        }
+                        out.push('\n');
+                    }
+                    Type::Object(ref properties) if !properties.is_empty() => {
+                        self.fields(&mut out, domain, "Properties", properties)
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        out
+    }
+
+    fn method(&self, out: &mut String, domain: &Domain, method: &Method, kind: &str) {
+        out.push_str(&format!(
+            "<a id=\"{}-{}\"></a>\n\n### {}.{}{}\n\n",
+            kind,
+            method.name,
+            domain.name,
+            method.name,
+            badges(method.experimental, method.deprecated)
+        ));
+        push_description(out, &method.description);
+        if let Some(ref redirect) = method.redirect {
+            out.push_str(&format!("Redirects to the {} domain.\n\n", redirect));
+        }
+        self.fields(out, domain, "Parameters", &method.parameters);
+        self.fields(out, domain, "Returns", &method.returns);
+    }
+
+    fn fields(&self, out: &mut String, domain: &Domain, heading: &str, fields: &[Field]) {
+        if fields.is_empty() {
+            return;
+        }
+        out.push_str(&format!(
+            "**{}**\n\n| Name | Type | Description |\n| --- | --- | --- |\n",
+            heading
+        ));
+        self.field_rows(out, domain, "", fields);
+        out.push('\n');
+    }
+
+    // Inline objects are flattened into the same table, with their properties
+    // named "parent.child".
+    fn field_rows(&self, out: &mut String, domain: &Domain, prefix: &str, fields: &[Field]) {
+        for field in fields {
+            let name = format!("{}{}", prefix, field.name);
+            let mut description = String::new();
+            if let Some(ref desc) = field.description {
+                description.push_str(&escape_cell(desc));
+            }
+            if let Type::Enum(ref values) = field.ty {
+                if !description.is_empty() {
+                    description.push_str("<br>");
+                }
+                let values: Vec<String> = values.iter().map(|v| format!("`{}`", v)).collect();
+                description.push_str(&format!("Allowed values: {}", values.join(", ")));
+            }
+
+            out.push_str(&format!(
+                "| `{}`{}{} | {} | {} |\n",
+                name,
+                if field.optional { " *(optional)*" } else { "" },
+                badges(field.experimental, field.deprecated),
+                self.type_name(domain, &field.ty),
+                description
+            ));
+
+            if let Type::Object(ref properties) = field.ty {
+                self.field_rows(out, domain, &format!("{}.", name), properties);
+            }
+        }
+    }
+
+    fn type_name(&self, domain: &Domain, ty: &Type) -> String {
+        match *ty {
+            Type::Reference(ref target) => match self.resolver.resolve(&domain.name, target) {
+                Some(resolved) => {
+                    let anchor = format!("#type-{}", resolved.type_def.name);
+                    let link = if resolved.domain.name == domain.name {
+                        anchor
+                    } else {
+                        format!("{}{}", page_name(&resolved.domain.name), anchor)
+                    };
+                    format!(
+                        "[{}.{}]({})",
+                        resolved.domain.name,
+                        resolved.type_def.name,
+                        link
+                    )
+                }
+                None => format!("`{}`", target),
+            },
+            Type::Boolean => "boolean".into(),
+            Type::Integer => "integer".into(),
+            Type::Number => "number".into(),
+            Type::String | Type::Enum(_) => "string".into(),
+            Type::Array { ref item, .. } => {
+                format!("array of {}", self.type_name(domain, &item.ty))
+            }
+            Type::Object(_) => "object".into(),
+            Type::Any => "any".into(),
+        }
+    }
+}
+
+fn page_name(domain_name: &str) -> String {
+    format!("{}.md", domain_name)
+}
+
+fn badges(experimental: bool, deprecated: bool) -> String {
+    let mut badges = String::new();
+    if experimental {
+        badges.push_str(" <span class=\"badge experimental\">Experimental</span>");
+    }
+    if deprecated {
+        badges.push_str(" <span class=\"badge deprecated\">Deprecated</span>");
+    }
+    badges
+}
+
+fn push_description(out: &mut String, description: &Option<String>) {
+    if let Some(ref description) = *description {
+        out.push_str(description);
+        out.push_str("\n\n");
+    }
+}
+
+fn summary(description: &Option<String>) -> Option<&str> {
+    description
+        .as_ref()
+        .and_then(|description| description.lines().next())
+        .map(str::trim)
+        .and_then(|line| if line.is_empty() { None } else { Some(line) })
+}
+
+fn escape_cell(src: &str) -> String {
+    src.replace('|', "\\|").replace('\n', "<br>")
+}
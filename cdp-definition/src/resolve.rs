@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use {Definition, Domain, Type, TypeDef};
+use visit::{split_reference, walk_types};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ResolvedType<'a> {
+    pub domain: &'a Domain,
+    pub type_def: &'a TypeDef,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reference<'a> {
+    pub path: String,
+    pub domain: &'a Domain,
+    pub target: &'a str,
+    pub resolved: Option<ResolvedType<'a>>,
+}
+
+// When a domain or type is defined more than once, the first definition wins;
+// `Definition::validate` reports the others.
+#[derive(Clone, Debug)]
+pub struct Resolver<'a> {
+    def: &'a Definition,
+    domains: HashMap<&'a str, (&'a Domain, HashMap<&'a str, &'a TypeDef>)>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(def: &'a Definition) -> Self {
+        let mut domains = HashMap::new();
+        for domain in &def.domains {
+            if let Entry::Vacant(entry) = domains.entry(domain.name.as_str()) {
+                let mut type_defs = HashMap::new();
+                for type_def in &domain.type_defs {
+                    type_defs.entry(type_def.name.as_str()).or_insert(type_def);
+                }
+                entry.insert((domain, type_defs));
+            }
+        }
+        Resolver {
+            def,
+            domains,
+        }
+    }
+
+    pub fn domain(&self, name: &str) -> Option<&'a Domain> {
+        self.domains.get(name).map(|&(domain, _)| domain)
+    }
+
+    // `target` is resolved the way a `$ref` appearing in `domain_name` would
+    // be.
+    pub fn resolve(&self, domain_name: &str, target: &str) -> Option<ResolvedType<'a>> {
+        let (domain_name, type_name) = split_reference(domain_name, target);
+        self.domains
+            .get(domain_name)
+            .and_then(|&(domain, ref type_defs)| {
+                type_defs.get(type_name).map(|&type_def| {
+                    ResolvedType {
+                        domain,
+                        type_def,
+                    }
+                })
+            })
+    }
+
+    pub fn references(&self) -> Vec<Reference<'a>> {
+        let mut references = Vec::new();
+        walk_types(self.def, |domain, path, ty| {
+            if let Type::Reference(ref target) = *ty {
+                references.push(Reference {
+                    path: path.into(),
+                    domain,
+                    target,
+                    resolved: self.resolve(&domain.name, target),
+                });
+            }
+        });
+        references
+    }
+}
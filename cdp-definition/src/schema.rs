@@ -0,0 +1,156 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+// Export to JSON Schema (draft 2020-12). Everything lives under `$defs`:
+//
+// * "Domain.Type" for each type definition,
+// * "Domain.command.parameters" and "Domain.command.returns" for each
+//   command, and
+// * "Domain.event.parameters" for each event.
+//
+// Objects don't forbid additional properties, since newer browsers are free
+// to send fields that an older definition doesn't know about.
+
+use serde_json::{Map, Value};
+
+use {Definition, Field, Type};
+use visit::split_reference;
+
+pub const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+pub fn to_value(def: &Definition) -> Value {
+    let mut defs = Map::new();
+    for domain in &def.domains {
+        for type_def in &domain.type_defs {
+            let mut schema = type_schema(&domain.name, &type_def.ty);
+            annotate(
+                &mut schema,
+                &type_def.description,
+                domain.experimental || type_def.experimental,
+                domain.deprecated || type_def.deprecated,
+            );
+            defs.insert(format!("{}.{}", domain.name, type_def.name), schema);
+        }
+
+        let methods = domain
+            .commands
+            .iter()
+            .map(|command| (command, true))
+            .chain(domain.events.iter().map(|event| (event, false)));
+        for (method, is_command) in methods {
+            let mut sections = vec![("parameters", &method.parameters)];
+            if is_command {
+                sections.push(("returns", &method.returns));
+            }
+            for (section, fields) in sections {
+                let mut schema = object_schema(&domain.name, fields);
+                annotate(
+                    &mut schema,
+                    &method.description,
+                    domain.experimental || method.experimental,
+                    domain.deprecated || method.deprecated,
+                );
+                let name = format!("{}.{}.{}", domain.name, method.name, section);
+                defs.insert(name, schema);
+            }
+        }
+    }
+
+    let mut schema = Map::new();
+    schema.insert("$schema".into(), DRAFT.into());
+    schema.insert("title".into(), format!("Chrome DevTools Protocol {}", def.version).into());
+    schema.insert("$defs".into(), Value::Object(defs));
+    Value::Object(schema)
+}
+
+fn type_schema(domain_name: &str, ty: &Type) -> Value {
+    let mut schema = Map::new();
+    match *ty {
+        Type::Reference(ref target) => {
+            let (domain_name, type_name) = split_reference(domain_name, target);
+            let pointer = format!("#/$defs/{}.{}", domain_name, type_name);
+            schema.insert("$ref".into(), pointer.into());
+        }
+        Type::Boolean => {
+            schema.insert("type".into(), "boolean".into());
+        }
+        Type::Integer => {
+            schema.insert("type".into(), "integer".into());
+        }
+        Type::Number => {
+            schema.insert("type".into(), "number".into());
+        }
+        Type::String => {
+            schema.insert("type".into(), "string".into());
+        }
+        Type::Enum(ref values) => {
+            let values = values.iter().map(|value| value.as_str().into()).collect();
+            schema.insert("type".into(), "string".into());
+            schema.insert("enum".into(), Value::Array(values));
+        }
+        Type::Array {
+            ref item,
+            min_items,
+            max_items,
+        } => {
+            let mut item_schema = type_schema(domain_name, &item.ty);
+            annotate(&mut item_schema, &item.description, false, false);
+            schema.insert("type".into(), "array".into());
+            schema.insert("items".into(), item_schema);
+            if let Some(min_items) = min_items {
+                schema.insert("minItems".into(), min_items.into());
+            }
+            if let Some(max_items) = max_items {
+                schema.insert("maxItems".into(), max_items.into());
+            }
+        }
+        Type::Object(ref properties) => return object_schema(domain_name, properties),
+        Type::Any => (),
+    }
+    Value::Object(schema)
+}
+
+fn object_schema(domain_name: &str, fields: &[Field]) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for field in fields {
+        let mut field_schema = type_schema(domain_name, &field.ty);
+        annotate(&mut field_schema, &field.description, field.experimental, field.deprecated);
+        properties.insert(field.name.clone(), field_schema);
+        if !field.optional {
+            required.push(field.name.as_str().into());
+        }
+    }
+
+    let mut schema = Map::new();
+    schema.insert("type".into(), "object".into());
+    if !properties.is_empty() {
+        schema.insert("properties".into(), Value::Object(properties));
+    }
+    if !required.is_empty() {
+        schema.insert("required".into(), Value::Array(required));
+    }
+    Value::Object(schema)
+}
+
+// "deprecated" is a standard annotation; "experimental" has no equivalent, so
+// it goes under an extension keyword.
+fn annotate(
+    schema: &mut Value,
+    description: &Option<String>,
+    experimental: bool,
+    deprecated: bool,
+) {
+    if let Value::Object(ref mut schema) = *schema {
+        if let Some(ref description) = *description {
+            schema.insert("description".into(), description.as_str().into());
+        }
+        if experimental {
+            schema.insert("x-experimental".into(), true.into());
+        }
+        if deprecated {
+            schema.insert("deprecated".into(), true.into());
+        }
+    }
+}
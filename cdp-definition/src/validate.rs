@@ -0,0 +1,297 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+
+use {Definition, Field, Type};
+use resolve::Resolver;
+use visit::walk_types;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    DuplicateDomain,
+    DuplicateTypeDef,
+    DuplicateCommand,
+    DuplicateEvent,
+    DuplicateField,
+    DuplicateEnumValue(String),
+    UnresolvedReference(String),
+    UnknownDependency(String),
+    UndeclaredDependency(String),
+    ReferenceCycle(Vec<String>),
+}
+
+impl DiagnosticKind {
+    // Warnings cover things that Chromium's own definitions get wrong but
+    // that don't stop code generation.
+    pub fn severity(&self) -> Severity {
+        match *self {
+            DiagnosticKind::UnknownDependency(_) | DiagnosticKind::UndeclaredDependency(_) => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            DiagnosticKind::DuplicateDomain => write!(f, "duplicate domain"),
+            DiagnosticKind::DuplicateTypeDef => write!(f, "duplicate type"),
+            DiagnosticKind::DuplicateCommand => write!(f, "duplicate command"),
+            DiagnosticKind::DuplicateEvent => write!(f, "duplicate event"),
+            DiagnosticKind::DuplicateField => write!(f, "duplicate field"),
+            DiagnosticKind::DuplicateEnumValue(ref value) => {
+                write!(f, "duplicate enum value '{}'", value)
+            }
+            DiagnosticKind::UnresolvedReference(ref target) => {
+                write!(f, "reference to unknown type '{}'", target)
+            }
+            DiagnosticKind::UnknownDependency(ref domain) => {
+                write!(f, "dependency on unknown domain '{}'", domain)
+            }
+            DiagnosticKind::UndeclaredDependency(ref domain) => {
+                write!(f, "reference to domain '{}' which isn't a listed dependency", domain)
+            }
+            DiagnosticKind::ReferenceCycle(ref cycle) => {
+                write!(f, "types contain each other by value: {}", cycle.join(" -> "))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub kind: DiagnosticKind,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+pub fn validate(def: &Definition) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    check_duplicates(def, &mut diagnostics);
+    check_references(def, &mut diagnostics);
+    check_cycles(def, &mut diagnostics);
+    diagnostics
+}
+
+fn check_duplicates(def: &Definition, diagnostics: &mut Vec<Diagnostic>) {
+    let mut domain_names = HashSet::new();
+    for domain in &def.domains {
+        if !domain_names.insert(domain.name.as_str()) {
+            push(diagnostics, &domain.name, DiagnosticKind::DuplicateDomain);
+        }
+
+        let mut type_def_names = HashSet::new();
+        for type_def in &domain.type_defs {
+            if !type_def_names.insert(type_def.name.as_str()) {
+                let path = format!("{}.{}", domain.name, type_def.name);
+                push(diagnostics, &path, DiagnosticKind::DuplicateTypeDef);
+            }
+        }
+
+        for (methods, kind) in &[
+            (&domain.commands, DiagnosticKind::DuplicateCommand),
+            (&domain.events, DiagnosticKind::DuplicateEvent),
+        ] {
+            let mut method_names = HashSet::new();
+            for method in methods.iter() {
+                let path = format!("{}.{}", domain.name, method.name);
+                if !method_names.insert(method.name.as_str()) {
+                    push(diagnostics, &path, kind.clone());
+                }
+                check_duplicate_fields(&path, &method.parameters, diagnostics);
+                check_duplicate_fields(&path, &method.returns, diagnostics);
+            }
+        }
+    }
+
+    walk_types(def, |_, path, ty| match *ty {
+        Type::Object(ref properties) => check_duplicate_fields(path, properties, diagnostics),
+        Type::Enum(ref values) => {
+            let mut seen = HashSet::new();
+            for value in values {
+                if !seen.insert(value.as_str()) {
+                    push(diagnostics, path, DiagnosticKind::DuplicateEnumValue(value.clone()));
+                }
+            }
+        }
+        _ => (),
+    });
+}
+
+fn check_duplicate_fields(path: &str, fields: &[Field], diagnostics: &mut Vec<Diagnostic>) {
+    let mut field_names = HashSet::new();
+    for field in fields {
+        if !field_names.insert(field.name.as_str()) {
+            let path = format!("{}.{}", path, field.name);
+            push(diagnostics, &path, DiagnosticKind::DuplicateField);
+        }
+    }
+}
+
+fn check_references(def: &Definition, diagnostics: &mut Vec<Diagnostic>) {
+    let resolver = Resolver::new(def);
+
+    for domain in &def.domains {
+        for dependency in &domain.dependencies {
+            if resolver.domain(dependency).is_none() {
+                let kind = DiagnosticKind::UnknownDependency(dependency.clone());
+                push(diagnostics, &domain.name, kind);
+            }
+        }
+    }
+
+    // Only report each missing dependency once per domain.
+    let mut undeclared = HashSet::new();
+    for reference in resolver.references() {
+        match reference.resolved {
+            None => {
+                let kind = DiagnosticKind::UnresolvedReference(reference.target.into());
+                push(diagnostics, &reference.path, kind);
+            }
+            Some(resolved) => {
+                let from = reference.domain.name.as_str();
+                let to = resolved.domain.name.as_str();
+                if from != to && !reference.domain.dependencies.iter().any(|d| d == to)
+                    && undeclared.insert((from, to))
+                {
+                    let kind = DiagnosticKind::UndeclaredDependency(to.into());
+                    push(diagnostics, &reference.path, kind);
+                }
+            }
+        }
+    }
+}
+
+// Types that contain each other by value (directly, or through object
+// properties) would have infinite size. Arrays break the chain, and so does a
+// property referring back to its own type, which the code generator boxes.
+fn check_cycles(def: &Definition, diagnostics: &mut Vec<Diagnostic>) {
+    let resolver = Resolver::new(def);
+
+    let mut names = Vec::new();
+    let mut indices = HashMap::new();
+    for domain in &def.domains {
+        for type_def in &domain.type_defs {
+            let name = format!("{}.{}", domain.name, type_def.name);
+            if !indices.contains_key(&name) {
+                indices.insert(name.clone(), names.len());
+                names.push(name);
+            }
+        }
+    }
+
+    let mut edges = vec![vec![]; names.len()];
+    for domain in &def.domains {
+        for type_def in &domain.type_defs {
+            let from = indices[&format!("{}.{}", domain.name, type_def.name)];
+            let mut targets = Vec::new();
+            by_value_references(&type_def.ty, false, &mut targets);
+            for (target, in_property) in targets {
+                if let Some(resolved) = resolver.resolve(&domain.name, target) {
+                    let to_name = format!("{}.{}", resolved.domain.name, resolved.type_def.name);
+                    let to = indices[&to_name];
+                    if !in_property || to != from {
+                        edges[from].push(to);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut state = vec![Visit::New; names.len()];
+    let mut stack = Vec::new();
+    let mut cycles = Vec::new();
+    for index in 0..names.len() {
+        find_cycles(index, &edges, &mut state, &mut stack, &mut cycles);
+    }
+
+    let mut seen = HashSet::new();
+    for cycle in cycles {
+        // Rotate each cycle to a canonical starting point so that the same
+        // loop found from different nodes is only reported once.
+        let start = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+        let cycle: Vec<usize> = cycle[start..]
+            .iter()
+            .chain(cycle[..start].iter())
+            .cloned()
+            .collect();
+        if seen.insert(cycle.clone()) {
+            let mut cycle_names: Vec<String> = cycle.iter().map(|&i| names[i].clone()).collect();
+            let path = cycle_names[0].clone();
+            cycle_names.push(path.clone());
+            push(diagnostics, &path, DiagnosticKind::ReferenceCycle(cycle_names));
+        }
+    }
+}
+
+fn by_value_references<'a>(ty: &'a Type, in_property: bool, targets: &mut Vec<(&'a str, bool)>) {
+    match *ty {
+        Type::Reference(ref target) => targets.push((target, in_property)),
+        Type::Object(ref properties) => for property in properties {
+            by_value_references(&property.ty, true, targets);
+        },
+        _ => (),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Visit {
+    New,
+    OnStack,
+    Done,
+}
+
+fn find_cycles(
+    index: usize,
+    edges: &[Vec<usize>],
+    state: &mut Vec<Visit>,
+    stack: &mut Vec<usize>,
+    cycles: &mut Vec<Vec<usize>>,
+) {
+    if state[index] != Visit::New {
+        return;
+    }
+    state[index] = Visit::OnStack;
+    stack.push(index);
+    for &next in &edges[index] {
+        match state[next] {
+            Visit::New => find_cycles(next, edges, state, stack, cycles),
+            Visit::OnStack => {
+                let start = stack.iter().position(|&i| i == next).unwrap_or(0);
+                cycles.push(stack[start..].to_vec());
+            }
+            Visit::Done => (),
+        }
+    }
+    stack.pop();
+    state[index] = Visit::Done;
+}
+
+fn push(diagnostics: &mut Vec<Diagnostic>, path: &str, kind: DiagnosticKind) {
+    diagnostics.push(Diagnostic {
+        path: path.into(),
+        kind,
+    });
+}
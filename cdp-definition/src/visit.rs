@@ -0,0 +1,56 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use {Definition, Domain, Field, Type};
+
+// Calls `f` for every type in the definition, including the types nested
+// inside object properties and array items, along with a dotted path to where
+// it was found (e.g. "Page.navigate.url").
+pub fn walk_types<'a, F>(def: &'a Definition, mut f: F)
+where
+    F: FnMut(&'a Domain, &str, &'a Type),
+{
+    for domain in &def.domains {
+        for type_def in &domain.type_defs {
+            let path = format!("{}.{}", domain.name, type_def.name);
+            walk_type(domain, &path, &type_def.ty, &mut f);
+        }
+        for method in domain.commands.iter().chain(domain.events.iter()) {
+            let path = format!("{}.{}", domain.name, method.name);
+            walk_fields(domain, &path, &method.parameters, &mut f);
+            walk_fields(domain, &path, &method.returns, &mut f);
+        }
+    }
+}
+
+pub fn walk_fields<'a, F>(domain: &'a Domain, path: &str, fields: &'a [Field], f: &mut F)
+where
+    F: FnMut(&'a Domain, &str, &'a Type),
+{
+    for field in fields {
+        let path = format!("{}.{}", path, field.name);
+        walk_type(domain, &path, &field.ty, f);
+    }
+}
+
+pub fn walk_type<'a, F>(domain: &'a Domain, path: &str, ty: &'a Type, f: &mut F)
+where
+    F: FnMut(&'a Domain, &str, &'a Type),
+{
+    f(domain, path, ty);
+    match *ty {
+        Type::Array { ref item, .. } => walk_type(domain, path, &item.ty, f),
+        Type::Object(ref properties) => walk_fields(domain, path, properties, f),
+        _ => (),
+    }
+}
+
+// Splits a `$ref` target into the domain and type names it refers to. Targets
+// without a domain prefix refer to the domain they're used in.
+pub fn split_reference<'a>(domain_name: &'a str, target: &'a str) -> (&'a str, &'a str) {
+    match target.find('.') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (domain_name, target),
+    }
+}
@@ -6,11 +6,15 @@
 #![cfg_attr(feature = "clippy", feature(plugin))]
 #![cfg_attr(feature = "clippy", plugin(clippy))]
 
+#[macro_use]
 extern crate serde_json;
 
 extern crate cdp_definition;
 
-use cdp_definition::Definition;
+use cdp_definition::{diff, lenient, pdl, reference, schema, Change, ChangeKind, Definition,
+                     Diagnostic, DiagnosticKind, ItemRef, Linter, MergeError, MessageValidator,
+                     Mismatch, MismatchKind, Overlay, Rule, Severity, Type, ALL_RULES};
+use serde_json::Value;
 
 #[test]
 fn test_browser_protocol() {
@@ -32,3 +36,1277 @@ fn do_test_protocol_file(orig_src: &str) {
     assert_eq!(orig_def, new_def);
     assert_eq!(new_src, serde_json::to_string(&new_def).expect("proto def (re-)serialize error"));
 }
+
+#[test]
+fn test_browser_protocol_pdl() {
+    do_test_pdl_round_trip(include_str!("../../json/browser_protocol.json"));
+}
+
+#[test]
+fn test_js_protocol_pdl() {
+    do_test_pdl_round_trip(include_str!("../../json/js_protocol.json"));
+}
+
+#[test]
+fn test_pdl_parse() {
+    let src = r#"# Copyright notice, which doesn't belong to anything.
+
+version
+  major 1
+  minor 3
+
+# Multi-line
+#
+#  description.
+experimental domain Accessibility
+  depends on DOM
+
+  # Unique accessibility node identifier.
+  type AXNodeId extends string
+
+  type AXValueType extends string
+    enum
+      boolean
+      tristate
+
+  type AXNodeIds extends array of AXNodeId
+
+  type AXValueSource extends object
+    properties
+      # What type of source this is.
+      AXValueType type
+      optional array of DOM.NodeId nodeIds
+      experimental optional enum superseded
+        yes
+        no
+
+  deprecated command getPartialAXTree
+    redirect DOM
+    parameters
+      optional DOM.NodeId nodeId
+    returns
+      array of AXNodeId nodes
+
+  event loadComplete
+"#;
+    let def = pdl::from_str(src).expect("pdl parse error");
+    let expected = serde_json::from_str::<Definition>(
+        r#"{
+            "version": { "major": "1", "minor": "3" },
+            "domains": [{
+                "domain": "Accessibility",
+                "description": "Multi-line\n\n description.",
+                "experimental": true,
+                "dependencies": ["DOM"],
+                "types": [
+                    {
+                        "id": "AXNodeId",
+                        "description": "Unique accessibility node identifier.",
+                        "type": "string"
+                    },
+                    { "id": "AXValueType", "type": "string", "enum": ["boolean", "tristate"] },
+                    { "id": "AXNodeIds", "type": "array", "items": { "$ref": "AXNodeId" } },
+                    {
+                        "id": "AXValueSource",
+                        "type": "object",
+                        "properties": [
+                            {
+                                "name": "type",
+                                "description": "What type of source this is.",
+                                "$ref": "AXValueType"
+                            },
+                            {
+                                "name": "nodeIds",
+                                "optional": true,
+                                "type": "array",
+                                "items": { "$ref": "DOM.NodeId" }
+                            },
+                            {
+                                "name": "superseded",
+                                "experimental": true,
+                                "optional": true,
+                                "type": "string",
+                                "enum": ["yes", "no"]
+                            }
+                        ]
+                    }
+                ],
+                "commands": [{
+                    "name": "getPartialAXTree",
+                    "deprecated": true,
+                    "redirect": "DOM",
+                    "parameters": [{ "name": "nodeId", "optional": true, "$ref": "DOM.NodeId" }],
+                    "returns": [
+                        { "name": "nodes", "type": "array", "items": { "$ref": "AXNodeId" } }
+                    ]
+                }],
+                "events": [{ "name": "loadComplete" }]
+            }]
+        }"#,
+    ).expect("proto def parse error");
+    assert_eq!(expected, def);
+    assert_eq!(def, pdl::from_str(&pdl::to_string(&def).unwrap()).unwrap());
+}
+
+#[test]
+fn test_pdl_parse_error() {
+    let src = "version\n  major 1\n  minor 3\n\ndomain Foo\n  type Bar extends array\n";
+    let err = pdl::from_str(src).unwrap_err();
+    assert_eq!(6, err.line);
+    assert_eq!("invalid type 'array'", err.message);
+
+    let err = pdl::from_str("domain Foo\n").unwrap_err();
+    assert_eq!((1, "missing protocol version"), (err.line, err.message.as_str()));
+}
+
+#[test]
+fn test_pdl_print_error() {
+    let mut def: Definition =
+        serde_json::from_str(include_str!("../../json/js_protocol.json")).unwrap();
+    let err = pdl::to_string(&def).unwrap_err();
+    assert_eq!("Schema.getDomains", err.path);
+    assert_eq!("PDL has no syntax for 'handlers'", err.message);
+
+    strip_non_pdl(&mut def);
+    def.domains[0].type_defs[0].name = "Two words".into();
+    assert!(pdl::to_string(&def).is_err());
+}
+
+#[test]
+fn test_validate_protocol() {
+    let def = load_protocol();
+    let diagnostics = def.validate();
+    assert!(
+        diagnostics.iter().all(|d| d.severity() == Severity::Warning),
+        "{:#?}",
+        diagnostics
+    );
+
+    let mut undeclared: Vec<String> = diagnostics
+        .iter()
+        .map(|d| match d.kind {
+            DiagnosticKind::UndeclaredDependency(ref to) => {
+                format!("{} -> {}", d.path.split('.').next().unwrap(), to)
+            }
+            ref kind => panic!("unexpected diagnostic: {:?}", kind),
+        })
+        .collect();
+    undeclared.sort();
+    assert_eq!(
+        vec![
+            "ApplicationCache -> Page",
+            "Browser -> Target",
+            "CSS -> Page",
+            "DOM -> Page",
+            "DOMDebugger -> Runtime",
+            "IO -> Runtime",
+            "Network -> Page",
+            "Page -> Emulation",
+            "Page -> Runtime",
+            "ServiceWorker -> Target",
+        ],
+        undeclared
+    );
+}
+
+#[test]
+fn test_validate_errors() {
+    let def: Definition = serde_json::from_str(
+        r#"{
+            "version": { "major": "1", "minor": "0" },
+            "domains": [
+                {
+                    "domain": "A",
+                    "dependencies": ["B", "C"],
+                    "types": [
+                        { "id": "Alias", "$ref": "Alias" },
+                        {
+                            "id": "Node",
+                            "type": "object",
+                            "properties": [
+                                { "name": "parent", "optional": true, "$ref": "Node" },
+                                {
+                                    "name": "children",
+                                    "type": "array",
+                                    "items": { "$ref": "Node" }
+                                },
+                                { "name": "other", "$ref": "B.Other" }
+                            ]
+                        },
+                        { "id": "Node", "type": "string", "enum": ["x", "y", "x"] }
+                    ],
+                    "commands": [
+                        {
+                            "name": "run",
+                            "parameters": [
+                                { "name": "x", "$ref": "Missing" },
+                                { "name": "x", "type": "integer" }
+                            ]
+                        },
+                        { "name": "run" }
+                    ],
+                    "events": [
+                        { "name": "ran", "parameters": [{ "name": "y", "$ref": "D.Thing" }] }
+                    ]
+                },
+                {
+                    "domain": "B",
+                    "types": [
+                        {
+                            "id": "Other",
+                            "type": "object",
+                            "properties": [{ "name": "node", "$ref": "A.Node" }]
+                        }
+                    ]
+                },
+                { "domain": "B" }
+            ]
+        }"#,
+    ).expect("proto def parse error");
+
+    let diagnostic = |path: &str, kind| {
+        Diagnostic {
+            path: path.into(),
+            kind: kind,
+        }
+    };
+    assert_eq!(
+        vec![
+            diagnostic("A.Node", DiagnosticKind::DuplicateTypeDef),
+            diagnostic("A.run.x", DiagnosticKind::DuplicateField),
+            diagnostic("A.run", DiagnosticKind::DuplicateCommand),
+            diagnostic("B", DiagnosticKind::DuplicateDomain),
+            diagnostic("A.Node", DiagnosticKind::DuplicateEnumValue("x".into())),
+            diagnostic("A", DiagnosticKind::UnknownDependency("C".into())),
+            diagnostic("A.run.x", DiagnosticKind::UnresolvedReference("Missing".into())),
+            diagnostic("A.ran.y", DiagnosticKind::UnresolvedReference("D.Thing".into())),
+            diagnostic("B.Other.node", DiagnosticKind::UndeclaredDependency("A".into())),
+            diagnostic(
+                "A.Alias",
+                DiagnosticKind::ReferenceCycle(vec!["A.Alias".into(), "A.Alias".into()]),
+            ),
+            diagnostic(
+                "A.Node",
+                DiagnosticKind::ReferenceCycle(vec![
+                    "A.Node".into(),
+                    "B.Other".into(),
+                    "A.Node".into(),
+                ]),
+            ),
+        ],
+        def.validate()
+    );
+    assert_eq!(
+        "A.Node: types contain each other by value: A.Node -> B.Other -> A.Node",
+        def.validate().last().unwrap().to_string()
+    );
+}
+
+#[test]
+fn test_resolver() {
+    let def = load_protocol();
+    let resolver = def.resolver();
+
+    let resolved = resolver.resolve("Page", "FrameId").expect("resolve error");
+    assert_eq!("Page", resolved.domain.name);
+    assert_eq!("FrameId", resolved.type_def.name);
+    let resolved = resolver.resolve("Page", "Runtime.ScriptId").expect("resolve error");
+    assert_eq!("Runtime", resolved.domain.name);
+    assert_eq!("ScriptId", resolved.type_def.name);
+    assert_eq!(None, resolver.resolve("Page", "ScriptId"));
+    assert_eq!(None, resolver.resolve("Page", "Nope.ScriptId"));
+
+    let references = resolver.references();
+    assert!(references.iter().all(|r| r.resolved.is_some()));
+    let reference = references
+        .iter()
+        .find(|r| r.path == "Page.navigate.frameId")
+        .expect("reference not found");
+    assert_eq!("FrameId", reference.target);
+    assert_eq!(Some(resolver.resolve("Page", "FrameId").unwrap()), reference.resolved);
+}
+
+#[test]
+fn test_diff_protocol() {
+    let def = load_protocol();
+    assert!(diff(&def, &def).is_empty());
+}
+
+#[test]
+fn test_diff() {
+    let old: Definition = serde_json::from_str(
+        r#"{
+            "version": { "major": "1", "minor": "2" },
+            "domains": [
+                {
+                    "domain": "Page",
+                    "types": [
+                        { "id": "FrameId", "type": "string" },
+                        { "id": "Mode", "type": "string", "enum": ["a", "b"] }
+                    ],
+                    "commands": [
+                        {
+                            "name": "navigate",
+                            "parameters": [
+                                { "name": "url", "type": "string" },
+                                { "name": "referrer", "optional": true, "type": "string" }
+                            ],
+                            "returns": [{ "name": "frameId", "$ref": "FrameId" }]
+                        },
+                        { "name": "reload" }
+                    ],
+                    "events": [
+                        { "name": "loaded", "parameters": [{ "name": "when", "type": "number" }] }
+                    ]
+                },
+                { "domain": "Old" }
+            ]
+        }"#,
+    ).expect("proto def parse error");
+    let new: Definition = serde_json::from_str(
+        r#"{
+            "version": { "major": "1", "minor": "3" },
+            "domains": [
+                {
+                    "domain": "Page",
+                    "experimental": true,
+                    "types": [
+                        { "id": "FrameId", "type": "integer" },
+                        { "id": "Mode", "type": "string", "enum": ["b", "c"] }
+                    ],
+                    "commands": [
+                        {
+                            "name": "navigate",
+                            "parameters": [
+                                { "name": "url", "type": "string" },
+                                { "name": "referrer", "type": "string" },
+                                { "name": "mode", "$ref": "Page.Mode" }
+                            ],
+                            "returns": [
+                                { "name": "frameId", "optional": true, "$ref": "Page.FrameId" }
+                            ]
+                        }
+                    ],
+                    "events": [
+                        {
+                            "name": "loaded",
+                            "deprecated": true,
+                            "parameters": [
+                                { "name": "when", "type": "number" },
+                                { "name": "where", "type": "string" }
+                            ]
+                        }
+                    ]
+                },
+                { "domain": "New" }
+            ]
+        }"#,
+    ).expect("proto def parse error");
+
+    let change = |path: &str, kind, breaking| {
+        Change {
+            path: path.into(),
+            kind: kind,
+            breaking: breaking,
+        }
+    };
+    let result = diff(&old, &new);
+    assert_eq!(
+        vec![
+            change(
+                "version",
+                ChangeKind::VersionChanged {
+                    old: "1.2".into(),
+                    new: "1.3".into(),
+                },
+                false,
+            ),
+            change("Page", ChangeKind::ExperimentalChanged { experimental: true }, false),
+            change(
+                "Page.FrameId",
+                ChangeKind::TypeChanged {
+                    old: "string".into(),
+                    new: "integer".into(),
+                },
+                true,
+            ),
+            change("Page.Mode", ChangeKind::EnumValueRemoved { value: "a".into() }, true),
+            change("Page.Mode", ChangeKind::EnumValueAdded { value: "c".into() }, true),
+            change("Page.navigate.referrer", ChangeKind::FieldBecameRequired, true),
+            change("Page.navigate.mode", ChangeKind::FieldAdded { optional: false }, true),
+            change("Page.navigate.frameId", ChangeKind::FieldBecameOptional, true),
+            change("Page.reload", ChangeKind::CommandRemoved, true),
+            change("Page.loaded", ChangeKind::DeprecatedChanged { deprecated: true }, false),
+            change("Page.loaded.where", ChangeKind::FieldAdded { optional: false }, false),
+            change("Old", ChangeKind::DomainRemoved, true),
+            change("New", ChangeKind::DomainAdded, false),
+        ],
+        result.changes
+    );
+    assert!(result.is_breaking());
+    assert_eq!(8, result.breaking_changes().len());
+
+    let text = result.to_string();
+    assert_eq!(
+        Some("breaking: Page.Mode: enum value 'a' removed"),
+        text.lines().nth(3)
+    );
+    assert_eq!(
+        Some("non-breaking: Page.loaded.where: required field added"),
+        text.lines().nth(10)
+    );
+
+    let json = serde_json::to_value(&result.changes[6]).expect("serialize error");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(
+            r#"{
+                "path": "Page.navigate.mode",
+                "kind": { "change": "fieldAdded", "optional": false },
+                "breaking": true
+            }"#,
+        ).unwrap(),
+        json
+    );
+}
+
+#[test]
+fn test_merge() {
+    let mut def: Definition =
+        serde_json::from_str(include_str!("../../json/browser_protocol.json")).unwrap();
+    let js_def: Definition =
+        serde_json::from_str(include_str!("../../json/js_protocol.json")).unwrap();
+
+    let mut other_version = js_def.clone();
+    other_version.version.minor = "0".into();
+    match def.clone().merge(other_version) {
+        Err(MergeError::VersionMismatch { .. }) => (),
+        result => panic!("unexpected merge result: {:?}", result),
+    }
+
+    let domain_count = def.domains.len() + js_def.domains.len();
+    def.merge(js_def.clone()).unwrap();
+    assert_eq!(domain_count, def.domains.len());
+    assert_eq!(
+        Err(MergeError::DuplicateDomain("Schema".into())),
+        def.clone().merge(js_def)
+    );
+}
+
+#[test]
+fn test_overlay() {
+    let mut def = load_protocol();
+    let overlay: Overlay = serde_json::from_str(
+        r#"{
+            "patches": [
+                {
+                    "op": "addDomain",
+                    "domain": {
+                        "domain": "Custom",
+                        "commands": [{ "name": "ping" }]
+                    }
+                },
+                {
+                    "op": "addTypes",
+                    "domain": "Page",
+                    "types": [{ "id": "Custom", "type": "string" }]
+                },
+                {
+                    "op": "addFields",
+                    "target": "Page.navigate.parameters",
+                    "fields": [{ "name": "custom", "$ref": "Custom", "optional": true }]
+                },
+                {
+                    "op": "setOptional",
+                    "target": "Page.Frame.properties",
+                    "field": "securityOrigin",
+                    "optional": true
+                }
+            ]
+        }"#,
+    ).unwrap();
+    def.apply_overlay(&overlay).unwrap();
+    assert!(def.validate().iter().all(|d| d.severity() == Severity::Warning));
+
+    let custom = def.domains.iter().find(|d| d.name == "Custom").unwrap();
+    assert_eq!("ping", custom.commands[0].name);
+    let page = def.domains.iter().find(|d| d.name == "Page").unwrap();
+    assert!(page.type_defs.iter().any(|t| t.name == "Custom"));
+    let navigate = page.commands.iter().find(|c| c.name == "navigate").unwrap();
+    assert_eq!(Type::Reference("Custom".into()), navigate.parameters.last().unwrap().ty);
+    let frame = page.type_defs.iter().find(|t| t.name == "Frame").unwrap();
+    match frame.ty {
+        Type::Object(ref properties) => {
+            let origin = properties.iter().find(|p| p.name == "securityOrigin").unwrap();
+            assert!(origin.optional);
+        }
+        ref ty => panic!("unexpected type: {:?}", ty),
+    }
+
+    // A failing patch leaves the definition untouched.
+    let before = def.clone();
+    let overlay: Overlay = serde_json::from_str(
+        r#"{
+            "patches": [
+                { "op": "addEvents", "domain": "Custom", "events": [{ "name": "pong" }] },
+                {
+                    "op": "addFields",
+                    "target": "Page.navigate.parameters",
+                    "fields": [{ "name": "url", "type": "string" }]
+                }
+            ]
+        }"#,
+    ).unwrap();
+    assert_eq!(
+        Err(MergeError::DuplicateItem("Page.navigate.parameters.url".into())),
+        def.apply_overlay(&overlay)
+    );
+    assert_eq!(before, def);
+
+    let overlay: Overlay = serde_json::from_str(
+        r#"{
+            "patches": [
+                {
+                    "op": "setOptional",
+                    "target": "Page.navigate.returns",
+                    "field": "nope",
+                    "optional": true
+                }
+            ]
+        }"#,
+    ).unwrap();
+    assert_eq!(
+        Err(MergeError::UnknownTarget("Page.navigate.returns.nope".into())),
+        def.apply_overlay(&overlay)
+    );
+}
+
+#[test]
+fn test_index() {
+    let def = load_protocol();
+    let index = def.index();
+
+    let (domain, navigate) = index.command("Page.navigate").unwrap();
+    assert_eq!("Page", domain.name);
+    assert_eq!("navigate", navigate.name);
+    assert!(index.command("Page.frameNavigated").is_none());
+    assert!(index.event("Page.frameNavigated").is_some());
+    assert!(index.type_def("Network.RequestId").is_some());
+    assert!(index.type_def("Network.requestWillBeSent").is_none());
+
+    let event_names: Vec<&str> = index
+        .events("Console")
+        .iter()
+        .map(|e| e.name.as_str())
+        .collect();
+    assert_eq!(vec!["messageAdded"], event_names);
+    assert!(index.commands("Nope").is_empty());
+
+    let references = index.references_to("Network.RequestId");
+    assert!(references.iter().all(|r| r.target.type_def.name == "RequestId"));
+    assert!(references.iter().any(|r| r.path == "Network.requestWillBeSent.requestId"));
+    assert!(references.iter().any(|r| r.path == "Network.getResponseBody.requestId"));
+
+    let referrers = index.referrers("Network.RequestId");
+    let mut referrer_paths: Vec<String> = referrers.iter().map(|item| item.path()).collect();
+    referrer_paths.dedup();
+    assert_eq!(referrers.len(), referrer_paths.len());
+    assert!(referrer_paths.contains(&"Network.getResponseBody".into()));
+
+    // Frame is used by value in FrameTree, so getResourceTree depends on it
+    // without referring to it directly.
+    let commands: Vec<String> = index
+        .commands_using("Page.Frame")
+        .iter()
+        .map(|&(domain, command)| format!("{}.{}", domain.name, command.name))
+        .collect();
+    assert!(commands.contains(&"Page.getResourceTree".into()));
+    assert!(
+        !index
+            .referrers("Page.Frame")
+            .iter()
+            .any(|item| item.path() == "Page.getResourceTree")
+    );
+    assert!(index.dependents("Page.Frame").iter().any(|item| match *item {
+        ItemRef::Event(_, event) => event.name == "frameNavigated",
+        _ => false,
+    }));
+}
+
+#[test]
+fn test_json_schema() {
+    let schema = schema::to_value(&load_protocol());
+    assert_eq!(schema::DRAFT, schema["$schema"]);
+
+    let defs = &schema["$defs"];
+    assert_eq!(
+        json!({
+            "$ref": "#/$defs/Network.LoaderId",
+            "description": "Identifier of the loader associated with this frame."
+        }),
+        defs["Page.Frame"]["properties"]["loaderId"]
+    );
+    assert_eq!(
+        json!({ "$ref": "#/$defs/Runtime.RemoteObject", "description": "Evaluation result." }),
+        defs["Runtime.evaluate.returns"]["properties"]["result"]
+    );
+    assert_eq!(true, defs["Page.FrameResourceTree"]["x-experimental"]);
+    assert_eq!(true, defs["Page.addScriptToEvaluateOnLoad.parameters"]["deprecated"]);
+    assert!(defs.get("Page.frameNavigated.returns").is_none());
+
+    let accepted = &[
+        ("Page.navigate.parameters", r#"{"url":"https://www.mozilla.org"}"#),
+        ("Page.navigate.parameters", r#"{"url":"https://www.mozilla.org","foo":"bar"}"#),
+        ("Page.navigate.returns", r#"{"frameId":"hello"}"#),
+        ("Page.enable.parameters", r#"{}"#),
+        ("Page.domContentEventFired.parameters", r#"{"timestamp":12.7}"#),
+        (
+            "Runtime.consoleAPICalled.parameters",
+            r#"{
+                "type": "log",
+                "args": [{ "type": "string", "value": "hi" }],
+                "executionContextId": 1,
+                "timestamp": 1500000000000.5
+            }"#,
+        ),
+        (
+            "Page.frameNavigated.parameters",
+            r#"{
+                "frame": {
+                    "id": "1.1",
+                    "loaderId": "2.1",
+                    "url": "https://www.mozilla.org/",
+                    "securityOrigin": "https://www.mozilla.org",
+                    "mimeType": "text/html"
+                }
+            }"#,
+        ),
+    ];
+    for &(name, message) in accepted {
+        let message: Value = serde_json::from_str(message).unwrap();
+        assert!(schema_accepts(&schema, &defs[name], &message), "{}: {}", name, message);
+    }
+
+    let rejected = &[
+        ("Page.navigate.parameters", r#"{}"#),
+        ("Page.navigate.parameters", r#"{"url":7}"#),
+        ("Page.domContentEventFired.parameters", r#"{"timestamp":"12.7"}"#),
+        (
+            "Runtime.consoleAPICalled.parameters",
+            r#"{"type":"shout","args":[],"executionContextId":1,"timestamp":1}"#,
+        ),
+        ("Page.frameNavigated.parameters", r#"{"frame":{"id":"1.1"}}"#),
+    ];
+    for &(name, message) in rejected {
+        let message: Value = serde_json::from_str(message).unwrap();
+        assert!(!schema_accepts(&schema, &defs[name], &message), "{}: {}", name, message);
+    }
+}
+
+// Enough of a JSON Schema validator to cover the keywords the exporter emits.
+fn schema_accepts(root: &Value, schema: &Value, value: &Value) -> bool {
+    if let Some(pointer) = schema["$ref"].as_str() {
+        let name = pointer.trim_left_matches("#/$defs/");
+        return schema_accepts(root, &root["$defs"][name], value);
+    }
+
+    let type_matches = match schema["type"].as_str() {
+        Some("boolean") => value.is_boolean(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("number") => value.is_number(),
+        Some("string") => value.is_string(),
+        Some("array") => value.is_array(),
+        Some("object") => value.is_object(),
+        _ => true,
+    };
+    if !type_matches {
+        return false;
+    }
+
+    if let Some(values) = schema["enum"].as_array() {
+        if !values.contains(value) {
+            return false;
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let min_items = schema["minItems"].as_u64().unwrap_or(0);
+        let max_items = schema["maxItems"].as_u64().unwrap_or(u64::max_value());
+        let len = items.len() as u64;
+        if len < min_items || len > max_items {
+            return false;
+        }
+        if !items
+            .iter()
+            .all(|item| schema_accepts(root, &schema["items"], item))
+        {
+            return false;
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema["required"].as_array() {
+            if !required
+                .iter()
+                .all(|name| object.contains_key(name.as_str().unwrap()))
+            {
+                return false;
+            }
+        }
+        if let Some(properties) = schema["properties"].as_object() {
+            for (name, property) in properties {
+                if let Some(value) = object.get(name) {
+                    if !schema_accepts(root, property, value) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    true
+}
+
+#[test]
+fn test_message_validator() {
+    let def = load_protocol();
+    let validator = MessageValidator::new(&def);
+
+    let params = json!({ "url": "https://www.mozilla.org", "transitionType": "typed" });
+    assert!(validator.validate_params("Page.navigate", &params).is_empty());
+    assert!(validator.validate_params("Page.enable", &Value::Null).is_empty());
+    assert!(
+        validator
+            .validate_params("Page.domContentEventFired", &json!({ "timestamp": 12.7 }))
+            .is_empty()
+    );
+
+    let mismatches: Vec<String> = validator
+        .validate_params(
+            "Page.captureScreenshot",
+            &json!({ "format": "gif", "quality": 1.5, "clip": { "x": 0 }, "a/b": true }),
+        )
+        .iter()
+        .map(|mismatch| mismatch.to_string())
+        .collect();
+    assert_eq!(
+        vec![
+            "/format: invalid enum value 'gif'",
+            "/quality: expected integer, found number",
+            "/clip/y: missing required field",
+            "/clip/width: missing required field",
+            "/clip/height: missing required field",
+            "/clip/scale: missing required field",
+            "/a~1b: unknown field",
+        ],
+        mismatches
+    );
+
+    let result = json!({
+        "model": {
+            "content": [0, 0, 1, 0, 1, 1, 0, 1],
+            "padding": [0, 0, 1, 0, 1, 1, 0],
+            "border": [0, 0, 1, 0, 1, 1, 0, 1, 2],
+            "margin": [0, 0, 1, 0, 1, 1, 0, "1"],
+            "width": 1
+        }
+    });
+    assert_eq!(
+        vec![
+            Mismatch {
+                pointer: "/model/padding".into(),
+                kind: MismatchKind::TooFewItems { min: 8, len: 7 },
+            },
+            Mismatch {
+                pointer: "/model/border".into(),
+                kind: MismatchKind::TooManyItems { max: 8, len: 9 },
+            },
+            Mismatch {
+                pointer: "/model/margin/7".into(),
+                kind: MismatchKind::WrongType {
+                    expected: "number",
+                    found: "string",
+                },
+            },
+            Mismatch {
+                pointer: "/model/height".into(),
+                kind: MismatchKind::MissingField,
+            },
+        ],
+        validator.validate_result("DOM.getBoxModel", &result)
+    );
+
+    assert_eq!(
+        vec![
+            Mismatch {
+                pointer: "".into(),
+                kind: MismatchKind::UnknownMethod("Page.frameNavigated".into()),
+            },
+        ],
+        validator.validate_result("Page.frameNavigated", &json!({}))
+    );
+    assert_eq!(
+        "(root): expected object, found array",
+        validator.validate_params("Page.navigate", &json!([]))[0].to_string()
+    );
+    assert_eq!(
+        vec![
+            Mismatch {
+                pointer: "/bogus".into(),
+                kind: MismatchKind::UnknownField,
+            },
+        ],
+        validator.validate_params("Page.enable", &json!({ "bogus": 1 }))
+    );
+}
+
+#[test]
+fn test_message_validator_alias_cycle() {
+    let def: Definition = serde_json::from_str(
+        r#"{
+            "version": { "major": "1", "minor": "0" },
+            "domains": [
+                {
+                    "domain": "A",
+                    "types": [
+                        { "id": "First", "$ref": "Second" },
+                        { "id": "Second", "$ref": "First" },
+                        { "id": "Extra", "type": "object" }
+                    ],
+                    "commands": [
+                        {
+                            "name": "run",
+                            "parameters": [
+                                { "name": "first", "$ref": "First" },
+                                { "name": "extra", "$ref": "Extra" }
+                            ]
+                        }
+                    ]
+                }
+            ]
+        }"#,
+    ).unwrap();
+    let validator = MessageValidator::new(&def);
+
+    let params = json!({ "first": 1, "extra": { "anything": true } });
+    assert_eq!(
+        vec![
+            Mismatch {
+                pointer: "/first".into(),
+                kind: MismatchKind::ReferenceCycle(vec![
+                    "A.First".into(),
+                    "A.Second".into(),
+                    "A.First".into(),
+                ]),
+            },
+        ],
+        validator.validate_params("A.run", &params)
+    );
+}
+
+#[test]
+fn test_lint_protocol() {
+    let violations = load_protocol().lint();
+    assert!(violations.iter().all(|v| v.severity == Severity::Warning));
+
+    let paths = |rule| -> Vec<String> {
+        violations
+            .iter()
+            .filter(|v| v.rule == rule)
+            .map(|v| v.path.clone())
+            .collect()
+    };
+    assert_eq!(
+        vec!["Database.addDatabase", "DOM.setChildNodes", "HeapProfiler.addHeapSnapshotChunk"],
+        paths(Rule::EventNamedLikeCommand)
+    );
+    assert_eq!(
+        vec![
+            "Accessibility.AXValue.value",
+            "Runtime.RemoteObject.value",
+            "Runtime.CallArgument.value",
+        ],
+        paths(Rule::OptionalAny)
+    );
+}
+
+#[test]
+fn test_lint() {
+    let def: Definition = serde_json::from_str(
+        r#"{
+            "version": { "major": "1", "minor": "2" },
+            "domains": [
+                {
+                    "domain": "my_domain",
+                    "description": "In-house domain.",
+                    "types": [
+                        {
+                            "id": "widgetKind",
+                            "type": "string",
+                            "enum": ["big", "really small"],
+                            "description": "Kind of widget."
+                        }
+                    ],
+                    "commands": [
+                        {
+                            "name": "Frob",
+                            "parameters": [
+                                { "name": "Kind", "$ref": "widgetKind" },
+                                { "name": "extra", "type": "any", "optional": true }
+                            ],
+                            "description": "Frobs a widget."
+                        }
+                    ],
+                    "events": [
+                        { "name": "frob", "description": "A widget was frobbed." },
+                        { "name": "setWidget" },
+                        { "name": "settled", "description": "Widgets have settled." }
+                    ]
+                }
+            ]
+        }"#,
+    ).unwrap();
+
+    let lines: Vec<String> = def.lint().iter().map(|v| v.to_string()).collect();
+    assert_eq!(
+        vec![
+            "my_domain: domain name isn't PascalCase [domain-name]",
+            "my_domain.widgetKind: type name isn't PascalCase [type-name]",
+            "my_domain.Frob: command name isn't camelCase [method-name]",
+            "my_domain.Frob.Kind: field name isn't camelCase [field-name]",
+            "my_domain.Frob.extra: optional field of type 'any' is ambiguous when null \
+             [optional-any]",
+            "my_domain.setWidget: event has no description [missing-description]",
+            "my_domain.frob: event has the same name as a command [event-named-like-command]",
+            "my_domain.setWidget: event name starts with the command verb 'set' \
+             [event-named-like-command]",
+            "my_domain.widgetKind: enum value 'really small' is blank or has whitespace \
+             [enum-value]",
+        ],
+        lines
+    );
+
+    let linter = Linter::empty()
+        .with_rule(Rule::MissingDescription, Severity::Error)
+        .with_rule(Rule::EnumValue, Severity::Warning);
+    let violations = linter.lint(&def);
+    assert_eq!(2, violations.len());
+    assert_eq!(Rule::MissingDescription, violations[0].rule);
+    assert_eq!(Severity::Error, violations[0].severity);
+    assert_eq!(Severity::Warning, violations[1].severity);
+
+    assert_eq!(0, Linter::empty().lint(&def).len());
+    assert_eq!(Linter::chromium().lint(&def), Linter::new().lint(&def));
+    assert_eq!(Linter::chromium().lint(&def), Linter::default().lint(&def));
+
+    let linter = Linter::chromium().without_rule(Rule::EventNamedLikeCommand);
+    assert_eq!(None, linter.rule(Rule::EventNamedLikeCommand));
+    assert_eq!(7, linter.lint(&def).len());
+
+    for &rule in ALL_RULES {
+        assert_eq!(Some(rule), Rule::from_name(rule.name()));
+    }
+}
+
+#[test]
+fn test_lenient() {
+    let src = include_str!("../../json/js_protocol.json");
+    let (def, source_map) = lenient::from_str(src).expect("lenient parse error");
+    assert_eq!(serde_json::from_str::<Definition>(src).unwrap(), def);
+    let span = source_map.get("Runtime.evaluate.expression").unwrap();
+    assert_eq!("{", &src[span.start..span.start + 1]);
+    assert!(src[span.start..span.end].contains("\"name\": \"expression\""));
+
+    let src = r#"{
+  "version": { "major": "1", "minor": "3", "build": "x" },
+  "x-vendor": "acme",
+  "domains": [
+    {
+      "domain": "Foo",
+      "x-owner": { "team": "foo" },
+      "types": [
+        {
+          "id": "Bar",
+          "type": "array",
+          "items": { "type": "string", "x-format": "url" }
+        }
+      ],
+      "commands": [
+        {
+          "name": "frob",
+          "parameters": [
+            { "name": "how", "type": "string", "x-since": 3 }
+          ]
+        }
+      ]
+    }
+  ]
+}"#;
+    assert!(serde_json::from_str::<Definition>(src).is_err());
+
+    let (def, source_map) = lenient::from_str(src).unwrap();
+    assert_eq!(Some(&json!("acme")), def.extra.get("x-vendor"));
+    assert_eq!(Some(&json!({"team": "foo"})), def.domains[0].extra.get("x-owner"));
+    assert_eq!(Some(&json!(3)), def.domains[0].commands[0].parameters[0].extra.get("x-since"));
+    match def.domains[0].type_defs[0].ty {
+        Type::Array { ref item, .. } => {
+            assert_eq!(Some(&json!("url")), item.extra.get("x-format"))
+        }
+        ref ty => panic!("unexpected type {:?}", ty),
+    }
+
+    assert_eq!(5, source_map.get("Foo").unwrap().line);
+    assert_eq!(9, source_map.get("Foo.Bar").unwrap().line);
+    assert_eq!(16, source_map.get("Foo.frob").unwrap().line);
+    let span = source_map.get("Foo.frob.how").unwrap();
+    assert_eq!((19, 13), (span.line, span.column));
+    assert_eq!(None, source_map.get("Foo.nope"));
+
+    let value = serde_json::to_value(&def).unwrap();
+    assert_eq!(json!("acme"), value["x-vendor"]);
+    assert_eq!(None, value["version"].get("build"));
+    assert_eq!(json!("url"), value["domains"][0]["types"][0]["items"]["x-format"]);
+    let (new_def, _) = lenient::from_str(&value.to_string()).unwrap();
+    assert_eq!(def, new_def);
+
+    let err = pdl::to_string(&def).unwrap_err();
+    assert_eq!("", err.path);
+    assert_eq!("PDL has no syntax for unknown key 'x-vendor'", err.message);
+
+    let bad_src = src.replace("\"type\": \"string\", \"x-since\"", "\"type\": 7, \"x-since\"");
+    let err = lenient::from_str(&bad_src).unwrap_err();
+    assert_eq!((19, 13), (err.line, err.column));
+
+    let err = lenient::from_str("{\n  \"domains\": [\n    nope\n  ]\n}").unwrap_err();
+    assert_eq!((3, 5), (err.line, err.column));
+
+    let deep_src = format!("{}{}", "[".repeat(200_000), "]".repeat(200_000));
+    let err = lenient::from_str(&deep_src).unwrap_err();
+    assert_eq!((1, 129), (err.line, err.column));
+    assert_eq!("recursion limit exceeded", err.message);
+    assert!(serde_json::from_str::<Definition>(&deep_src).is_err());
+
+    let bad_escapes = [
+        (r#"{ "x": "\u+12a" }"#, "invalid unicode escape"),
+        (r#"{ "x": "\u-12a" }"#, "invalid unicode escape"),
+        (r#"{ "x": "\u12g4" }"#, "invalid unicode escape"),
+        (r#"{ "x": "\u12" }"#, "invalid unicode escape"),
+        (r#"{ "x": "\ud800" }"#, "unpaired surrogate in unicode escape"),
+        (r#"{ "x": "\ud800\u+c00" }"#, "invalid unicode escape"),
+        (r#"{ "x": "\q" }"#, "invalid escape"),
+    ];
+    for &(bad_src, message) in &bad_escapes {
+        let err = lenient::from_str(bad_src).unwrap_err();
+        assert_eq!(message, err.message, "{}", bad_src);
+        assert!(serde_json::from_str::<Definition>(bad_src).is_err());
+    }
+}
+
+#[test]
+fn test_lenient_shared_paths() {
+    let src = r#"{
+  "version": { "major": "1", "minor": "3" },
+  "domains": [
+    {
+      "domain": "A",
+      "commands": [
+        {
+          "name": "m",
+          "parameters": [{ "name": "x", "type": "string" }],
+          "returns": [{ "name": "x", "type": "integer" }]
+        }
+      ],
+      "events": [{ "name": "m" }]
+    }
+  ]
+}"#;
+    let (_, source_map) = lenient::from_str(src).unwrap();
+
+    let spans = source_map.get_all("A.m.x");
+    assert_eq!(vec![9, 10], spans.iter().map(|span| span.line).collect::<Vec<_>>());
+    assert_eq!(Some(spans[0]), source_map.get("A.m.x"));
+    let span = source_map.get_pointer("/domains/0/commands/0/returns/0").unwrap();
+    assert_eq!((10, 23), (span.line, span.column));
+
+    assert_eq!(2, source_map.get_all("A.m").len());
+    assert_eq!(13, source_map.get_pointer("/domains/0/events/0").unwrap().line);
+    assert_eq!(5, source_map.iter().count());
+}
+
+#[test]
+fn test_reference() {
+    let def = load_protocol();
+    let pages = reference::render(&def);
+    assert_eq!(def.domains.len() + 1, pages.len());
+    assert_eq!("index.md", pages[0].file_name);
+    assert!(pages[0].content.contains("- [Page](Page.md)"));
+
+    let page = pages.iter().find(|page| page.file_name == "Page.md").unwrap();
+    assert!(page.content.contains("<a id=\"command-navigate\"></a>\n\n### Page.navigate\n"));
+    assert!(page.content.contains("<a id=\"event-frameNavigated\"></a>"));
+    assert!(page.content.contains("<a id=\"type-FrameId\"></a>"));
+    assert!(page.content.contains("| `frameId` | [Page.FrameId](#type-FrameId) |"));
+    assert!(page.content.contains("[Network.LoaderId](Network.md#type-LoaderId)"));
+
+    let src = r#"
+version
+  major 1
+  minor 3
+
+# Widgets.
+experimental domain Foo
+  depends on Bar
+
+  type Mode extends string
+    enum
+      fast
+      slow
+
+  type Shape extends object
+    properties
+      # Width | height.
+      number size
+      optional object box
+
+  deprecated command frob
+    parameters
+      array of Mode modes
+      experimental enum speed
+        low
+        high
+
+  event frobbed
+
+domain Bar
+  type Unit extends integer
+"#;
+    let mut def = pdl::from_str(src).unwrap();
+    let unit = json!({ "name": "unit", "$ref": "Bar.Unit" });
+    def.domains[0].type_defs[1].ty = match def.domains[0].type_defs[1].ty {
+        Type::Object(ref properties) => {
+            let mut properties = properties.clone();
+            properties[1].ty = Type::Object(vec![serde_json::from_value(unit).unwrap()]);
+            Type::Object(properties)
+        }
+        ref ty => panic!("unexpected type {:?}", ty),
+    };
+    let pages = reference::render(&def);
+    let names: Vec<&str> = pages.iter().map(|page| page.file_name.as_str()).collect();
+    assert_eq!(vec!["index.md", "Foo.md", "Bar.md"], names);
+    assert_eq!(
+        "# Protocol reference\n\nVersion 1.3\n\n## Domains\n\n\
+         - [Foo](Foo.md) <span class=\"badge experimental\">Experimental</span>: Widgets.\n\
+         - [Bar](Bar.md)\n",
+        pages[0].content
+    );
+    assert_eq!(
+        r#"# Foo <span class="badge experimental">Experimental</span>
+
+Widgets.
+
+Depends on: [Bar](Bar.md)
+
+## Commands
+
+<a id="command-frob"></a>
+
+### Foo.frob <span class="badge deprecated">Deprecated</span>
+
+**Parameters**
+
+| Name | Type | Description |
+| --- | --- | --- |
+| `modes` | array of [Foo.Mode](#type-Mode) |  |
+| `speed` <span class="badge experimental">Experimental</span> | string | Allowed values: `low`, `high` |
+
+## Events
+
+<a id="event-frobbed"></a>
+
+### Foo.frobbed
+
+## Types
+
+<a id="type-Mode"></a>
+
+### Foo.Mode
+
+Type: string
+
+Allowed values:
+
+- `fast`
+- `slow`
+
+<a id="type-Shape"></a>
+
+### Foo.Shape
+
+Type: object
+
+**Properties**
+
+| Name | Type | Description |
+| --- | --- | --- |
+| `size` | number | Width \| height. |
+| `box` *(optional)* | object |  |
+| `box.unit` | [Bar.Unit](Bar.md#type-Unit) |  |
+
+"#,
+        pages[1].content
+    );
+}
+
+fn load_protocol() -> Definition {
+    let mut def: Definition =
+        serde_json::from_str(include_str!("../../json/browser_protocol.json")).unwrap();
+    let js_def: Definition =
+        serde_json::from_str(include_str!("../../json/js_protocol.json")).unwrap();
+    def.merge(js_def).unwrap();
+    def
+}
+
+fn do_test_pdl_round_trip(orig_src: &str) {
+    let mut orig_def: Definition = serde_json::from_str(orig_src).expect("proto def parse error");
+    strip_non_pdl(&mut orig_def);
+
+    let pdl_src = pdl::to_string(&orig_def).expect("proto def pdl print error");
+    let new_def = pdl::from_str(pdl_src.as_str()).expect("proto def pdl parse error");
+
+    assert_eq!(orig_def, new_def);
+    assert_eq!(pdl_src, pdl::to_string(&new_def).expect("proto def pdl (re-)print error"));
+}
+
+// Strips the handful of things that the JSON format can express but PDL
+// can't.
+fn strip_non_pdl(def: &mut Definition) {
+    fn strip_type(ty: &mut Type) {
+        match *ty {
+            Type::Array {
+                ref mut item,
+                ref mut min_items,
+                ref mut max_items,
+            } => {
+                item.description = None;
+                *min_items = None;
+                *max_items = None;
+                strip_type(&mut item.ty);
+            }
+            Type::Object(ref mut properties) => for property in properties {
+                strip_type(&mut property.ty);
+            },
+            _ => (),
+        }
+    }
+
+    for domain in &mut def.domains {
+        for type_def in &mut domain.type_defs {
+            strip_type(&mut type_def.ty);
+        }
+        for method in domain.commands.iter_mut().chain(domain.events.iter_mut()) {
+            method.handlers.clear();
+            for field in method.parameters.iter_mut().chain(method.returns.iter_mut()) {
+                strip_type(&mut field.ty);
+            }
+        }
+    }
+}
@@ -9,93 +9,545 @@
 #![doc(html_root_url = "https://docs.rs/cdp-derive/0.1.0")]
 
 extern crate proc_macro;
+extern crate proc_macro2;
 #[macro_use]
 extern crate quote;
+#[macro_use]
 extern crate syn;
 
 use proc_macro::TokenStream;
-use quote::Tokens;
+use proc_macro2::{Span, TokenStream as Tokens, TokenTree};
 use std::mem;
-use syn::{Attribute, Body, DeriveInput, Generics, Ident, LifetimeDef, Lit, MetaItem, TyGenerics,
-          Variant, VariantData, WhereClause};
+use syn::{Attribute, Data, DeriveInput, Error, Expr, ExprLit, Field, Fields, GenericArgument,
+          GenericParam, Generics, Ident, Lifetime, LifetimeParam, Lit, LitStr, Meta,
+          PathArguments, Type, TypeGenerics, Variant, WhereClause};
+
+#[proc_macro_derive(CdpCommand, attributes(cdp))]
+pub fn derive_cdp_command(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_method_impls(&input, "Command"))
+}
+
+#[proc_macro_derive(CdpEvent, attributes(cdp))]
+pub fn derive_cdp_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_method_impls(&input, "Event"))
+}
+
+#[proc_macro_derive(SerializeCdpCommand, attributes(cdp))]
+pub fn derive_serialize_cdp_command(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_serialize_impl(&input, "Command"))
+}
+
+#[proc_macro_derive(SerializeCdpEvent, attributes(cdp))]
+pub fn derive_serialize_cdp_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_serialize_impl(&input, "Event"))
+}
 
 #[proc_macro_derive(DeserializeCdpCommand, attributes(cdp))]
 pub fn derive_deserialize_cdp_command(input: TokenStream) -> TokenStream {
-    let input = syn::parse_derive_input(&input.to_string()).unwrap();
-    match generate_cdp_deserialize_impl(&input, "Command") {
-        Ok(expanded) => expanded.parse().unwrap(),
-        Err(msg) => panic!(msg),
-    }
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_deserialize_impl(&input, "Command"))
 }
 
 #[proc_macro_derive(DeserializeCdpEvent, attributes(cdp))]
 pub fn derive_deserialize_cdp_event(input: TokenStream) -> TokenStream {
-    let input = syn::parse_derive_input(&input.to_string()).unwrap();
-    match generate_cdp_deserialize_impl(&input, "Event") {
-        Ok(expanded) => expanded.parse().unwrap(),
-        Err(msg) => panic!(msg),
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_deserialize_impl(&input, "Event"))
+}
+
+#[proc_macro_derive(CdpService, attributes(cdp))]
+pub fn derive_cdp_service(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(generate_cdp_service(&input))
+}
+
+// Errors are reported through `compile_error!`, spanned on the offending
+// variant, attribute or field. syn invokes it as `::core::compile_error!`,
+// which 2015-edition crates can't resolve, so it's rewritten to `::std`.
+fn expand(result: Result<Tokens, Error>) -> TokenStream {
+    match result {
+        Ok(expanded) => expanded.into(),
+        Err(err) => err.to_compile_error()
+            .into_iter()
+            .map(|token| match token {
+                TokenTree::Ident(ref ident) if ident == "core" => {
+                    TokenTree::Ident(Ident::new("std", ident.span()))
+                }
+                token => token,
+            })
+            .collect::<Tokens>()
+            .into(),
     }
 }
 
-fn generate_cdp_deserialize_impl(input: &DeriveInput, kind: &str) -> Result<Tokens, String> {
+// Emits the same impls that the generator does for each built-in command or
+// event struct (and, given a response type, for the response struct too).
+fn generate_cdp_method_impls(input: &DeriveInput, kind: &str) -> Result<Tokens, Error> {
     let DeriveInput {
         ref ident,
+        ref attrs,
         ref generics,
-        ref body,
+        ref data,
+        ..
+    } = *input;
+
+    if let Data::Struct(_) = *data {
+    } else {
+        return Err(Error::new(ident.span(), "expected a struct definition"));
+    }
+
+    let (method_name, maybe_response) = extract_method_attrs_from_struct(ident, attrs, kind)?;
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (de_quantification, _, _, unique_lifetime_prefix) =
+        generate_cdp_deserialize_impl_generics(generics);
+    let de_lifetime = Lifetime::new(&format!("'{}de", unique_lifetime_prefix), Span::call_site());
+
+    let self_ty = quote! { #ident #ty_generics };
+    let kind_where_clause = generate_where_clause(where_clause, &[]);
+    let serialize_where_clause =
+        generate_where_clause(where_clause, &[quote! { #self_ty: serde::Serialize }]);
+    let deserialize_where_clause = generate_where_clause(
+        where_clause,
+        &[quote! { #self_ty: serde::Deserialize<#de_lifetime> }],
+    );
+
+    let response_impls = match maybe_response {
+        None => None,
+        Some(ref response) => Some(generate_cdp_response_impls(
+            ident,
+            generics,
+            &method_name,
+            response,
+            &unique_lifetime_prefix,
+        )?),
+    };
+
+    let kind_trait = format_ident!("Cdp{}", kind);
+    let name_const = format_ident!("{}_NAME", kind.to_uppercase());
+    let serialize_trait = format_ident!("SerializeCdp{}", kind);
+    let name_fn_name = format_ident!("{}_name", kind.to_lowercase());
+    let serialize_params_fn_name = format_ident!("serialize_{}_params", kind.to_lowercase());
+    let deserialize_trait = format_ident!("DeserializeCdp{}", kind);
+    let deserialize_fn_name = format_ident!("deserialize_{}", kind.to_lowercase());
+    let deserialize_borrowed_trait = format_ident!("DeserializeBorrowedCdp{}", kind);
+    let deserialize_borrowed_fn_name =
+        format_ident!("deserialize_borrowed_{}", kind.to_lowercase());
+
+    Ok(quote! {
+        #[allow(non_upper_case_globals, unused_attributes, unused_qualifications)]
+        const _: () = {
+            extern crate cdp;
+            extern crate serde;
+
+            impl #impl_generics cdp::#kind_trait for #self_ty #kind_where_clause {
+                const #name_const: &'static str = #method_name;
+            }
+
+            impl #impl_generics cdp::#serialize_trait for #self_ty #serialize_where_clause {
+                fn #name_fn_name(&self) -> &str {
+                    #method_name
+                }
+
+                fn #serialize_params_fn_name<S>(
+                    &self,
+                    serializer: S,
+                ) -> ::std::result::Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer
+                {
+                    serde::Serialize::serialize(self, serializer)
+                }
+            }
+
+            impl #de_quantification cdp::#deserialize_borrowed_trait<#de_lifetime>
+                    for #self_ty #deserialize_where_clause {
+                fn #deserialize_borrowed_fn_name<D>(
+                    name: &#de_lifetime str,
+                    params: D,
+                ) -> ::std::result::Result<::std::result::Result<Self, D::Error>, D>
+                where
+                    D: serde::Deserializer<#de_lifetime>
+                {
+                    <Self as cdp::#deserialize_trait<#de_lifetime>>::#deserialize_fn_name(
+                        name,
+                        params,
+                    )
+                }
+            }
+
+            impl #de_quantification cdp::#deserialize_trait<#de_lifetime>
+                    for #self_ty #deserialize_where_clause {
+                fn #deserialize_fn_name<D>(
+                    name: &str,
+                    params: D,
+                ) -> ::std::result::Result<::std::result::Result<Self, D::Error>, D>
+                where
+                    D: serde::Deserializer<#de_lifetime>
+                {
+                    if name == #method_name {
+                        Ok(<#self_ty as serde::Deserialize<#de_lifetime>>::deserialize(params))
+                    } else {
+                        Err(params)
+                    }
+                }
+            }
+
+            #response_impls
+        };
+    })
+}
+
+// Like the generated structs, the command and response may each borrow for
+// at most one lifetime, which the relation impls tie to their own.
+fn generate_cdp_response_impls(
+    ident: &Ident,
+    generics: &Generics,
+    method_name: &str,
+    response: &Type,
+    unique_lifetime_prefix: &str,
+) -> Result<Tokens, Error> {
+    let lifetime_count = generics.lifetimes().count();
+    if generics.params.len() != lifetime_count || lifetime_count > 1 {
+        return Err(Error::new_spanned(
+            generics,
+            format!(
+                "`{}` may only have one lifetime parameter and no type parameters if it has a \
+                 response",
+                ident
+            ),
+        ));
+    }
+
+    let command_lifetime =
+        Lifetime::new(&format!("'{}a", unique_lifetime_prefix), Span::call_site());
+    let response_lifetime =
+        Lifetime::new(&format!("'{}b", unique_lifetime_prefix), Span::call_site());
+
+    let maybe_command_lifetime = if lifetime_count == 0 {
+        None
+    } else {
+        Some(&command_lifetime)
+    };
+    let command_ty = match maybe_command_lifetime {
+        None => quote! { #ident },
+        Some(command_lifetime) => quote! { #ident<#command_lifetime> },
+    };
+
+    let (response_ty, response_uses_lifetime) =
+        replace_response_lifetime(response, &response_lifetime)?;
+    let maybe_response_lifetime = if response_uses_lifetime {
+        Some(&response_lifetime)
+    } else {
+        None
+    };
+
+    Ok(quote! {
+        impl<#response_lifetime, #maybe_command_lifetime> cdp::HasCdpResponse<#response_lifetime>
+                for #command_ty {
+            type Response = #response_ty;
+        }
+
+        impl<#command_lifetime, #maybe_response_lifetime> cdp::HasCdpCommand<#command_lifetime>
+                for #response_ty {
+            type Command = #command_ty;
+        }
+
+        impl<#maybe_response_lifetime> cdp::CdpCommand for #response_ty {
+            const COMMAND_NAME: &'static str = #method_name;
+        }
+    })
+}
+
+fn replace_response_lifetime(response: &Type, lifetime: &Lifetime) -> Result<(Type, bool), Error> {
+    let mut replaced_response = response.clone();
+    let mut replaced = false;
+    match replaced_response {
+        Type::Path(ref mut type_path) if type_path.qself.is_none() => {
+            if let Some(segment) = type_path.path.segments.last_mut() {
+                if let PathArguments::AngleBracketed(ref mut data) = segment.arguments {
+                    let mut lifetime_count = 0;
+                    for arg in &mut data.args {
+                        if let GenericArgument::Lifetime(ref mut response_lifetime) = *arg {
+                            lifetime_count += 1;
+                            if response_lifetime.ident != "static" {
+                                *response_lifetime = lifetime.clone();
+                                replaced = true;
+                            }
+                        }
+                    }
+                    if lifetime_count > 1 {
+                        return Err(Error::new_spanned(
+                            response,
+                            "the response type may only have one lifetime parameter",
+                        ));
+                    }
+                }
+            }
+        }
+        _ => return Err(Error::new_spanned(response, "the response type must be a struct")),
+    }
+    Ok((replaced_response, replaced))
+}
+
+fn generate_cdp_serialize_impl(input: &DeriveInput, kind: &str) -> Result<Tokens, Error> {
+    let DeriveInput {
+        ref ident,
+        ref generics,
+        ref data,
+        ..
+    } = *input;
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let variants = match *data {
+        Data::Enum(ref data) => &data.variants,
+        _ => return Err(Error::new(ident.span(), "expected an enum definition")),
+    };
+
+    let mut name_arms = Vec::new();
+    let mut params_arms = Vec::new();
+    let mut new_predicates = Vec::new();
+    let mut name_checks = Vec::new();
+    for (i, variant) in variants.iter().enumerate() {
+        generate_cdp_name_checks(kind, variant, &mut name_checks)?;
+        generate_cdp_serialize_impl_arms(
+            kind,
+            ident,
+            variant,
+            i + 1 == variants.len(),
+            &mut name_arms,
+            &mut params_arms,
+            &mut new_predicates,
+        )?;
+    }
+
+    let new_where_clause = generate_where_clause(where_clause, &new_predicates);
+    let name_checks_fn = generate_name_checks_fn(generics, &name_checks);
+
+    let trait_name = format_ident!("SerializeCdp{}", kind);
+    let name_fn_name = format_ident!("{}_name", kind.to_lowercase());
+    let serialize_params_fn_name = format_ident!("serialize_{}_params", kind.to_lowercase());
+
+    Ok(quote! {
+        #[allow(dead_code, non_upper_case_globals, unused_attributes, unused_qualifications)]
+        const _: () = {
+            extern crate cdp;
+            extern crate serde;
+
+            #name_checks_fn
+
+            impl #impl_generics cdp::#trait_name for #ident #ty_generics #new_where_clause {
+                fn #name_fn_name(&self) -> &str {
+                    match *self {
+                        #(#name_arms, )*
+                    }
+                }
+
+                fn #serialize_params_fn_name<S>(
+                    &self,
+                    serializer: S,
+                ) -> ::std::result::Result<S::Ok, S::Error>
+                where
+                    S: serde::Serializer
+                {
+                    match *self {
+                        #(#params_arms, )*
+                    }
+                }
+            }
+        };
+    })
+}
+
+fn generate_cdp_serialize_impl_arms(
+    kind: &str,
+    ident: &Ident,
+    variant: &Variant,
+    is_last: bool,
+    name_arms: &mut Vec<Tokens>,
+    params_arms: &mut Vec<Tokens>,
+    new_predicates: &mut Vec<Tokens>,
+) -> Result<(), Error> {
+    let variant_ident = &variant.ident;
+    let variant_fields: Vec<&Field> = variant.fields.iter().collect();
+    let ctor = quote! { #ident::#variant_ident };
+
+    let mut maybe_method_name = None;
+    let mut aliases = Vec::new();
+    let mut maybe_prefix = None;
+    extract_variant_attrs(
+        &variant.attrs,
+        &mut maybe_method_name,
+        &mut aliases,
+        &mut maybe_prefix,
+        &mut false,
+    )?;
+
+    if maybe_prefix.is_some() {
+        let (inner, pattern) = delegate_field(&ctor, variant)?;
+        let inner_ty = &inner.ty;
+        let serialize_trait = format_ident!("SerializeCdp{}", kind);
+        let name_fn_name = format_ident!("{}_name", kind.to_lowercase());
+        let serialize_params_fn_name = format_ident!("serialize_{}_params", kind.to_lowercase());
+
+        name_arms.push(quote! { #pattern => cdp::#serialize_trait::#name_fn_name(inner) });
+        params_arms.push(quote! {
+            #pattern => cdp::#serialize_trait::#serialize_params_fn_name(inner, serializer)
+        });
+
+        new_predicates.push(quote! { #inner_ty: cdp::#serialize_trait });
+
+        return Ok(());
+    }
+
+    match variant_fields.len() {
+        0 => {
+            let method_name = match maybe_method_name {
+                Some(method_name) => method_name,
+                None => return Err(missing_variant_name_error(variant, kind)),
+            };
+
+            name_arms.push(quote! { #ctor { .. } => #method_name });
+            params_arms.push(quote! {
+                #ctor { .. } => serde::Serialize::serialize(&cdp::Empty, serializer)
+            });
+
+            Ok(())
+        }
+        1 => {
+            let params = variant_fields[0];
+            let params_type = &params.ty;
+
+            let (name, prefix_bound) = match maybe_method_name {
+                Some(method_name) => (quote!(#method_name), None),
+                None => {
+                    let kind_trait = format_ident!("Cdp{}", kind);
+                    let name_const = format_ident!("{}_NAME", kind.to_uppercase());
+
+                    let name = quote!(<#params_type as cdp::#kind_trait>::#name_const);
+                    let prefix_bound = quote! { cdp::#kind_trait + };
+                    (name, Some(prefix_bound))
+                }
+            };
+
+            let pattern = match params.ident {
+                None => quote! { #ctor(ref params) },
+                Some(ref params_ident) => quote! { #ctor { #params_ident: ref params } },
+            };
+            name_arms.push(quote! { #ctor { .. } => #name });
+            params_arms.push(quote! {
+                #pattern => serde::Serialize::serialize(params, serializer)
+            });
+
+            new_predicates.push(quote! { #params_type: #prefix_bound serde::Serialize });
+
+            Ok(())
+        }
+        2 => {
+            if !is_last {
+                return Err(wildcard_not_last_error(variant, kind));
+            }
+
+            let name = variant_fields[0];
+            let name_ty = &name.ty;
+
+            let params = variant_fields[1];
+            let params_ty = &params.ty;
+
+            let field_idents = name.ident.as_ref().and_then(|name_ident| {
+                params
+                    .ident
+                    .as_ref()
+                    .map(|params_ident| (name_ident, params_ident))
+            });
+            let (name_pattern, params_pattern) = match field_idents {
+                None => (quote! { #ctor(ref name, _) }, quote! { #ctor(_, ref params) }),
+                Some((name_ident, params_ident)) => (
+                    quote! { #ctor { #name_ident: ref name, .. } },
+                    quote! { #ctor { #params_ident: ref params, .. } },
+                ),
+            };
+            name_arms.push(quote! {
+                #name_pattern => ::std::convert::AsRef::<str>::as_ref(name)
+            });
+            params_arms.push(quote! {
+                #params_pattern => serde::Serialize::serialize(params, serializer)
+            });
+
+            new_predicates.push(quote! { #name_ty: ::std::convert::AsRef<str> });
+            new_predicates.push(quote! { #params_ty: serde::Serialize });
+
+            Ok(())
+        }
+        n => Err(field_count_error(variant, n)),
+    }
+}
+
+fn generate_cdp_deserialize_impl(input: &DeriveInput, kind: &str) -> Result<Tokens, Error> {
+    let DeriveInput {
+        ref ident,
+        ref generics,
+        ref data,
         ..
     } = *input;
 
     let (quantification, ty_generics, where_clause, unique_lifetime_prefix) =
         generate_cdp_deserialize_impl_generics(generics);
-    let de_lifetime = Ident::from(format!("'{}de", unique_lifetime_prefix));
+    let de_lifetime = Lifetime::new(&format!("'{}de", unique_lifetime_prefix), Span::call_site());
 
-    let variants = match *body {
-        Body::Enum(ref variants) => variants,
-        _ => return Err("expected an enum definition".into()),
+    let variants = match *data {
+        Data::Enum(ref data) => &data.variants,
+        _ => return Err(Error::new(ident.span(), "expected an enum definition")),
     };
 
-    let mut match_arms = Vec::new();
-    let mut maybe_wildcard_arm = None;
+    let mut steps = Vec::new();
+    let mut borrowed_steps = Vec::new();
+    let mut maybe_wildcard = None;
     let mut new_predicates = Vec::new();
-    for variant in variants {
-        generate_cdp_deserialize_impl_arm(
+    let mut copy_predicates = Vec::new();
+    let mut name_checks = Vec::new();
+    for (i, variant) in variants.iter().enumerate() {
+        generate_cdp_name_checks(kind, variant, &mut name_checks)?;
+        generate_cdp_deserialize_impl_step(
             kind,
             ident,
             variant,
+            i + 1 == variants.len(),
             &unique_lifetime_prefix,
             &de_lifetime,
-            &mut match_arms,
-            &mut maybe_wildcard_arm,
+            &mut steps,
+            &mut borrowed_steps,
+            &mut maybe_wildcard,
             &mut new_predicates,
+            &mut copy_predicates,
         )?;
     }
 
-    let new_where_clause = if where_clause.predicates.is_empty() {
-        if new_predicates.is_empty() {
-            None
-        } else {
-            Some(quote! { where #(#new_predicates, )* })
-        }
-    } else {
-        Some(quote! { #where_clause, #(#new_predicates, )* })
-    };
+    let borrowed_where_clause = generate_where_clause(where_clause, &new_predicates);
+    new_predicates.extend(copy_predicates);
+    let new_where_clause = generate_where_clause(where_clause, &new_predicates);
+    let name_checks_fn = generate_name_checks_fn(generics, &name_checks);
 
-    let wildcard_arm = match maybe_wildcard_arm {
-        Some(wildcard_arm) => wildcard_arm,
-        None => quote! { _ => { Err(params) } },
+    let (maybe_wildcard, borrowed_wildcard) = match maybe_wildcard {
+        Some((maybe_wildcard, borrowed_wildcard)) => (maybe_wildcard, borrowed_wildcard),
+        None => (Some(quote! { Err(params) }), quote! { Err(params) }),
     };
 
-    let const_ident =
-        Ident::new(format!("_IMPL_DESERIALIZE_CDP_{}_FOR_{}", kind.to_uppercase(), ident));
-    let trait_name = Ident::from(format!("DeserializeCdp{}", kind));
-    let deserialize_fn_name = Ident::from(format!("deserialize_{}", kind.to_lowercase()));
-
-    Ok(quote! {
-        #[allow(non_upper_case_globals, unused_attributes, unused_qualifications)]
-        const #const_ident: () = {
-            extern crate cdp;
-            extern crate serde;
+    let trait_name = format_ident!("DeserializeCdp{}", kind);
+    let deserialize_fn_name = format_ident!("deserialize_{}", kind.to_lowercase());
+    let borrowed_trait_name = format_ident!("DeserializeBorrowedCdp{}", kind);
+    let deserialize_borrowed_fn_name =
+        format_ident!("deserialize_borrowed_{}", kind.to_lowercase());
 
+    // A wildcard that can only borrow its name would have to leave the names
+    // it's meant to match unrecognized if the name were copied, so its enum
+    // only implements the borrowed trait.
+    let maybe_copy_impl = maybe_wildcard.map(|wildcard| {
+        quote! {
             impl #quantification cdp::#trait_name <#de_lifetime>
                     for #ident #ty_generics #new_where_clause {
                 fn #deserialize_fn_name<D>(
@@ -105,100 +557,173 @@ fn generate_cdp_deserialize_impl(input: &DeriveInput, kind: &str) -> Result<Toke
                 where
                     D: serde::Deserializer<#de_lifetime>
                 {
-                    match name {
-                        #(#match_arms, )*
-                        #wildcard_arm,
-                    }
+                    #(#steps)*
+                    #wildcard
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        #[allow(dead_code, non_upper_case_globals, unused_attributes, unused_qualifications,
+                unused_variables)]
+        const _: () = {
+            extern crate cdp;
+            extern crate serde;
+
+            #name_checks_fn
+
+            impl #quantification cdp::#borrowed_trait_name <#de_lifetime>
+                    for #ident #ty_generics #borrowed_where_clause {
+                fn #deserialize_borrowed_fn_name<D>(
+                    name: &#de_lifetime str,
+                    params: D,
+                ) -> ::std::result::Result<::std::result::Result<Self, D::Error>, D>
+                where
+                    D: serde::Deserializer<#de_lifetime>
+                {
+                    #(#borrowed_steps)*
+                    #borrowed_wildcard
                 }
             }
+
+            #maybe_copy_impl
         };
     })
 }
 
 fn generate_cdp_deserialize_impl_generics(
     generics: &Generics,
-) -> (Tokens, TyGenerics, &WhereClause, String) {
+) -> (Tokens, TypeGenerics, Option<&WhereClause>, String) {
     let max_lifetime_len = generics
-        .lifetimes
-        .iter()
-        .map(|x| x.lifetime.ident.as_ref().len() - 1)
+        .lifetimes()
+        .map(|x| x.lifetime.ident.to_string().len())
         .max()
         .unwrap_or(0);
     let unique_lifetime_prefix = "_".repeat(max_lifetime_len);
 
     let mut quant_generics = generics.clone();
-    quant_generics
-        .lifetimes
-        .insert(0, LifetimeDef::new(format!("'{}de", unique_lifetime_prefix)));
+    quant_generics.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(Lifetime::new(
+            &format!("'{}de", unique_lifetime_prefix),
+            Span::call_site(),
+        ))),
+    );
     let (quantification, _, _) = quant_generics.split_for_impl();
 
     let (_, ty_generics, where_clause) = generics.split_for_impl();
     (quote!(#quantification), ty_generics, where_clause, unique_lifetime_prefix)
 }
 
+// Each variant becomes a step that returns if it matches the name; variants
+// that delegate to another type fall through to the next step if the other
+// type doesn't know the name either. The steps are generated twice, for
+// `deserialize_*` and `deserialize_borrowed_*`, which differ only in how
+// delegating variants call into the other type and how the wildcard variant
+// keeps the name. Predicates that only `deserialize_*` needs go in
+// `copy_predicates`.
 #[cfg_attr(feature = "clippy", allow(too_many_arguments))]
-fn generate_cdp_deserialize_impl_arm(
+fn generate_cdp_deserialize_impl_step(
     kind: &str,
     ident: &Ident,
     variant: &Variant,
+    is_last: bool,
     unique_lifetime_prefix: &str,
-    de_lifetime: &Ident,
-    match_arms: &mut Vec<Tokens>,
-    maybe_wildcard_arm: &mut Option<Tokens>,
+    de_lifetime: &Lifetime,
+    steps: &mut Vec<Tokens>,
+    borrowed_steps: &mut Vec<Tokens>,
+    maybe_wildcard: &mut Option<(Option<Tokens>, Tokens)>,
     new_predicates: &mut Vec<Tokens>,
-) -> Result<(), String> {
-    if maybe_wildcard_arm.is_some() {
-        return Err(format!(
-            "any 'wildcard' {} variant (with 2 fields) must come last in the enumeration",
-            kind.to_lowercase()
-        ));
-    }
-
+    copy_predicates: &mut Vec<Tokens>,
+) -> Result<(), Error> {
     let variant_ident = &variant.ident;
-    let variant_fields = variant.data.fields();
+    let variant_fields: Vec<&Field> = variant.fields.iter().collect();
     let ctor = quote! { #ident::#variant_ident };
 
     let mut maybe_method_name = None;
-    extract_method_name_from_attrs(&ctor, &variant.attrs, &mut maybe_method_name)?;
+    let mut aliases = Vec::new();
+    let mut maybe_prefix = None;
+    extract_variant_attrs(
+        &variant.attrs,
+        &mut maybe_method_name,
+        &mut aliases,
+        &mut maybe_prefix,
+        &mut false,
+    )?;
+
+    if let Some(prefix) = maybe_prefix {
+        let (inner, _) = delegate_field(&ctor, variant)?;
+        let inner_ty = &inner.ty;
+        let populate = match inner.ident {
+            None => quote! { #ctor },
+            Some(ref inner_ident) => quote! { |inner| { #ctor { #inner_ident: inner } } },
+        };
+        let trait_name = format_ident!("DeserializeCdp{}", kind);
+        let borrowed_trait_name = format_ident!("DeserializeBorrowedCdp{}", kind);
+        let deserialize_fns = [
+            (&trait_name, format_ident!("deserialize_{}", kind.to_lowercase())),
+            (&borrowed_trait_name, format_ident!("deserialize_borrowed_{}", kind.to_lowercase())),
+        ];
+
+        let mut delegate_steps = deserialize_fns.iter().map(|&(trait_name, ref fn_name)| {
+            quote! {
+                let params = if name.starts_with(#prefix) {
+                    match <#inner_ty as cdp::#trait_name<#de_lifetime>>::#fn_name(
+                        name,
+                        params,
+                    ) {
+                        Ok(result) => return Ok(result.map(#populate)),
+                        Err(params) => params,
+                    }
+                } else {
+                    params
+                };
+            }
+        });
+        steps.extend(delegate_steps.next());
+        borrowed_steps.extend(delegate_steps.next());
+
+        new_predicates.push(quote! { #inner_ty: cdp::#borrowed_trait_name<#de_lifetime> });
+        copy_predicates.push(quote! { #inner_ty: cdp::#trait_name<#de_lifetime> });
+
+        return Ok(());
+    }
 
     match variant_fields.len() {
         0 => {
             let method_name = match maybe_method_name {
                 Some(method_name) => method_name,
-                None => {
-                    return Err(format!(
-                        "unit variant `{}` is missing a #[cdp = \"...\"] attribute to specify \
-                         the {} name",
-                        ctor,
-                        kind.to_lowercase()
-                    ))
-                }
+                None => return Err(missing_variant_name_error(variant, kind)),
             };
 
-            let suffix = match variant.data {
-                VariantData::Struct(_) => Some(quote!({})),
-                VariantData::Tuple(_) => Some(quote!(())),
-                VariantData::Unit => None,
+            let suffix = match variant.fields {
+                Fields::Named(_) => Some(quote!({})),
+                Fields::Unnamed(_) => Some(quote!(())),
+                Fields::Unit => None,
             };
-            match_arms.push(quote! {
-                #method_name => {
-                    Ok(serde::Deserialize::deserialize(params).map(|cdp::Empty| {
-                        #ctor#suffix
-                    }))
+            let condition = generate_name_condition(&quote!(#method_name), &aliases);
+            let step = quote! {
+                if #condition {
+                    return Ok(serde::Deserialize::deserialize(params).map(|cdp::Empty| {
+                        #ctor #suffix
+                    }));
                 }
-            });
+            };
+            steps.push(step.clone());
+            borrowed_steps.push(step);
 
             Ok(())
         }
         1 => {
-            let params = &variant_fields[0];
+            let params = variant_fields[0];
             let params_type = &params.ty;
 
             let (pattern, prefix_bound) = match maybe_method_name {
                 Some(method_name) => (quote!(#method_name), None),
                 None => {
-                    let kind_trait = Ident::from(format!("Cdp{}", kind));
-                    let name_const = Ident::from(format!("{}_NAME", kind.to_uppercase()));
+                    let kind_trait = format_ident!("Cdp{}", kind);
+                    let name_const = format_ident!("{}_NAME", kind.to_uppercase());
 
                     let pattern = quote!(<#params_type as cdp::#kind_trait>::#name_const);
                     let prefix_bound = quote! { cdp::#kind_trait + };
@@ -212,9 +737,14 @@ fn generate_cdp_deserialize_impl_arm(
                     quote! { |params| { #ctor { #params_ident: params } } }
                 }
             };
-            match_arms.push(quote! {
-                #pattern => { Ok(serde::Deserialize::deserialize(params).map(#populate)) }
-            });
+            let condition = generate_name_condition(&pattern, &aliases);
+            let step = quote! {
+                if #condition {
+                    return Ok(serde::Deserialize::deserialize(params).map(#populate));
+                }
+            };
+            steps.push(step.clone());
+            borrowed_steps.push(step);
 
             new_predicates.push(quote! {
                 #params_type: #prefix_bound serde::Deserialize<#de_lifetime>
@@ -223,64 +753,598 @@ fn generate_cdp_deserialize_impl_arm(
             Ok(())
         }
         2 => {
-            let name = &variant_fields[0];
+            if !is_last {
+                return Err(wildcard_not_last_error(variant, kind));
+            }
+
+            let name = variant_fields[0];
             let name_ty = &name.ty;
 
-            let params = &variant_fields[1];
+            let params = variant_fields[1];
             let params_ty = &params.ty;
 
-            let convert_name = quote! { ::std::convert::From::from(name) };
+            // A name of type `&'a str` can only be kept by
+            // `deserialize_borrowed_*`, so there's no `deserialize_*`.
+            let name_lifetime = extract_name_lifetime(name_ty);
+            let (maybe_convert_name, convert_borrowed_name) = match name_lifetime {
+                BorrowedName::Str(name_lifetime) => {
+                    new_predicates.push(quote! { #de_lifetime: #name_lifetime });
+                    (None, quote! { name })
+                }
+                BorrowedName::Cow(name_lifetime) => {
+                    new_predicates.push(quote! { #de_lifetime: #name_lifetime });
+                    (
+                        Some(quote! { ::std::borrow::Cow::Owned(name.to_owned()) }),
+                        quote! { ::std::borrow::Cow::Borrowed(name) },
+                    )
+                }
+                BorrowedName::None => {
+                    let str_lifetime =
+                        Lifetime::new(&format!("'{}a", unique_lifetime_prefix), Span::call_site());
+                    new_predicates
+                        .push(quote! { #name_ty: for<#str_lifetime> From<&#str_lifetime str> });
+                    let convert_name = quote! { ::std::convert::From::from(name) };
+                    (Some(convert_name.clone()), convert_name)
+                }
+            };
+            new_predicates.push(quote! { #params_ty: serde::Deserialize<#de_lifetime> });
+
             let field_idents = name.ident.as_ref().and_then(|name_ident| {
                 params
                     .ident
                     .as_ref()
                     .map(|params_ident| (name_ident, params_ident))
             });
-            let populate = match field_idents {
-                None => quote! { |params| { #ctor(#convert_name, params) } },
-                Some((name_ident, params_ident)) => {
-                    quote! {
-                        |params| { #ctor { #name_ident: #convert_name, #params_ident: params } }
+            let generate_wildcard = |convert_name: Tokens| {
+                let populate = match field_idents {
+                    None => quote! { |params| { #ctor(#convert_name, params) } },
+                    Some((name_ident, params_ident)) => {
+                        quote! {
+                            |params| {
+                                #ctor { #name_ident: #convert_name, #params_ident: params }
+                            }
+                        }
                     }
+                };
+                quote! { Ok(serde::Deserialize::deserialize(params).map(#populate)) }
+            };
+            *maybe_wildcard = Some((
+                maybe_convert_name.map(&generate_wildcard),
+                generate_wildcard(convert_borrowed_name),
+            ));
+
+            Ok(())
+        }
+        n => Err(field_count_error(variant, n)),
+    }
+}
+
+enum BorrowedName<'a> {
+    Str(&'a Lifetime),
+    Cow(&'a Lifetime),
+    None,
+}
+
+// Recognizes wildcard names of type `&'a str` and `Cow<'a, str>`.
+fn extract_name_lifetime(name_ty: &Type) -> BorrowedName {
+    fn is_str(ty: &Type) -> bool {
+        match *ty {
+            Type::Path(ref path) => path.qself.is_none() && path.path.is_ident("str"),
+            _ => false,
+        }
+    }
+
+    match *name_ty {
+        Type::Reference(ref reference) if reference.mutability.is_none() => {
+            match reference.lifetime {
+                Some(ref lifetime) if is_str(&reference.elem) => BorrowedName::Str(lifetime),
+                _ => BorrowedName::None,
+            }
+        }
+        Type::Path(ref path) if path.qself.is_none() => {
+            let segment = match path.path.segments.last() {
+                Some(segment) if segment.ident == "Cow" => segment,
+                _ => return BorrowedName::None,
+            };
+            let args = match segment.arguments {
+                PathArguments::AngleBracketed(ref args) if args.args.len() == 2 => &args.args,
+                _ => return BorrowedName::None,
+            };
+            match (&args[0], &args[1]) {
+                (&GenericArgument::Lifetime(ref lifetime), &GenericArgument::Type(ref ty))
+                    if is_str(ty) =>
+                {
+                    BorrowedName::Cow(lifetime)
                 }
+                _ => BorrowedName::None,
+            }
+        }
+        _ => BorrowedName::None,
+    }
+}
+
+// The service trait has a handler method per command, named after its variant,
+// and a `dispatch` method that parses an incoming command into the enum, hands
+// it to its handler and returns a future of the frame. Unlike the other derives, the trait is declared
+// alongside the enum, so its paths start from the crate root and only need
+// `cdp` to be linked.
+fn generate_cdp_service(input: &DeriveInput) -> Result<Tokens, Error> {
+    let DeriveInput {
+        ref ident,
+        ref vis,
+        ref attrs,
+        ref generics,
+        ref data,
+    } = *input;
+
+    let variants = match *data {
+        Data::Enum(ref data) => &data.variants,
+        _ => return Err(Error::new(ident.span(), "expected an enum definition")),
+    };
+
+    let service = extract_service_name(ident, attrs)?;
+
+    let mut handlers = Vec::new();
+    let mut parse_steps = Vec::new();
+    let mut dispatch_arms = Vec::new();
+    let mut name_checks = Vec::new();
+    for variant in variants {
+        generate_cdp_name_checks("Command", variant, &mut name_checks)?;
+        generate_cdp_service_handler(
+            ident,
+            variant,
+            &mut handlers,
+            &mut parse_steps,
+            &mut dispatch_arms,
+        )?;
+    }
+
+    let name_checks_const = generate_name_checks_fn(generics, &name_checks).map(|name_checks_fn| {
+        quote! {
+            #[allow(dead_code)]
+            const _: () = {
+                extern crate cdp;
+
+                #name_checks_fn
             };
-            mem::replace(
-                maybe_wildcard_arm,
-                Some(quote! {
-                    _ => { Ok(serde::Deserialize::deserialize(params).map(#populate)) }
-                }),
-            );
+        }
+    });
 
-            let str_lifetime = Ident::from(format!("'{}a", unique_lifetime_prefix));
-            new_predicates.push(quote! { #name_ty: for<#str_lifetime> From<&#str_lifetime str> });
-            new_predicates.push(quote! { #params_ty: serde::Deserialize<#de_lifetime> });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
+    Ok(quote! {
+        #vis trait #service #impl_generics #where_clause {
+            #(#handlers)*
+
+            fn dispatch(&mut self, incoming: ::cdp::CdpIncoming) -> ::cdp::server::FrameFuture {
+                let ::cdp::CdpIncoming {
+                    id,
+                    command_name,
+                    command_params: params,
+                } = incoming;
+                let name: &str = &command_name;
+                let command: ::std::result::Result<#ident #ty_generics, ::cdp::CdpError<'static>> =
+                    #(#parse_steps else)* {
+                        let error = ::cdp::CdpError::method_not_found(name);
+                        let frame = ::cdp::server::error_frame(Some(id), &error);
+                        return ::cdp::server::ready_frame(frame);
+                    };
+                match command {
+                    #(#dispatch_arms, )*
+                    Err(error) => {
+                        ::cdp::server::ready_frame(::cdp::server::error_frame(Some(id), &error))
+                    }
+                }
+            }
+        }
+
+        #name_checks_const
+    })
+}
+
+fn generate_cdp_service_handler(
+    ident: &Ident,
+    variant: &Variant,
+    handlers: &mut Vec<Tokens>,
+    parse_steps: &mut Vec<Tokens>,
+    dispatch_arms: &mut Vec<Tokens>,
+) -> Result<(), Error> {
+    let mut maybe_method_name = None;
+    let mut aliases = Vec::new();
+    let mut maybe_prefix = None;
+    extract_variant_attrs(
+        &variant.attrs,
+        &mut maybe_method_name,
+        &mut aliases,
+        &mut maybe_prefix,
+        &mut false,
+    )?;
+
+    if maybe_prefix.is_some() {
+        return Err(Error::new(
+            variant.ident.span(),
+            "delegating variants aren't supported by `CdpService`",
+        ));
+    }
+
+    let params = match variant.fields.iter().next() {
+        Some(params) if variant.fields.len() == 1 => params,
+        _ => {
+            return Err(Error::new(
+                variant.ident.span(),
+                format!(
+                    "expected 1 field on `{}`, holding the command, but found {}",
+                    variant.ident,
+                    variant.fields.len()
+                ),
+            ))
+        }
+    };
+    let params_type = &params.ty;
+
+    let variant_ident = &variant.ident;
+    let ctor = quote! { #ident::#variant_ident };
+    let handler = Ident::new(&snake_case(&variant_ident.to_string()), variant_ident.span());
+
+    handlers.push(quote! {
+        fn #handler(
+            &mut self,
+            params: #params_type,
+        ) -> ::cdp::server::HandlerFuture<
+            <#params_type as ::cdp::HasCdpResponse<'static>>::Response,
+        >;
+    });
+
+    let primary = match maybe_method_name {
+        Some(method_name) => quote!(#method_name),
+        None => quote!(<#params_type as ::cdp::CdpCommand>::COMMAND_NAME),
+    };
+    let condition = generate_name_condition(&primary, &aliases);
+    let (populate, pattern) = match params.ident {
+        None => (quote! { #ctor }, quote! { #ctor(params) }),
+        Some(ref params_ident) => (
+            quote! { |params| { #ctor { #params_ident: params } } },
+            quote! { #ctor { #params_ident: params } },
+        ),
+    };
+    parse_steps.push(quote! {
+        if #condition {
+            ::cdp::server::parse_params::<#params_type>(params).map(#populate)
+        }
+    });
+
+    dispatch_arms.push(quote! {
+        Ok(#pattern) => ::cdp::server::handler_frame(id, self.#handler(params))
+    });
+
+    Ok(())
+}
+
+fn extract_service_name(ident: &Ident, attrs: &[Attribute]) -> Result<Ident, Error> {
+    let form_error = "`cdp` attribute must be used in #[cdp(service = \"...\")] form";
+
+    let mut maybe_service = None;
+    for attr in attrs {
+        if !attr.path().is_ident("cdp") {
+            continue;
+        }
+        if let Meta::List(_) = attr.meta {
+        } else {
+            return Err(Error::new_spanned(attr, form_error));
+        }
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("service") {
+                return Err(meta.error(form_error));
+            }
+            let service = meta.value()?.parse::<LitStr>()?.parse()?;
+            if mem::replace(&mut maybe_service, Some(service)).is_some() {
+                return Err(meta.error("multiple `service` values in `cdp` attributes"));
+            }
             Ok(())
+        })?;
+    }
+
+    Ok(maybe_service.unwrap_or_else(|| format_ident!("{}Service", ident)))
+}
+
+// "PageNavigate" becomes "page_navigate", and "DOMEnable" becomes
+// "dom_enable".
+fn snake_case(src: &str) -> String {
+    let chars: Vec<char> = src.chars().collect();
+    let mut out = String::new();
+    for (i, &ch) in chars.iter().enumerate() {
+        if ch.is_uppercase() {
+            if i > 0 && chars[i - 1] != '_' {
+                let prev_upper = chars[i - 1].is_uppercase();
+                let next_lower = i + 1 < chars.len() && chars[i + 1].is_lowercase();
+                if !prev_upper || next_lower {
+                    out.push('_');
+                }
+            }
+            out.extend(ch.to_lowercase());
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+fn generate_name_condition(primary: &Tokens, aliases: &[LitStr]) -> Tokens {
+    quote! { name == #primary #(|| name == #aliases)* }
+}
+
+// Explicit names and aliases are checked against the methods that the `cdp`
+// crate knows about, unless they're marked `unchecked` (for methods newer than
+// the `cdp` crate's protocol). Only the primary name is checked against the
+// payload type, since an alias may be registered with a struct of its own.
+fn generate_cdp_name_checks(
+    kind: &str,
+    variant: &Variant,
+    name_checks: &mut Vec<Tokens>,
+) -> Result<(), Error> {
+    let mut maybe_method_name = None;
+    let mut aliases = Vec::new();
+    let mut maybe_prefix = None;
+    let mut unchecked = false;
+    extract_variant_attrs(
+        &variant.attrs,
+        &mut maybe_method_name,
+        &mut aliases,
+        &mut maybe_prefix,
+        &mut unchecked,
+    )?;
+
+    if maybe_prefix.is_some() || unchecked || variant.fields.len() > 1 {
+        return Ok(());
+    }
+    let maybe_payload = variant.fields.iter().next().map(|field| &field.ty);
+
+    if let Some(ref method_name) = maybe_method_name {
+        name_checks.extend(generate_name_check(kind, method_name, maybe_payload));
+    }
+    for alias in &aliases {
+        name_checks.extend(generate_name_check(kind, alias, None));
+    }
+    Ok(())
+}
+
+// Everything in the check is spanned to the name, so that errors from inside
+// the `cdp` crate's macro point at it.
+fn generate_name_check(
+    kind: &str,
+    method_name: &LitStr,
+    maybe_payload: Option<&Type>,
+) -> Option<Tokens> {
+    let span = method_name.span();
+    let method_name_value = method_name.value();
+    let dot = method_name_value.find('.')?;
+    let domain = LitStr::new(&method_name_value[..dot], span);
+    let method = LitStr::new(&method_name_value[dot + 1..], span);
+    let check_macro = Ident::new(&format!("__cdp_check_{}", kind.to_lowercase()), span);
+    let payload = maybe_payload.map(|payload| quote! { , #payload });
+    Some(quote_spanned! {span=>
+        cdp::#check_macro!(#domain, #method #payload);
+    })
+}
+
+// The checks go in a function of their own so that payload types can refer to
+// the enum's generics.
+fn generate_name_checks_fn(generics: &Generics, name_checks: &[Tokens]) -> Option<Tokens> {
+    if name_checks.is_empty() {
+        return None;
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    Some(quote! {
+        fn check_method_names #impl_generics () #where_clause {
+            #(#name_checks)*
         }
-        n => Err(format!("expected 0, 1, or 2 fields on {}, but found {}", ctor, n)),
+    })
+}
+
+fn generate_where_clause(
+    where_clause: Option<&WhereClause>,
+    new_predicates: &[Tokens],
+) -> Option<Tokens> {
+    let predicates: Vec<_> = where_clause
+        .into_iter()
+        .flat_map(|where_clause| where_clause.predicates.iter())
+        .collect();
+    if predicates.is_empty() && new_predicates.is_empty() {
+        None
+    } else {
+        Some(quote! { where #(#predicates, )* #(#new_predicates, )* })
     }
 }
 
-fn extract_method_name_from_attrs(
-    target: &Tokens,
+// The response type can be given either directly, as in
+// #[cdp(response = Foo<'a>)], or as a string, #[cdp(response = "Foo<'a>")].
+fn extract_method_attrs_from_struct(
+    ident: &Ident,
     attrs: &[Attribute],
-    maybe_method_name: &mut Option<String>,
-) -> Result<(), String> {
+    kind: &str,
+) -> Result<(String, Option<Type>), Error> {
+    let form_error = format!(
+        "`cdp` attribute must be used in #[cdp(name = \"...\"{})] form",
+        if kind == "Command" { ", response = ..." } else { "" }
+    );
+
+    let mut maybe_method_name = None;
+    let mut maybe_response = None;
     for attr in attrs {
-        match attr.value {
-            MetaItem::NameValue(ref ident, Lit::Str(ref text, _)) => if ident.as_ref() == "cdp" {
-                if maybe_method_name.is_none() {
-                    mem::replace(maybe_method_name, Some(text.clone()));
+        if !attr.path().is_ident("cdp") {
+            continue;
+        }
+        if let Meta::List(_) = attr.meta {
+        } else {
+            return Err(Error::new_spanned(attr, &form_error));
+        }
+        attr.parse_nested_meta(|meta| {
+            let (key, duplicate) = if meta.path.is_ident("name") {
+                let value: LitStr = meta.value()?.parse()?;
+                ("name", mem::replace(&mut maybe_method_name, Some(value.value())).is_some())
+            } else if meta.path.is_ident("response") && kind == "Command" {
+                let value = meta.value()?;
+                let response: Type = if value.peek(LitStr) {
+                    value.parse::<LitStr>()?.parse()?
                 } else {
-                    return Err(format!("multiple `cdp` attributes attached to `{}`", target));
+                    value.parse()?
+                };
+                ("response", mem::replace(&mut maybe_response, Some(response)).is_some())
+            } else {
+                return Err(meta.error(&form_error));
+            };
+            if duplicate {
+                return Err(meta.error(format!("multiple `{}` values in `cdp` attributes", key)));
+            }
+            Ok(())
+        })?;
+    }
+
+    match maybe_method_name {
+        Some(method_name) => Ok((method_name, maybe_response)),
+        None => Err(Error::new(
+            ident.span(),
+            format!(
+                "`{}` is missing a #[cdp(name = \"...\")] attribute to specify the {} name",
+                ident,
+                kind.to_lowercase()
+            ),
+        )),
+    }
+}
+
+// A variant is selected either by its names, #[cdp = "..."] or
+// #[cdp(name = "...", alias = "...")], or by a prefix that it delegates on,
+// #[cdp(domain = "...")] (short for the prefix "Domain.") or
+// #[cdp(prefix = "...")]. A variant with one field takes its name from the
+// field's type unless it's given, so it may also have only aliases. Names
+// that are given can be marked #[cdp(name = "...", unchecked)].
+fn extract_variant_attrs(
+    attrs: &[Attribute],
+    maybe_method_name: &mut Option<LitStr>,
+    aliases: &mut Vec<LitStr>,
+    maybe_prefix: &mut Option<String>,
+    unchecked: &mut bool,
+) -> Result<(), Error> {
+    let form_error = "`cdp` attribute must be used in #[cdp = \"...\"], \
+                      #[cdp(name = \"...\", alias = \"...\", unchecked)], \
+                      #[cdp(domain = \"...\")] or #[cdp(prefix = \"...\")] form";
+
+    for attr in attrs {
+        if !attr.path().is_ident("cdp") {
+            continue;
+        }
+        if maybe_method_name.is_some() || !aliases.is_empty() || maybe_prefix.is_some() {
+            return Err(Error::new_spanned(attr, "multiple `cdp` attributes attached to variant"));
+        }
+        match attr.meta {
+            Meta::NameValue(ref name_value) => match name_value.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(ref text),
+                    ..
+                }) => {
+                    *maybe_method_name = Some(text.clone());
                 }
+                _ => return Err(Error::new_spanned(&name_value.value, form_error)),
             },
-            MetaItem::Word(ref ident) |
-            MetaItem::List(ref ident, _) |
-            MetaItem::NameValue(ref ident, _) => if ident.as_ref() == "cdp" {
-                return Err("`cdp` attribute must be used in #[cdp = \"...\"] form".into());
-            },
+            Meta::List(_) => {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("name") {
+                        let method_name = meta.value()?.parse()?;
+                        if mem::replace(maybe_method_name, Some(method_name)).is_some() {
+                            return Err(meta.error("multiple names in `cdp` attribute"));
+                        }
+                    } else if meta.path.is_ident("alias") {
+                        aliases.push(meta.value()?.parse()?);
+                    } else if meta.path.is_ident("unchecked") {
+                        *unchecked = true;
+                    } else {
+                        let prefix = if meta.path.is_ident("domain") {
+                            format!("{}.", meta.value()?.parse::<LitStr>()?.value())
+                        } else if meta.path.is_ident("prefix") {
+                            meta.value()?.parse::<LitStr>()?.value()
+                        } else {
+                            return Err(meta.error(form_error));
+                        };
+                        if mem::replace(maybe_prefix, Some(prefix)).is_some() {
+                            return Err(meta.error("multiple prefixes in `cdp` attribute"));
+                        }
+                    }
+                    if maybe_prefix.is_some()
+                        && (maybe_method_name.is_some() || !aliases.is_empty())
+                    {
+                        return Err(meta.error(
+                            "a delegating variant can't also have a `name` or an `alias`",
+                        ));
+                    }
+                    Ok(())
+                })?;
+                if maybe_method_name.is_none() && aliases.is_empty() && maybe_prefix.is_none() {
+                    return Err(Error::new_spanned(attr, form_error));
+                }
+                if *unchecked && maybe_method_name.is_none() && aliases.is_empty() {
+                    return Err(Error::new_spanned(
+                        attr,
+                        "`unchecked` needs a `name` or an `alias` to apply to",
+                    ));
+                }
+            }
+            Meta::Path(_) => return Err(Error::new_spanned(attr, form_error)),
         }
     }
     Ok(())
 }
+
+fn missing_variant_name_error(variant: &Variant, kind: &str) -> Error {
+    Error::new(
+        variant.ident.span(),
+        format!(
+            "unit variant `{}` is missing a #[cdp = \"...\"] or #[cdp(name = \"...\")] \
+             attribute to specify the {} name",
+            variant.ident,
+            kind.to_lowercase()
+        ),
+    )
+}
+
+fn wildcard_not_last_error(variant: &Variant, kind: &str) -> Error {
+    Error::new(
+        variant.ident.span(),
+        format!(
+            "any 'wildcard' {} variant (with 2 fields) must come last in the enumeration",
+            kind.to_lowercase()
+        ),
+    )
+}
+
+fn field_count_error(variant: &Variant, n: usize) -> Error {
+    Error::new_spanned(
+        &variant.fields,
+        format!("expected 0, 1, or 2 fields on `{}`, but found {}", variant.ident, n),
+    )
+}
+
+// Delegation variants wrap a single field: another enum (or struct) deriving
+// the same trait.
+fn delegate_field<'a>(ctor: &Tokens, variant: &'a Variant) -> Result<(&'a Field, Tokens), Error> {
+    let field = match variant.fields.iter().next() {
+        Some(field) if variant.fields.len() == 1 => field,
+        _ => {
+            let msg = format!(
+                "expected 1 field on delegating variant `{}`, but found {}",
+                variant.ident,
+                variant.fields.len()
+            );
+            return Err(match variant.fields {
+                Fields::Unit => Error::new(variant.ident.span(), msg),
+                _ => Error::new_spanned(&variant.fields, msg),
+            });
+        }
+    };
+
+    let pattern = match field.ident {
+        None => quote! { #ctor(ref inner) },
+        Some(ref field_ident) => quote! { #ctor { #field_ident: ref inner } },
+    };
+    Ok((field, pattern))
+}
@@ -0,0 +1,86 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+#[macro_use]
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{page, DeserializeCdpCommand, SerializeCdpCommand};
+
+#[derive(DeserializeCdpCommand, SerializeCdpCommand, Clone, Debug, PartialEq)]
+enum Foo<'a> {
+    #[cdp(name = "Page.navigate", alias = "Legacy.navigate", alias = "Legacy.goTo")]
+    PageNavigate(page::NavigateCommand<'a>),
+    #[cdp(alias = "Legacy.captureScreenshot")]
+    PageCaptureScreenshot(page::CaptureScreenshotCommand),
+    #[cdp(name = "Page.enable", alias = "Page.disable")]
+    PageEnable,
+    #[cdp(name = "Page.frobnicate", unchecked)]
+    PageFrobnicate(serde_json::Value),
+    #[cdp(alias = "Page.reloadHard", unchecked)]
+    PageReload(page::ReloadCommand<'a>),
+}
+
+fn deserialize<'a>(name: &str, params: &'a str) -> Option<Foo<'a>> {
+    let mut deserializer = serde_json::Deserializer::from_str(params);
+    let result = Foo::deserialize_command(name, &mut deserializer);
+    result.ok().and_then(Result::ok)
+}
+
+#[test]
+fn test() {
+    let json_navigate = r#"{"url":"https://www.mozilla.org"}"#;
+    let rust_navigate = Foo::PageNavigate(page::NavigateCommand {
+        url: "https://www.mozilla.org".into(),
+        referrer: None,
+        transition_type: None,
+    });
+    assert_eq!(deserialize("Page.navigate", json_navigate), Some(rust_navigate.clone()));
+    assert_eq!(deserialize("Legacy.navigate", json_navigate), Some(rust_navigate.clone()));
+    assert_eq!(deserialize("Legacy.goTo", json_navigate), Some(rust_navigate.clone()));
+    assert_eq!(rust_navigate.command_name(), "Page.navigate");
+
+    let json_capture_screenshot = r#"{"format":"png"}"#;
+    let rust_capture_screenshot = Foo::PageCaptureScreenshot(page::CaptureScreenshotCommand {
+        format: Some(page::CaptureScreenshotCommandFormat::Png),
+        quality: None,
+        from_surface: None,
+        clip: None,
+    });
+    assert_eq!(
+        deserialize("Page.captureScreenshot", json_capture_screenshot),
+        Some(rust_capture_screenshot.clone())
+    );
+    assert_eq!(
+        deserialize("Legacy.captureScreenshot", json_capture_screenshot),
+        Some(rust_capture_screenshot.clone())
+    );
+    assert_eq!(rust_capture_screenshot.command_name(), "Page.captureScreenshot");
+
+    assert_eq!(deserialize("Page.enable", "{}"), Some(Foo::PageEnable));
+    assert_eq!(deserialize("Page.disable", "{}"), Some(Foo::PageEnable));
+    assert_eq!(Foo::PageEnable.command_name(), "Page.enable");
+
+    let rust_frobnicate = Foo::PageFrobnicate(json!({"how": "gently"}));
+    assert_eq!(
+        deserialize("Page.frobnicate", r#"{"how":"gently"}"#),
+        Some(rust_frobnicate.clone())
+    );
+    assert_eq!(rust_frobnicate.command_name(), "Page.frobnicate");
+
+    let rust_reload = Foo::PageReload(page::ReloadCommand {
+        ignore_cache: Some(true),
+        script_to_evaluate_on_load: None,
+    });
+    assert_eq!(deserialize("Page.reloadHard", r#"{"ignoreCache":true}"#), Some(rust_reload));
+
+    assert_eq!(deserialize("Legacy.reload", "{}"), None);
+}
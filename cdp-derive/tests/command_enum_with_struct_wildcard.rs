@@ -25,7 +25,7 @@ enum Foo<'a> {
     #[cdp = "Page.enable"]
     PageEnable,
     #[cdp = "Page.disable"]
-    PageDisable(page::EnableCommand),
+    PageDisable(cdp::Empty),
     Other { name: String, params: Value },
 }
 
@@ -78,7 +78,7 @@ fn test() {
     {
         let mut deserializer = serde_json::Deserializer::from_str("{}");
         let result = Foo::deserialize_command("Page.disable", &mut deserializer);
-        assert_eq!(result.ok().map(Result::ok), Some(Some(Foo::PageDisable(page::EnableCommand))));
+        assert_eq!(result.ok().map(Result::ok), Some(Some(Foo::PageDisable(cdp::Empty))));
     }
 
     {
@@ -0,0 +1,106 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{CdpCommand, DeserializeCdpCommand, HasCdpCommand, HasCdpResponse, SerializeCdpCommand};
+use std::borrow::Cow;
+
+#[derive(CdpCommand, Serialize, Deserialize, Debug, PartialEq)]
+#[cdp(name = "MyDomain.doThing", response = "DoThingResponse<'a>")]
+struct DoThingCommand<'a> {
+    #[serde(borrow)]
+    what: Cow<'a, str>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct DoThingResponse<'a> {
+    #[serde(borrow)]
+    result: Cow<'a, str>,
+}
+
+#[derive(CdpCommand, Serialize, Deserialize, Debug, PartialEq)]
+#[cdp(name = "MyDomain.count")]
+#[cdp(response = "CountResponse")]
+struct CountCommand {
+    max: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct CountResponse {
+    count: u32,
+}
+
+#[derive(CdpCommand, Serialize, Deserialize, Debug, PartialEq)]
+#[cdp(name = "MyDomain.reset")]
+struct ResetCommand {}
+
+#[derive(DeserializeCdpCommand, SerializeCdpCommand, Debug, PartialEq)]
+enum MyDomainCommand {
+    Count(CountCommand),
+    Reset(ResetCommand),
+}
+
+fn assert_relation<'a, 'b, C, R>()
+where
+    C: CdpCommand + HasCdpResponse<'b, Response = R>,
+    R: CdpCommand + HasCdpCommand<'a, Command = C>,
+{
+    assert_eq!(C::COMMAND_NAME, R::COMMAND_NAME);
+}
+
+#[test]
+fn test() {
+    assert_eq!(DoThingCommand::COMMAND_NAME, "MyDomain.doThing");
+    assert_eq!(CountCommand::COMMAND_NAME, "MyDomain.count");
+    assert_eq!(ResetCommand::COMMAND_NAME, "MyDomain.reset");
+    assert_relation::<DoThingCommand, DoThingResponse>();
+    assert_relation::<CountCommand, CountResponse>();
+
+    let rust_do_thing = DoThingCommand {
+        what: "stuff".into(),
+    };
+    assert_eq!(rust_do_thing.command_name(), "MyDomain.doThing");
+    let mut out = Vec::new();
+    rust_do_thing
+        .serialize_command_params(&mut serde_json::Serializer::new(&mut out))
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), r#"{"what":"stuff"}"#);
+
+    {
+        let mut deserializer = serde_json::Deserializer::from_str(r#"{"what":"stuff"}"#);
+        let result = DoThingCommand::deserialize_command("MyDomain.doThing", &mut deserializer);
+        assert_eq!(result.ok().map(Result::ok), Some(Some(rust_do_thing)));
+    }
+
+    {
+        let mut deserializer = serde_json::Deserializer::from_str(r#"{"max":3}"#);
+        let result = CountCommand::deserialize_command("MyDomain.doThing", &mut deserializer);
+        assert!(result.is_err());
+    }
+
+    {
+        let mut deserializer = serde_json::Deserializer::from_str(r#"{"max":3}"#);
+        let result = MyDomainCommand::deserialize_command("MyDomain.count", &mut deserializer);
+        let rust_count = MyDomainCommand::Count(CountCommand { max: 3 });
+        assert_eq!(result.ok().map(Result::ok), Some(Some(rust_count)));
+    }
+
+    {
+        let mut deserializer = serde_json::Deserializer::from_str("{}");
+        let result = MyDomainCommand::deserialize_command("MyDomain.reset", &mut deserializer);
+        let rust_reset = MyDomainCommand::Reset(ResetCommand {});
+        assert_eq!(result.ok().map(Result::ok), Some(Some(rust_reset)));
+    }
+}
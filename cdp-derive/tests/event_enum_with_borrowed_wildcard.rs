@@ -0,0 +1,118 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{page, DeserializeBorrowedCdpEvent, DeserializeCdpEvent};
+use serde_json::Value;
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+
+#[derive(DeserializeCdpEvent, Debug)]
+enum Proxied<'a> {
+    PageDomContentEventFired(page::DomContentEventFiredEvent),
+    #[cdp(domain = "Runtime")]
+    Runtime(RuntimeEvent<'a>),
+    Other(&'a str, &'a RawValue),
+}
+
+#[derive(DeserializeCdpEvent, Debug)]
+enum RuntimeEvent<'a> {
+    Other(&'a str, &'a RawValue),
+}
+
+#[derive(DeserializeCdpEvent, Debug, PartialEq)]
+enum Logged<'a> {
+    PageLoadEventFired(page::LoadEventFiredEvent),
+    Other { name: Cow<'a, str>, params: Value },
+}
+
+#[derive(Deserialize)]
+struct Frame<'a> {
+    method: &'a str,
+    #[serde(borrow)]
+    params: &'a RawValue,
+}
+
+fn points_into(outer: &str, inner: &str) -> bool {
+    let start = outer.as_ptr() as usize;
+    let ptr = inner.as_ptr() as usize;
+    ptr >= start && ptr < start + outer.len()
+}
+
+#[test]
+fn test_str_name() {
+    let json = r#"{"method":"Page.domContentEventFired","params":{"timestamp":12.7}}"#;
+    let frame: Frame = serde_json::from_str(json).expect("parse error");
+    match Proxied::deserialize_borrowed_event(frame.method, frame.params) {
+        Ok(Ok(Proxied::PageDomContentEventFired(event))) => assert_eq!(12.7, event.timestamp),
+        other => panic!("expected a domContentEventFired event: {:?}", other),
+    }
+
+    let json = r#"{"method":"Network.dataReceived","params":{"requestId":"7","dataLength":12}}"#;
+    let frame: Frame = serde_json::from_str(json).expect("parse error");
+    match Proxied::deserialize_borrowed_event(frame.method, frame.params) {
+        Ok(Ok(Proxied::Other(name, params))) => {
+            assert_eq!("Network.dataReceived", name);
+            assert_eq!(r#"{"requestId":"7","dataLength":12}"#, params.get());
+            assert!(points_into(json, name));
+            assert!(points_into(json, params.get()));
+        }
+        other => panic!("expected a wildcard event: {:?}", other),
+    }
+
+    let json = r#"{"method":"Runtime.executionContextsCleared","params":{}}"#;
+    let frame: Frame = serde_json::from_str(json).expect("parse error");
+    match Proxied::deserialize_borrowed_event(frame.method, frame.params) {
+        Ok(Ok(Proxied::Runtime(RuntimeEvent::Other(name, params)))) => {
+            assert!(points_into(json, name));
+            assert_eq!("{}", params.get());
+        }
+        other => panic!("expected a delegated wildcard event: {:?}", other),
+    }
+}
+
+#[test]
+fn test_cow_name() {
+    let json = r#"{"method":"Network.dataReceived","params":{"requestId":"7","dataLength":12}}"#;
+    let frame: Frame = serde_json::from_str(json).expect("parse error");
+
+    match Logged::deserialize_borrowed_event(frame.method, frame.params) {
+        Ok(Ok(Logged::Other {
+            name: Cow::Borrowed(name),
+            params,
+        })) => {
+            assert_eq!("Network.dataReceived", name);
+            assert_eq!(Value::from("7"), params["requestId"]);
+        }
+        other => panic!("expected a borrowed wildcard event: {:?}", other),
+    }
+
+    let name = frame.method.to_string();
+    match Logged::deserialize_event(&name, frame.params) {
+        Ok(Ok(Logged::Other {
+            name: Cow::Owned(name),
+            ..
+        })) => assert_eq!("Network.dataReceived", name),
+        other => panic!("expected an owned wildcard event: {:?}", other),
+    }
+
+    let json = r#"{"timestamp":12.7}"#;
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let result = Logged::deserialize_borrowed_event("Page.loadEventFired", &mut deserializer);
+    assert_eq!(
+        Some(Logged::PageLoadEventFired(page::LoadEventFiredEvent { timestamp: 12.7 })),
+        result.ok().and_then(Result::ok)
+    );
+}
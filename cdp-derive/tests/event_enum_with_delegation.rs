@@ -0,0 +1,85 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{page, DeserializeCdpEvent, SerializeCdpEvent};
+use serde_json::{Map, Value};
+
+#[derive(DeserializeCdpEvent, SerializeCdpEvent, Debug, PartialEq)]
+enum PageEvent {
+    DomContentEventFired(page::DomContentEventFiredEvent),
+    LoadEventFired(page::LoadEventFiredEvent),
+}
+
+#[derive(DeserializeCdpEvent, SerializeCdpEvent, Debug, PartialEq)]
+enum Foo {
+    #[cdp(domain = "Page")]
+    Page(PageEvent),
+    #[cdp = "Page.interstitialHidden"]
+    PageInterstitialHidden,
+    #[cdp(prefix = "Quux.z")]
+    QuuxZ {
+        event: (String, Map<String, Value>),
+    },
+    Other(String, Value),
+}
+
+fn deserialize(name: &str, params: &str) -> Option<Foo> {
+    let mut deserializer = serde_json::Deserializer::from_str(params);
+    let result = Foo::deserialize_event(name, &mut deserializer);
+    result.ok().and_then(Result::ok)
+}
+
+fn serialize_params(event: &Foo) -> String {
+    let mut out = Vec::new();
+    event
+        .serialize_event_params(&mut serde_json::Serializer::new(&mut out))
+        .expect("serialize error");
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test() {
+    let json_load_event = r#"{"timestamp":12.7}"#;
+    let rust_load_event =
+        Foo::Page(PageEvent::LoadEventFired(page::LoadEventFiredEvent { timestamp: 12.7 }));
+    assert_eq!(deserialize("Page.loadEventFired", json_load_event), Some(rust_load_event));
+
+    // Names the inner enum doesn't know fall through to the later variants.
+    assert_eq!(deserialize("Page.interstitialHidden", "{}"), Some(Foo::PageInterstitialHidden));
+    assert_eq!(
+        deserialize("Page.frameResized", "{}"),
+        Some(Foo::Other("Page.frameResized".into(), Value::Object(Map::new())))
+    );
+
+    let rust_zot = Foo::QuuxZ {
+        event: ("Quux.zot".into(), Map::new()),
+    };
+    assert_eq!(deserialize("Quux.zot", "{}"), Some(rust_zot));
+    assert_eq!(
+        deserialize("Quux.blah", "{}"),
+        Some(Foo::Other("Quux.blah".into(), Value::Object(Map::new())))
+    );
+
+    let rust_dom_event = Foo::Page(PageEvent::DomContentEventFired(
+        page::DomContentEventFiredEvent { timestamp: 12.7 },
+    ));
+    assert_eq!(rust_dom_event.event_name(), "Page.domContentEventFired");
+    assert_eq!(serialize_params(&rust_dom_event), json_load_event);
+
+    let rust_zot = Foo::QuuxZ {
+        event: ("Quux.zot".into(), Map::new()),
+    };
+    assert_eq!(rust_zot.event_name(), "Quux.zot");
+    assert_eq!(serialize_params(&rust_zot), "{}");
+}
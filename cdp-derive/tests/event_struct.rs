@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{CdpEvent, DeserializeCdpEvent, SerializeCdpEvent};
+use serde_json::Value;
+
+#[derive(CdpEvent, Serialize, Deserialize, Debug, PartialEq)]
+#[cdp(name = "MyDomain.thingDone")]
+struct ThingDoneEvent<T> {
+    id: u32,
+    detail: T,
+}
+
+#[derive(DeserializeCdpEvent, SerializeCdpEvent, Debug, PartialEq)]
+enum MyDomainEvent {
+    ThingDone(ThingDoneEvent<Value>),
+    Other(String, Value),
+}
+
+#[test]
+fn test() {
+    assert_eq!(ThingDoneEvent::<Value>::EVENT_NAME, "MyDomain.thingDone");
+
+    let json_thing_done = r#"{"id":7,"detail":"ok"}"#;
+    let rust_thing_done = ThingDoneEvent {
+        id: 7,
+        detail: Value::String("ok".into()),
+    };
+    assert_eq!(rust_thing_done.event_name(), "MyDomain.thingDone");
+    let mut out = Vec::new();
+    rust_thing_done
+        .serialize_event_params(&mut serde_json::Serializer::new(&mut out))
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), json_thing_done);
+
+    {
+        let mut deserializer = serde_json::Deserializer::from_str(json_thing_done);
+        let result = MyDomainEvent::deserialize_event("MyDomain.thingDone", &mut deserializer);
+        let rust_event = MyDomainEvent::ThingDone(rust_thing_done);
+        assert_eq!(result.ok().map(Result::ok), Some(Some(rust_event)));
+    }
+
+    {
+        let mut deserializer = serde_json::Deserializer::from_str(json_thing_done);
+        let result =
+            ThingDoneEvent::<Value>::deserialize_event("MyDomain.thingUndone", &mut deserializer);
+        assert!(result.is_err());
+    }
+}
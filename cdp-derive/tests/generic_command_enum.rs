@@ -46,7 +46,7 @@ where
     #[cdp = "Page.enable"]
     PageEnable,
     #[cdp = "Page.disable"]
-    PageDisable(page::EnableCommand),
+    PageDisable(cdp::Empty),
     Other { name: Wrapper<'a, T>, params: Value },
 }
 
@@ -104,7 +104,7 @@ fn test() {
         let result = Foo::deserialize_command("Page.disable", &mut deserializer);
         assert_eq!(
             result.ok().map(Result::ok),
-            Some(Some(GenericFoo::PageDisable(page::EnableCommand)))
+            Some(Some(GenericFoo::PageDisable(cdp::Empty)))
         );
     }
 
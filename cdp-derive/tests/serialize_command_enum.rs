@@ -0,0 +1,89 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{page, DeserializeCdpCommand, SerializeCdpCommand};
+use serde_json::{Map, Value};
+
+#[derive(SerializeCdpCommand, DeserializeCdpCommand, Debug, PartialEq)]
+enum Foo<'a> {
+    #[cdp = "Page.navigate"]
+    PageNavigate { params: page::NavigateCommand<'a> },
+    #[cdp = "Page.captureScreenshot"]
+    PageCaptureScreenshot(page::CaptureScreenshotCommand),
+    #[cdp = "Page.enable"]
+    PageEnable,
+    #[cdp = "Page.disable"]
+    PageDisable(cdp::Empty),
+    Other(String, Value),
+}
+
+fn serialize_params(command: &Foo) -> String {
+    let mut out = Vec::new();
+    command
+        .serialize_command_params(&mut serde_json::Serializer::new(&mut out))
+        .expect("serialize error");
+    String::from_utf8(out).unwrap()
+}
+
+fn round_trip(command: Foo) {
+    let params = serialize_params(&command);
+    let mut deserializer = serde_json::Deserializer::from_str(&params);
+    let result = Foo::deserialize_command(command.command_name(), &mut deserializer);
+    assert_eq!(result.ok().map(Result::ok), Some(Some(command)));
+}
+
+#[test]
+fn test() {
+    let rust_navigate = Foo::PageNavigate {
+        params: page::NavigateCommand {
+            url: "https://www.mozilla.org".into(),
+            referrer: None,
+            transition_type: None,
+        },
+    };
+    assert_eq!(rust_navigate.command_name(), "Page.navigate");
+    assert_eq!(serialize_params(&rust_navigate), r#"{"url":"https://www.mozilla.org"}"#);
+    round_trip(rust_navigate);
+
+    let rust_capture_screenshot = Foo::PageCaptureScreenshot(page::CaptureScreenshotCommand {
+        format: Some(page::CaptureScreenshotCommandFormat::Png),
+        quality: None,
+        from_surface: None,
+        clip: None,
+    });
+    assert_eq!(rust_capture_screenshot.command_name(), "Page.captureScreenshot");
+    assert_eq!(serialize_params(&rust_capture_screenshot), r#"{"format":"png"}"#);
+    round_trip(rust_capture_screenshot);
+
+    assert_eq!(Foo::PageEnable.command_name(), "Page.enable");
+    assert_eq!(serialize_params(&Foo::PageEnable), "{}");
+    round_trip(Foo::PageEnable);
+
+    let rust_disable = Foo::PageDisable(cdp::Empty);
+    assert_eq!(rust_disable.command_name(), "Page.disable");
+    assert_eq!(serialize_params(&rust_disable), "{}");
+    round_trip(rust_disable);
+
+    let rust_other = Foo::Other(
+        "Bar.baz".into(),
+        Value::Object({
+            let mut params = Map::with_capacity(1);
+            params.insert("url".into(), Value::String("https://www.mozilla.org".into()));
+            params
+        }),
+    );
+    assert_eq!(rust_other.command_name(), "Bar.baz");
+    assert_eq!(serialize_params(&rust_other), r#"{"url":"https://www.mozilla.org"}"#);
+    round_trip(rust_other);
+}
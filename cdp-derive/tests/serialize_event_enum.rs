@@ -0,0 +1,78 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{page, DeserializeCdpEvent, SerializeCdpEvent};
+use serde_json::{Map, Number, Value};
+
+#[derive(SerializeCdpEvent, DeserializeCdpEvent, Debug, PartialEq)]
+enum GenericFoo<N, P> {
+    PageDomContentEventFired(page::DomContentEventFiredEvent),
+    #[cdp = "Page.loadEventFired"]
+    PageLoadEventFired {
+        params: page::LoadEventFiredEvent,
+    },
+    #[cdp = "Page.interstitialHidden"]
+    PageInterstitialHidden {},
+    Other { name: N, params: P },
+}
+
+type Foo = GenericFoo<String, Value>;
+
+fn serialize_params(event: &Foo) -> String {
+    let mut out = Vec::new();
+    event
+        .serialize_event_params(&mut serde_json::Serializer::new(&mut out))
+        .expect("serialize error");
+    String::from_utf8(out).unwrap()
+}
+
+fn round_trip(event: Foo) {
+    let params = serialize_params(&event);
+    let mut deserializer = serde_json::Deserializer::from_str(&params);
+    let result = Foo::deserialize_event(event.event_name(), &mut deserializer);
+    assert_eq!(result.ok().map(Result::ok), Some(Some(event)));
+}
+
+#[test]
+fn test() {
+    let rust_dom_event =
+        GenericFoo::PageDomContentEventFired(page::DomContentEventFiredEvent { timestamp: 12.7 });
+    assert_eq!(rust_dom_event.event_name(), "Page.domContentEventFired");
+    assert_eq!(serialize_params(&rust_dom_event), r#"{"timestamp":12.7}"#);
+    round_trip(rust_dom_event);
+
+    let rust_load_event = GenericFoo::PageLoadEventFired {
+        params: page::LoadEventFiredEvent { timestamp: 12.7 },
+    };
+    assert_eq!(rust_load_event.event_name(), "Page.loadEventFired");
+    assert_eq!(serialize_params(&rust_load_event), r#"{"timestamp":12.7}"#);
+    round_trip(rust_load_event);
+
+    let rust_hidden = GenericFoo::PageInterstitialHidden {};
+    assert_eq!(rust_hidden.event_name(), "Page.interstitialHidden");
+    assert_eq!(serialize_params(&rust_hidden), "{}");
+    round_trip(rust_hidden);
+
+    let rust_other = GenericFoo::Other {
+        name: "Bar.baz".into(),
+        params: Value::Object({
+            let mut params = Map::with_capacity(1);
+            params.insert("timestamp".into(), Value::Number(Number::from_f64(12.7).unwrap()));
+            params
+        }),
+    };
+    assert_eq!(rust_other.event_name(), "Bar.baz");
+    assert_eq!(serialize_params(&rust_other), r#"{"timestamp":12.7}"#);
+    round_trip(rust_other);
+}
@@ -0,0 +1,130 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+#![cfg_attr(feature = "strict", deny(warnings))]
+#![cfg_attr(feature = "clippy", feature(plugin))]
+#![cfg_attr(feature = "clippy", plugin(clippy))]
+
+extern crate cdp;
+extern crate futures;
+extern crate serde_json;
+
+#[macro_use]
+extern crate cdp_derive;
+
+use cdp::{page, CdpError, CdpErrorKind, CdpIncoming, CdpOutgoing};
+use cdp::server::HandlerFuture;
+use futures::{future, Future};
+use futures::sync::oneshot;
+
+#[derive(CdpService)]
+#[cdp(service = "FakeBrowser")]
+pub enum Commands<'a> {
+    PageNavigate(page::NavigateCommand<'a>),
+    #[cdp(name = "Page.enable", alias = "Legacy.enablePage")]
+    PageEnable(page::EnableCommand),
+}
+
+// Navigation finishes right away, while enabling waits for the test to let it
+// through.
+#[derive(Default)]
+struct Browser {
+    urls: Vec<String>,
+    enablers: Vec<oneshot::Sender<()>>,
+}
+
+impl<'a> FakeBrowser<'a> for Browser {
+    fn page_navigate(
+        &mut self,
+        params: page::NavigateCommand<'a>,
+    ) -> HandlerFuture<page::NavigateResponse<'static>> {
+        if params.url == "about:crash" {
+            let error = CdpError::server_error("Cannot navigate to invalid URL".into());
+            return Box::new(future::err(error));
+        }
+        self.urls.push(params.url.into_owned());
+        Box::new(future::ok(page::NavigateResponse {
+            frame_id: "0".into(),
+        }))
+    }
+
+    fn page_enable(&mut self, _: page::EnableCommand) -> HandlerFuture<page::EnableResponse> {
+        let (sender, receiver) = oneshot::channel();
+        self.enablers.push(sender);
+        Box::new(
+            receiver
+                .map(|()| page::EnableResponse)
+                .map_err(|_| CdpError::server_error("Browser closed".into())),
+        )
+    }
+}
+
+fn dispatch(browser: &mut Browser, json: &str) -> String {
+    let incoming = CdpIncoming::parse_from_str(json).expect("parse error");
+    browser.dispatch(incoming).wait().expect("dispatch error")
+}
+
+#[test]
+fn test_response() {
+    let mut browser = Browser::default();
+
+    let json = r#"{"id":1,"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#;
+    assert_eq!(r#"{"id":1,"result":{"frameId":"0"}}"#, dispatch(&mut browser, json));
+    assert_eq!(vec!["https://www.mozilla.org".to_string()], browser.urls);
+
+    let json = r#"{"id":2,"method":"Legacy.enablePage"}"#;
+    let frame = browser.dispatch(CdpIncoming::parse_from_str(json).expect("parse error"));
+    assert_eq!(1, browser.enablers.len());
+    browser.enablers.remove(0).send(()).expect("send error");
+    assert_eq!(Ok(r#"{"id":2,"result":{}}"#.to_string()), frame.wait());
+}
+
+#[test]
+fn test_handler_error() {
+    let mut browser = Browser::default();
+
+    let json = r#"{"id":3,"method":"Page.navigate","params":{"url":"about:crash"}}"#;
+    assert_eq!(
+        r#"{"id":3,"error":{"code":-32000,"message":"Cannot navigate to invalid URL"}}"#,
+        dispatch(&mut browser, json)
+    );
+    assert!(browser.urls.is_empty());
+
+    let json = r#"{"id":5,"method":"Page.enable"}"#;
+    let frame = browser.dispatch(CdpIncoming::parse_from_str(json).expect("parse error"));
+    browser.enablers.clear();
+    assert_eq!(
+        Ok(r#"{"id":5,"error":{"code":-32000,"message":"Browser closed"}}"#.to_string()),
+        frame.wait()
+    );
+}
+
+#[test]
+fn test_method_not_found() {
+    let mut browser = Browser::default();
+
+    let json = r#"{"id":1,"method":"Foo.bar","params":{}}"#;
+    assert_eq!(
+        r#"{"id":1,"error":{"code":-32601,"message":"'Foo.bar' wasn't found"}}"#,
+        dispatch(&mut browser, json)
+    );
+}
+
+#[test]
+fn test_invalid_params() {
+    let mut browser = Browser::default();
+
+    let json = r#"{"id":4,"method":"Page.navigate","params":{"url":7}}"#;
+    let frame = dispatch(&mut browser, json);
+    match serde_json::from_str(&frame).expect("parse error") {
+        CdpOutgoing::Result {
+            id: 4,
+            result: Err(error),
+        } => {
+            assert_eq!(CdpErrorKind::InvalidParams, error.kind);
+            assert_eq!("Invalid parameters", error.message);
+        }
+        other => panic!("expected an invalid params error: {:#?}", other),
+    }
+}
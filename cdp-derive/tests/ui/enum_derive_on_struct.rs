@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(DeserializeCdpCommand)]
+struct Foo {
+    params: serde_json::Value,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(CdpEvent)]
+#[cdp(name = "Page.loadEventFired", response = Bar)]
+struct Foo {
+    timestamp: f64,
+}
+
+struct Bar;
+
+fn main() {}
@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(DeserializeCdpCommand)]
+enum Foo {
+    #[cdp = "Page.enable"]
+    #[cdp = "Page.disable"]
+    PageEnable,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(CdpCommand)]
+#[cdp(name = "MyDomain.doThing", response = (u32, u32))]
+struct Foo;
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(CdpCommand)]
+#[cdp(name = "MyDomain.doThing", response = Bar<'a, 'b>)]
+struct Foo;
+
+struct Bar<'a, 'b>(&'a str, &'b str);
+
+fn main() {}
@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+
+#[derive(CdpService)]
+enum Foo {
+    #[cdp(domain = "Page")]
+    Page(cdp::page::EnableCommand),
+}
+
+fn main() {}
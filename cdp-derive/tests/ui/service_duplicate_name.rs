@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+
+#[derive(CdpService)]
+#[cdp(service = "Browser", service = "FakeBrowser")]
+enum Foo {
+    PageEnable(cdp::page::EnableCommand),
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+
+#[derive(CdpService)]
+struct Foo(cdp::page::EnableCommand);
+
+fn main() {}
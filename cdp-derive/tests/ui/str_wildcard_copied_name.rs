@@ -0,0 +1,17 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+use cdp::DeserializeCdpEvent;
+use serde_json::Value;
+
+#[derive(DeserializeCdpEvent)]
+enum Proxied<'a> {
+    Other(&'a str, Value),
+}
+
+fn main() {
+    let name = "Network.dataReceived".to_string();
+    let _ = Proxied::deserialize_event(&name, Value::Null);
+}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(CdpEvent)]
+#[cdp = "Page.loadEventFired"]
+struct Foo {
+    timestamp: f64,
+}
+
+fn main() {}
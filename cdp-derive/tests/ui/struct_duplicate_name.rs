@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(CdpEvent)]
+#[cdp(name = "Page.loadEventFired")]
+#[cdp(name = "Page.domContentEventFired")]
+struct Foo {
+    timestamp: f64,
+}
+
+fn main() {}
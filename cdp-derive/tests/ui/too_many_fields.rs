@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(SerializeCdpCommand)]
+enum Foo {
+    Other(String, serde_json::Value, u32),
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(DeserializeCdpCommand)]
+enum Foo {
+    #[cdp(alias = "Page.enable")]
+    PageEnable,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(DeserializeCdpEvent)]
+enum Foo {
+    #[cdp(protocol = "Network")]
+    Network(serde_json::Value),
+}
+
+fn main() {}
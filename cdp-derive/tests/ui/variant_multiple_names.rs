@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(DeserializeCdpCommand)]
+enum Foo {
+    #[cdp(name = "Page.enable", name = "Page.disable")]
+    PageEnable,
+}
+
+fn main() {}
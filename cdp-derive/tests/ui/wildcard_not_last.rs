@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate cdp_derive;
+extern crate cdp;
+extern crate serde_json;
+
+#[derive(DeserializeCdpEvent)]
+enum Foo {
+    Other(String, serde_json::Value),
+    #[cdp = "Page.loadEventFired"]
+    PageLoadEventFired,
+}
+
+fn main() {}
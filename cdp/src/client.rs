@@ -0,0 +1,575 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::Serializer;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Value};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::mem;
+use std::str;
+
+use {CdpError, CdpIncoming, CdpOutgoing, HasCdpResponse, SerializeCdpCommand};
+
+type ResponseDecoder = fn(Map<String, Value>) -> Result<Box<Any>, serde_json::Error>;
+
+struct PendingCommand {
+    command_name: String,
+    decoder: ResponseDecoder,
+}
+
+pub struct Correlator {
+    next_id: u64,
+    pending: HashMap<u64, PendingCommand>,
+}
+
+impl Correlator {
+    pub fn new() -> Self {
+        Correlator::with_first_id(1)
+    }
+
+    pub fn with_first_id(first_id: u64) -> Self {
+        Correlator {
+            next_id: first_id,
+            pending: HashMap::new(),
+        }
+    }
+
+    // Numbering continues from `next_id`; ids that are still pending are
+    // skipped when it reaches them.
+    pub fn set_next_id(&mut self, next_id: u64) {
+        self.next_id = next_id;
+    }
+
+    pub fn register<C>(&mut self, command: &C) -> u64
+    where
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        self.register_with_decoder(
+            command.command_name(),
+            decode_response::<<C as HasCdpResponse<'static>>::Response>,
+        )
+    }
+
+    pub fn register_raw(&mut self, command_name: &str) -> u64 {
+        self.register_with_decoder(command_name, decode_raw_response)
+    }
+
+    fn register_with_decoder(&mut self, command_name: &str, decoder: ResponseDecoder) -> u64 {
+        let id = self.allocate_id();
+        self.pending.insert(
+            id,
+            PendingCommand {
+                command_name: command_name.into(),
+                decoder: decoder,
+            },
+        );
+        id
+    }
+
+    fn allocate_id(&mut self) -> u64 {
+        // Skip over any ids that are still waiting on a response after the
+        // counter wraps around.
+        while self.pending.contains_key(&self.next_id) {
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        id
+    }
+
+    pub fn serialize_command<S, C>(&mut self, serializer: S, command: &C) -> Result<u64, S::Error>
+    where
+        S: Serializer,
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        let id = self.register(command);
+        match CdpIncoming::serialize_command(serializer, id, command) {
+            Ok(_) => Ok(id),
+            Err(err) => {
+                self.cancel(id);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn serialize_command_to_writer<W, C>(
+        &mut self,
+        writer: W,
+        command: &C,
+    ) -> Result<u64, serde_json::Error>
+    where
+        W: Write,
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        let mut serializer = serde_json::Serializer::new(writer);
+        self.serialize_command(&mut serializer, command)
+    }
+
+    pub fn serialize_command_to_string<C>(
+        &mut self,
+        string: &mut String,
+        command: &C,
+    ) -> Result<u64, serde_json::Error>
+    where
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        // serde_json won't produce invalid UTF-8.
+        self.serialize_command_to_writer(unsafe { string.as_mut_vec() }, command)
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+
+    pub fn is_pending(&self, id: u64) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn pending_command_name(&self, id: u64) -> Option<&str> {
+        self.pending
+            .get(&id)
+            .map(|pending| pending.command_name.as_str())
+    }
+
+    pub fn route<'a>(&mut self, frame: CdpOutgoing<'a>) -> Routed<'a> {
+        match frame {
+            CdpOutgoing::Result { id, result } => match self.pending.remove(&id) {
+                None => Routed::Event(CdpOutgoing::Result {
+                    id: id,
+                    result: result,
+                }),
+                Some(pending) => {
+                    let decoded = match result {
+                        Ok(result) => (pending.decoder)(result)
+                            .map_err(|err| CdpError::invalid_response(err.to_string())),
+                        Err(err) => Err(err.into_owned()),
+                    };
+                    Routed::Response(CorrelatedResponse {
+                        id: id,
+                        command_name: pending.command_name,
+                        result: decoded,
+                    })
+                }
+            },
+            other => Routed::Event(other),
+        }
+    }
+
+    pub fn route_str<'a>(&mut self, src: &'a str) -> Result<Routed<'a>, serde_json::Error> {
+        Ok(self.route(serde_json::from_str(src)?))
+    }
+
+    pub fn route_slice<'a>(&mut self, src: &'a [u8]) -> Result<Routed<'a>, serde_json::Error> {
+        Ok(self.route(serde_json::from_slice(src)?))
+    }
+}
+
+fn decode_response<R>(result: Map<String, Value>) -> Result<Box<Any>, serde_json::Error>
+where
+    R: DeserializeOwned + Any,
+{
+    let response: R = serde_json::from_value(Value::Object(result))?;
+    Ok(Box::new(response))
+}
+
+fn decode_raw_response(result: Map<String, Value>) -> Result<Box<Any>, serde_json::Error> {
+    Ok(Box::new(result))
+}
+
+impl Default for Correlator {
+    fn default() -> Self {
+        Correlator::new()
+    }
+}
+
+impl fmt::Debug for Correlator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pending: HashMap<&u64, &str> = self.pending
+            .iter()
+            .map(|(id, pending)| (id, pending.command_name.as_str()))
+            .collect();
+        f.debug_struct("Correlator")
+            .field("next_id", &self.next_id)
+            .field("pending", &pending)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum Routed<'a> {
+    Response(CorrelatedResponse),
+    // Anything that isn't the answer to a pending command, including
+    // responses to ids we never allocated (or have since cancelled) and
+    // errors that aren't attached to any id.
+    Event(CdpOutgoing<'a>),
+}
+
+pub struct CorrelatedResponse {
+    pub id: u64,
+    pub command_name: String,
+    pub result: Result<Box<Any>, CdpError<'static>>,
+}
+
+impl CorrelatedResponse {
+    pub fn downcast<R>(self) -> Result<Result<R, CdpError<'static>>, Self>
+    where
+        R: Any,
+    {
+        match self.result {
+            Ok(response) => match response.downcast::<R>() {
+                Ok(response) => Ok(Ok(*response)),
+                Err(response) => Err(CorrelatedResponse {
+                    id: self.id,
+                    command_name: self.command_name,
+                    result: Ok(response),
+                }),
+            },
+            Err(err) => Ok(Err(err)),
+        }
+    }
+}
+
+impl fmt::Debug for CorrelatedResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = match self.result {
+            Ok(_) => Ok(Omitted),
+            Err(ref err) => Err(err),
+        };
+        f.debug_struct("CorrelatedResponse")
+            .field("id", &self.id)
+            .field("command_name", &self.command_name)
+            .field("result", &result)
+            .finish()
+    }
+}
+
+// Serializes a run of commands with consecutive ids into one reusable buffer,
+// keeping track of where each frame ends so that they can be sent as
+// separate messages.
+pub struct CommandBatch {
+    first_id: u64,
+    buffer: Vec<u8>,
+    frame_ends: Vec<usize>,
+    decoders: Vec<ResponseDecoder>,
+}
+
+impl CommandBatch {
+    pub fn new(first_id: u64) -> Self {
+        CommandBatch {
+            first_id: first_id,
+            buffer: Vec::new(),
+            frame_ends: Vec::new(),
+            decoders: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self, first_id: u64) {
+        self.first_id = first_id;
+        self.buffer.clear();
+        self.frame_ends.clear();
+        self.decoders.clear();
+    }
+
+    pub fn push<C>(
+        &mut self,
+        command: &C,
+    ) -> Result<BatchSlot<<C as HasCdpResponse<'static>>::Response>, serde_json::Error>
+    where
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        let id = self.push_with_decoder(
+            command,
+            decode_response::<<C as HasCdpResponse<'static>>::Response>,
+        )?;
+        Ok(BatchSlot {
+            id: id,
+            response: PhantomData,
+        })
+    }
+
+    pub fn push_raw<C>(
+        &mut self,
+        command: &C,
+    ) -> Result<BatchSlot<Map<String, Value>>, serde_json::Error>
+    where
+        C: SerializeCdpCommand,
+    {
+        let id = self.push_with_decoder(command, decode_raw_response)?;
+        Ok(BatchSlot {
+            id: id,
+            response: PhantomData,
+        })
+    }
+
+    fn push_with_decoder<C>(
+        &mut self,
+        command: &C,
+        decoder: ResponseDecoder,
+    ) -> Result<u64, serde_json::Error>
+    where
+        C: SerializeCdpCommand,
+    {
+        let id = self.first_id.wrapping_add(self.len() as u64);
+        let start = self.buffer.len();
+        if let Err(err) = CdpIncoming::serialize_command_to_writer(&mut self.buffer, id, command) {
+            self.buffer.truncate(start);
+            return Err(err);
+        }
+        self.frame_ends.push(self.buffer.len());
+        self.decoders.push(decoder);
+        Ok(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frame_ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frame_ends.is_empty()
+    }
+
+    pub fn ids(&self) -> BatchIds {
+        BatchIds {
+            next_id: self.first_id,
+            remaining: self.len(),
+        }
+    }
+
+    pub fn frame(&self, index: usize) -> Option<&str> {
+        let end = match self.frame_ends.get(index) {
+            Some(&end) => end,
+            None => return None,
+        };
+        let start = if index == 0 {
+            0
+        } else {
+            self.frame_ends[index - 1]
+        };
+        // serde_json won't produce invalid UTF-8.
+        Some(unsafe { str::from_utf8_unchecked(&self.buffer[start..end]) })
+    }
+
+    pub fn frames(&self) -> BatchFrames {
+        BatchFrames {
+            batch: self,
+            index: 0,
+        }
+    }
+
+    pub fn collector(&self) -> BatchCollector {
+        BatchCollector {
+            first_id: self.first_id,
+            decoders: self.decoders.clone(),
+            results: self.decoders.iter().map(|_| BatchResult::Pending).collect(),
+            remaining: self.decoders.len(),
+        }
+    }
+}
+
+impl fmt::Debug for CommandBatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandBatch")
+            .field("ids", &self.ids())
+            .field("frames", &self.frames().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+// Ids wrap around past `u64::max_value()`, the same as the correlator's.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BatchIds {
+    next_id: u64,
+    remaining: usize,
+}
+
+impl Iterator for BatchIds {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.remaining -= 1;
+        Some(id)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for BatchIds {}
+
+#[derive(Clone, Debug)]
+pub struct BatchFrames<'a> {
+    batch: &'a CommandBatch,
+    index: usize,
+}
+
+impl<'a> Iterator for BatchFrames<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.batch.frame(self.index);
+        if frame.is_some() {
+            self.index += 1;
+        }
+        frame
+    }
+}
+
+pub struct BatchSlot<R> {
+    id: u64,
+    response: PhantomData<fn() -> R>,
+}
+
+impl<R> BatchSlot<R> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<R> Clone for BatchSlot<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for BatchSlot<R> {}
+
+impl<R> fmt::Debug for BatchSlot<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BatchSlot").field("id", &self.id).finish()
+    }
+}
+
+pub struct BatchCollector {
+    first_id: u64,
+    decoders: Vec<ResponseDecoder>,
+    results: Vec<BatchResult>,
+    remaining: usize,
+}
+
+enum BatchResult {
+    Pending,
+    Filled(Result<Box<Any>, CdpError<'static>>),
+    Taken,
+}
+
+impl BatchCollector {
+    // Hands back any frame that isn't an outstanding response for this batch.
+    pub fn collect<'a>(&mut self, frame: CdpOutgoing<'a>) -> Option<CdpOutgoing<'a>> {
+        let (id, result) = match frame {
+            CdpOutgoing::Result { id, result } => (id, result),
+            other => return Some(other),
+        };
+        let index = id.wrapping_sub(self.first_id) as usize;
+        match self.results.get(index) {
+            Some(&BatchResult::Pending) => {}
+            // Repeated responses are as unmatched as ones for other batches.
+            _ => {
+                return Some(CdpOutgoing::Result {
+                    id: id,
+                    result: result,
+                })
+            }
+        }
+
+        let decoded = match result {
+            Ok(result) => (self.decoders[index])(result)
+                .map_err(|err| CdpError::invalid_response(err.to_string())),
+            Err(err) => Err(err.into_owned()),
+        };
+        self.results[index] = BatchResult::Filled(decoded);
+        self.remaining -= 1;
+        None
+    }
+
+    pub fn collect_str<'a>(
+        &mut self,
+        src: &'a str,
+    ) -> Result<Option<CdpOutgoing<'a>>, serde_json::Error> {
+        Ok(self.collect(serde_json::from_str(src)?))
+    }
+
+    pub fn collect_slice<'a>(
+        &mut self,
+        src: &'a [u8],
+    ) -> Result<Option<CdpOutgoing<'a>>, serde_json::Error> {
+        Ok(self.collect(serde_json::from_slice(src)?))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+
+    // Returns `None` while the response hasn't arrived yet, or if the slot
+    // belongs to a different batch.
+    pub fn take<R>(&mut self, slot: BatchSlot<R>) -> Option<Result<R, CdpError<'static>>>
+    where
+        R: Any,
+    {
+        let index = slot.id.wrapping_sub(self.first_id) as usize;
+        match self.results.get(index) {
+            Some(&BatchResult::Filled(_)) => {}
+            _ => return None,
+        }
+        let result = mem::replace(&mut self.results[index], BatchResult::Taken);
+        match result {
+            BatchResult::Filled(Ok(response)) => match response.downcast::<R>() {
+                Ok(response) => Some(Ok(*response)),
+                Err(response) => {
+                    self.results[index] = BatchResult::Filled(Ok(response));
+                    None
+                }
+            },
+            BatchResult::Filled(Err(err)) => Some(Err(err)),
+            BatchResult::Pending | BatchResult::Taken => None,
+        }
+    }
+}
+
+impl fmt::Debug for BatchCollector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let results: Vec<Option<Result<Omitted, &CdpError>>> = self.results
+            .iter()
+            .map(|result| match *result {
+                BatchResult::Pending | BatchResult::Taken => None,
+                BatchResult::Filled(Ok(_)) => Some(Ok(Omitted)),
+                BatchResult::Filled(Err(ref err)) => Some(Err(err)),
+            })
+            .collect();
+        f.debug_struct("BatchCollector")
+            .field("first_id", &self.first_id)
+            .field("results", &results)
+            .finish()
+    }
+}
+
+struct Omitted;
+
+impl fmt::Debug for Omitted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "...")
+    }
+}
@@ -12,7 +12,6 @@
 extern crate inflector;
 #[macro_use]
 extern crate lazy_static;
-extern crate petgraph;
 #[macro_use]
 extern crate quote;
 extern crate regex;
@@ -20,43 +19,52 @@ extern crate rustfmt;
 extern crate serde_json;
 
 use inflector::Inflector;
-use petgraph::Directed;
-use petgraph::graph::{Graph, NodeIndex};
-use petgraph::visit::{Control, DfsEvent};
 use quote::{Ident, Tokens};
 use regex::Regex;
 use rustfmt::Input;
 use rustfmt::config::Config;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::env;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::iter;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 extern crate cdp_definition;
 
-use cdp_definition::{Definition, Domain, Field, Method, Type, TypeDef, Version};
+use cdp_definition::{pdl, Definition, Domain, Field, ItemRef, Method, Overlay, Type, TypeDef,
+                     Version};
 
 fn main() {
     let out_dir = env::var("OUT_DIR").expect("error retrieving OUT_DIR environment variable");
 
-    let mut browser_protocol: Definition = serde_json::from_str(
+    let mut browser_protocol = load_protocol(
+        "browser_protocol.json",
         include_str!("../../json/browser_protocol.json"),
-    ).expect("error parsing browser_protocol.json");
-
-    let js_protocol: Definition = serde_json::from_str(
+        "CDP_BROWSER_PROTOCOL",
+    );
+    let js_protocol = load_protocol(
+        "js_protocol.json",
         include_str!("../../json/js_protocol.json"),
-    ).expect("error parsing js_protocol.json");
-
-    if browser_protocol.version != js_protocol.version {
-        panic!("json/browser_protocol.json and json/js_protocol.json versions don't match");
-    }
+        "CDP_JS_PROTOCOL",
+    );
 
     browser_protocol
-        .domains
-        .extend_from_slice(&js_protocol.domains);
+        .merge(js_protocol)
+        .expect("error merging js_protocol.json into browser_protocol.json");
+
+    // Lets downstream builds patch the protocol (e.g. to add private domains)
+    // without forking the JSON files.
+    if let Some(overlay_path) = env::var_os("CDP_PROTOCOL_OVERLAY") {
+        let overlay_file = File::open(&overlay_path).expect("error opening protocol overlay");
+        let overlay: Overlay =
+            serde_json::from_reader(overlay_file).expect("error parsing protocol overlay");
+        browser_protocol
+            .apply_overlay(&overlay)
+            .expect("error applying protocol overlay");
+        println!("cargo:rerun-if-changed={}", Path::new(&overlay_path).display());
+    }
 
     let generated_src = generate_rust_source(&browser_protocol);
     let generated_path = Path::new(&out_dir).join("generated.rs");
@@ -66,13 +74,42 @@ fn main() {
 
     println!("cargo:rerun-if-changed=../json/browser_protocol.json");
     println!("cargo:rerun-if-changed=../json/js_protocol.json");
+    println!("cargo:rerun-if-env-changed=CDP_PROTOCOL_OVERLAY");
+}
+
+// Either protocol can be replaced with a file of its own, which is read as PDL
+// if it has a .pdl extension (e.g. Chromium's browser_protocol.pdl) and as JSON
+// otherwise.
+fn load_protocol(bundled_name: &str, bundled_src: &str, env_var: &str) -> Definition {
+    println!("cargo:rerun-if-env-changed={}", env_var);
+    let path = match env::var_os(env_var) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            return serde_json::from_str(bundled_src)
+                .unwrap_or_else(|err| panic!("error parsing {}: {}", bundled_name, err))
+        }
+    };
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let mut src = String::new();
+    File::open(&path)
+        .and_then(|mut file| file.read_to_string(&mut src))
+        .unwrap_or_else(|err| panic!("error reading {}: {}", path.display(), err));
+    let result = if path.extension() == Some("pdl".as_ref()) {
+        pdl::from_str(&src).map_err(|err| err.to_string())
+    } else {
+        serde_json::from_str(&src).map_err(|err| err.to_string())
+    };
+    result.unwrap_or_else(|err| panic!("error parsing {}: {}", path.display(), err))
 }
 
 fn generate_rust_source(def: &Definition) -> String {
     let version = generate_version(&def.version);
-    let domains = generate_domains(&def.domains);
+    let domains = generate_domains(def);
+    let command_registry = generate_method_registry(def, MethodKind::Command);
+    let event_registry = generate_method_registry(def, MethodKind::Event);
 
-    quote!(#version #domains).to_string()
+    quote!(#version #domains #command_registry #event_registry).to_string()
 }
 
 fn write_generated_source<T>(src: String, out: &mut T) -> Result<(), io::Error>
@@ -109,156 +146,107 @@ fn generate_version(version: &Version) -> Tokens {
     })
 }
 
-fn generate_domains(domains: &[Domain]) -> Tokens {
-    let uses_lifetime_set = generate_uses_lifetime_set(domains);
-    let modules = domains
-        .iter()
-        .map(|domain| generate_domain(domain, &uses_lifetime_set));
-    quote!(#(#modules)*)
-}
-
-fn generate_uses_lifetime_set(domains: &[Domain]) -> HashSet<Ident> {
-    let mut reference_graph = Graph::new();
-    let mut item_indices = HashMap::new();
-
-    let string_index = reference_graph.add_node(None);
-
-    fn item_index(
-        item_fully_qualified: Ident,
-        reference_graph: &mut Graph<Option<Ident>, (), Directed>,
-        item_indices: &mut HashMap<Ident, NodeIndex>,
-    ) -> NodeIndex {
-        if let Some(index) = item_indices.get(&item_fully_qualified) {
-            return *index;
-        }
-
-        let index = reference_graph.add_node(Some(item_fully_qualified.clone()));
-        item_indices.insert(item_fully_qualified, index);
-        index
-    }
-
-    fn traverse_fields<'a, T>(
-        domain_snake_case: &Ident,
-        parent_pascal_case: &Ident,
-        fields: T,
-        string_index: NodeIndex,
-        reference_graph: &mut Graph<Option<Ident>, (), Directed>,
-        item_indices: &mut HashMap<Ident, NodeIndex>,
-    ) where
-        T: Iterator<Item = &'a Field>,
-    {
-        for field in fields {
-            traverse_type(
-                domain_snake_case,
-                parent_pascal_case,
-                &field.ty,
-                string_index,
-                reference_graph,
-                item_indices,
-            )
+// `cdp-derive` checks explicit method names against these macros at compile
+// time: a name in a known domain must name one of its methods, and a payload
+// type given for it must be the method's own struct, if it's a method struct
+// at all.
+fn generate_method_registry(def: &Definition, kind: MethodKind) -> Tokens {
+    let kind_lowercase = kind.to_string().to_lowercase();
+    let macro_name = Ident::from(format!("__cdp_check_{}", kind_lowercase));
+    let check_method = Ident::from(format!("check_{}", kind_lowercase));
+
+    let mut arms = vec![];
+    for domain in &def.domains {
+        let domain_name = &domain.name;
+        let domain_snake_case = snake_case_ident(&domain.name);
+        let methods = match kind {
+            MethodKind::Command => &domain.commands,
+            MethodKind::Event => &domain.events,
+        };
+        for method in methods {
+            let method_name = &method.name;
+            let struct_pascal_case =
+                Ident::from(format!("{}{}", pascal_case_ident(&method.name), kind));
+            arms.push(quote! {
+                (#domain_name, #method_name $(, $payload:ty)*) => {
+                    $({
+                        #[allow(unused_imports)]
+                        use $crate::CheckUntypedPayload;
+                        let _: $crate::CheckPayload<
+                            $crate::#domain_snake_case::#struct_pascal_case
+                        > = $crate::CheckPayload::<$payload>::new().#check_method::<
+                            $crate::#domain_snake_case::#struct_pascal_case
+                        >();
+                    })*
+                };
+            });
         }
-    }
 
-    fn traverse_type(
-        domain_snake_case: &Ident,
-        parent_pascal_case: &Ident,
-        ty: &Type,
-        string_index: NodeIndex,
-        reference_graph: &mut Graph<Option<Ident>, (), Directed>,
-        item_indices: &mut HashMap<Ident, NodeIndex>,
-    ) {
-        match *ty {
-            Type::String => {
-                let parent_fully_qualified =
-                    fully_qualified_ident(domain_snake_case, parent_pascal_case);
-                let parent_index =
-                    item_index(parent_fully_qualified, reference_graph, item_indices);
-                reference_graph.add_edge(string_index, parent_index, ());
-            }
-            Type::Reference(ref target) => {
-                let target_pascal_case = pascal_case_ident(target);
-                if target_pascal_case != parent_pascal_case {
-                    let target_fully_qualified =
-                        resolve_reference(domain_snake_case, target, &target_pascal_case);
-                    let target_index =
-                        item_index(target_fully_qualified, reference_graph, item_indices);
-
-                    let parent_fully_qualified =
-                        fully_qualified_ident(domain_snake_case, parent_pascal_case);
-                    let parent_index =
-                        item_index(parent_fully_qualified, reference_graph, item_indices);
-
-                    reference_graph.add_edge(target_index, parent_index, ());
-                }
-            }
-            Type::Array { ref item, .. } => {
-                traverse_type(
-                    domain_snake_case,
-                    parent_pascal_case,
-                    &item.ty,
-                    string_index,
-                    reference_graph,
-                    item_indices,
-                );
-            }
-            Type::Object(ref fields) => traverse_fields(
-                domain_snake_case,
-                parent_pascal_case,
-                fields.iter(),
-                string_index,
-                reference_graph,
-                item_indices,
-            ),
-            Type::Boolean | Type::Integer | Type::Number | Type::Any | Type::Enum(_) => (),
-        }
+        let unknown_prefix = format!("unknown {} `{}.", kind_lowercase, domain.name);
+        arms.push(quote! {
+            (#domain_name, $method:tt $(, $payload:ty)*) => {
+                compile_error!(concat!(#unknown_prefix, $method, "`"));
+            };
+        });
     }
 
-    for domain in domains.iter() {
-        let domain_snake_case = snake_case_ident(&domain.name);
-        let domain_methods = domain.commands.iter().chain(domain.events.iter());
-        for method in domain_methods {
-            let method_pascal_case = pascal_case_ident(&method.name);
-            let method_fields = method.parameters.iter().chain(method.returns.iter());
-            traverse_fields(
-                &domain_snake_case,
-                &method_pascal_case,
-                method_fields,
-                string_index,
-                &mut reference_graph,
-                &mut item_indices,
-            );
-        }
-        for type_def in &domain.type_defs {
-            let type_def_pascal_case = pascal_case_ident(&type_def.name);
-            traverse_type(
-                &domain_snake_case,
-                &type_def_pascal_case,
-                &type_def.ty,
-                string_index,
-                &mut reference_graph,
-                &mut item_indices,
-            );
+    quote! {
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! #macro_name {
+            #(#arms)*
+            ($domain:tt, $method:tt $(, $payload:ty)*) => {};
         }
     }
+}
 
-    // 1 = starting String node which won't make it into the final set
-    let mut uses_lifetime_set = HashSet::with_capacity(reference_graph.node_count() - 1);
+fn generate_domains(def: &Definition) -> Tokens {
+    let uses_lifetime_set = generate_uses_lifetime_set(def);
+    let modules = def.domains
+        .iter()
+        .map(|domain| generate_domain(domain, &uses_lifetime_set));
+    quote!(#(#modules)*)
+}
 
-    petgraph::visit::depth_first_search(&reference_graph, iter::once(string_index), |event| {
-        if let DfsEvent::Discover(item_index, _) = event {
-            if let Some(ref item_fully_qualified) =
-                *reference_graph.node_weight(item_index).unwrap()
-            {
-                uses_lifetime_set.insert(item_fully_qualified.clone());
+// Anything holding a string borrows it, so it needs a lifetime parameter, and
+// so does everything that refers to it.
+fn generate_uses_lifetime_set(def: &Definition) -> HashSet<Ident> {
+    let index = def.index();
+    let mut uses_lifetime_set = HashSet::new();
+
+    for &item in index.items() {
+        let contains_string = match item {
+            ItemRef::TypeDef(_, type_def) => type_contains_string(&type_def.ty),
+            ItemRef::Command(_, method) | ItemRef::Event(_, method) => method
+                .parameters
+                .iter()
+                .chain(method.returns.iter())
+                .any(|field| type_contains_string(&field.ty)),
+        };
+        if contains_string {
+            let dependents = index.dependents(&item.path());
+            for item in iter::once(item).chain(dependents) {
+                let domain_snake_case = snake_case_ident(&item.domain().name);
+                let item_pascal_case = pascal_case_ident(item.name());
+                let item_fully_qualified =
+                    fully_qualified_ident(&domain_snake_case, &item_pascal_case);
+                uses_lifetime_set.insert(item_fully_qualified);
             }
         }
-        Control::Continue::<()>
-    });
+    }
 
-    uses_lifetime_set.shrink_to_fit();
     uses_lifetime_set
 }
 
+fn type_contains_string(ty: &Type) -> bool {
+    match *ty {
+        Type::String => true,
+        Type::Array { ref item, .. } => type_contains_string(&item.ty),
+        Type::Object(ref fields) => fields.iter().any(|field| type_contains_string(&field.ty)),
+        _ => false,
+    }
+}
+
 #[derive(Clone, Copy)]
 enum MethodKind {
     Command,
@@ -719,7 +707,27 @@ fn generate_method(
 
     let request_deserialize_trait = Ident::from(format!("DeserializeCdp{}", kind));
     let request_deserialize_method = Ident::from(format!("deserialize_{}", kind).to_lowercase());
+    let request_deserialize_borrowed_trait =
+        Ident::from(format!("DeserializeBorrowedCdp{}", kind));
+    let request_deserialize_borrowed_method =
+        Ident::from(format!("deserialize_borrowed_{}", kind).to_lowercase());
     type_defs.push(quote! {
+        impl<'de, #maybe_request_lifetime> ::#request_deserialize_borrowed_trait<'de>
+                for #request_pascal_case#request_lifetime_generics {
+            fn #request_deserialize_borrowed_method<D>(
+                name: &'de str,
+                params: D,
+            ) -> Result<Result<Self, D::Error>, D>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                <Self as ::#request_deserialize_trait<'de>>::#request_deserialize_method(
+                    name,
+                    params,
+                )
+            }
+        }
+
         impl<'de, #maybe_request_lifetime> ::#request_deserialize_trait<'de>
                 for #request_pascal_case#request_lifetime_generics {
             fn #request_deserialize_method<D>(
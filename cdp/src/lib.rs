@@ -9,6 +9,7 @@
 #![cfg_attr(feature = "clippy", plugin(clippy))]
 #![doc(html_root_url = "https://docs.rs/cdp/0.1.0")]
 
+extern crate futures;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -22,11 +23,18 @@ use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
 use std::io::{Read, Write};
+use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 
 mod generated;
+mod limits;
+
+pub mod client;
+pub mod pretty;
+pub mod server;
 
 pub use generated::*;
+pub use limits::{ParseLimitViolation, ParseLimits};
 
 // Empty Objects
 
@@ -117,7 +125,20 @@ impl<'a> SerializeCdpCommand for (&'a str, &'a Map<String, Value>) {
     }
 }
 
-pub trait DeserializeCdpCommand<'de>: Sized {
+// Like `DeserializeCdpCommand`, but with a name that lives as long as the
+// params, which types that keep the name (such as the wildcard variants of
+// derived enums) can borrow instead of copying. Types that can only borrow the
+// name implement this trait alone.
+pub trait DeserializeBorrowedCdpCommand<'de>: Sized {
+    fn deserialize_borrowed_command<D>(
+        command_name: &'de str,
+        command_params: D,
+    ) -> Result<Result<Self, D::Error>, D>
+    where
+        D: Deserializer<'de>;
+}
+
+pub trait DeserializeCdpCommand<'de>: DeserializeBorrowedCdpCommand<'de> {
     fn deserialize_command<D>(
         command_name: &str,
         command_params: D,
@@ -126,6 +147,18 @@ pub trait DeserializeCdpCommand<'de>: Sized {
         D: Deserializer<'de>;
 }
 
+impl<'de> DeserializeBorrowedCdpCommand<'de> for (String, Map<String, Value>) {
+    fn deserialize_borrowed_command<D>(
+        command_name: &'de str,
+        command_params: D,
+    ) -> Result<Result<Self, D::Error>, D>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::deserialize_command(command_name, command_params)
+    }
+}
+
 impl<'de> DeserializeCdpCommand<'de> for (String, Map<String, Value>) {
     fn deserialize_command<D>(
         command_name: &str,
@@ -178,7 +211,17 @@ impl<'a> SerializeCdpEvent for (&'a str, &'a Map<String, Value>) {
     }
 }
 
-pub trait DeserializeCdpEvent<'de>: Sized {
+// See `DeserializeBorrowedCdpCommand`.
+pub trait DeserializeBorrowedCdpEvent<'de>: Sized {
+    fn deserialize_borrowed_event<D>(
+        event_name: &'de str,
+        event_params: D,
+    ) -> Result<Result<Self, D::Error>, D>
+    where
+        D: Deserializer<'de>;
+}
+
+pub trait DeserializeCdpEvent<'de>: DeserializeBorrowedCdpEvent<'de> {
     fn deserialize_event<D>(
         event_name: &str,
         event_params: D,
@@ -187,6 +230,18 @@ pub trait DeserializeCdpEvent<'de>: Sized {
         D: Deserializer<'de>;
 }
 
+impl<'de> DeserializeBorrowedCdpEvent<'de> for (String, Map<String, Value>) {
+    fn deserialize_borrowed_event<D>(
+        event_name: &'de str,
+        event_params: D,
+    ) -> Result<Result<Self, D::Error>, D>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::deserialize_event(event_name, event_params)
+    }
+}
+
 impl<'de> DeserializeCdpEvent<'de> for (String, Map<String, Value>) {
     fn deserialize_event<D>(event_name: &str, event_params: D) -> Result<Result<Self, D::Error>, D>
     where
@@ -207,6 +262,69 @@ pub trait HasCdpResponse<'a> {
     type Response: 'a;
 }
 
+// Method Name Checks
+
+// Used by the code that `cdp-derive` generates, as in
+// `let _: CheckPayload<Registered> = CheckPayload::<Payload>::new()
+//      .check_command::<Registered>()`.
+// If the payload is a command or event struct, `check_command` and
+// `check_event` resolve to the inherent methods, which keep the payload type,
+// so it must be the struct registered for the method name. Any other payload
+// falls back to `CheckUntypedPayload`, which always passes.
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct CheckPayload<T>(PhantomData<T>);
+
+impl<T> CheckPayload<T> {
+    pub fn new() -> Self {
+        CheckPayload(PhantomData)
+    }
+}
+
+impl<T> Default for CheckPayload<T> {
+    fn default() -> Self {
+        CheckPayload::new()
+    }
+}
+
+impl<T> CheckPayload<T>
+where
+    T: CdpCommand,
+{
+    pub fn check_command<U>(self) -> Self {
+        self
+    }
+}
+
+impl<T> CheckPayload<T>
+where
+    T: CdpEvent,
+{
+    pub fn check_event<U>(self) -> Self {
+        self
+    }
+}
+
+#[doc(hidden)]
+pub trait CheckUntypedPayload {
+    fn check_command<U>(self) -> CheckPayload<U>
+    where
+        Self: Sized,
+    {
+        CheckPayload::new()
+    }
+
+    fn check_event<U>(self) -> CheckPayload<U>
+    where
+        Self: Sized,
+    {
+        CheckPayload::new()
+    }
+}
+
+impl<T> CheckUntypedPayload for CheckPayload<T> {}
+
 // Incoming Messages (to the server, from the client)
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -223,8 +341,23 @@ impl<'a> CdpIncoming<'a> {
     where
         D: Deserializer<'de>,
     {
-        let value =
-            Value::deserialize(deserializer).map_err(|_| (CdpError::invalid_message(), None))?;
+        CdpIncoming::parse_with_limits(deserializer, &ParseLimits::unlimited())
+    }
+
+    // The deserializer's input is opaque to us, so `max_message_bytes` can't
+    // be enforced here; use one of the `parse_from_*` variants for that.
+    pub fn parse_with_limits<'de, D>(
+        deserializer: D,
+        limits: &ParseLimits,
+    ) -> Result<Self, (CdpError<'static>, Option<u64>)>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = limits::deserialize_value(deserializer, limits)?;
+        CdpIncoming::from_value(value)
+    }
+
+    fn from_value(value: Value) -> Result<Self, (CdpError<'static>, Option<u64>)> {
         let mut obj = match value {
             Value::Object(obj) => obj,
             _ => return Err((CdpError::must_be_object(), None)),
@@ -253,15 +386,42 @@ impl<'a> CdpIncoming<'a> {
     where
         T: Read,
     {
-        CdpIncoming::parse(&mut serde_json::Deserializer::from_reader(reader))
+        CdpIncoming::parse_from_reader_with_limits(reader, &ParseLimits::unlimited())
+    }
+
+    pub fn parse_from_reader_with_limits<T>(
+        reader: T,
+        limits: &ParseLimits,
+    ) -> Result<Self, (CdpError<'static>, Option<u64>)>
+    where
+        T: Read,
+    {
+        let value = limits::deserialize_value_from_reader(reader, limits)?;
+        CdpIncoming::from_value(value)
     }
 
     pub fn parse_from_str(src: &str) -> Result<Self, (CdpError<'static>, Option<u64>)> {
-        CdpIncoming::parse(&mut serde_json::Deserializer::from_str(src))
+        CdpIncoming::parse_from_str_with_limits(src, &ParseLimits::unlimited())
+    }
+
+    pub fn parse_from_str_with_limits(
+        src: &str,
+        limits: &ParseLimits,
+    ) -> Result<Self, (CdpError<'static>, Option<u64>)> {
+        limits::check_message_len(src.as_bytes(), limits)?;
+        CdpIncoming::parse_with_limits(&mut serde_json::Deserializer::from_str(src), limits)
     }
 
     pub fn parse_from_slice(src: &[u8]) -> Result<Self, (CdpError<'static>, Option<u64>)> {
-        CdpIncoming::parse(&mut serde_json::Deserializer::from_slice(src))
+        CdpIncoming::parse_from_slice_with_limits(src, &ParseLimits::unlimited())
+    }
+
+    pub fn parse_from_slice_with_limits(
+        src: &[u8],
+        limits: &ParseLimits,
+    ) -> Result<Self, (CdpError<'static>, Option<u64>)> {
+        limits::check_message_len(src, limits)?;
+        CdpIncoming::parse_with_limits(&mut serde_json::Deserializer::from_slice(src), limits)
     }
 
     pub fn serialize_command<S, C>(serializer: S, id: u64, command: &C) -> Result<S::Ok, S::Error>
@@ -865,6 +1025,14 @@ pub struct CdpError<'a> {
 }
 
 impl<'a> CdpError<'a> {
+    pub fn into_owned(self) -> CdpError<'static> {
+        CdpError {
+            kind: self.kind,
+            message: self.message.into_owned().into(),
+            data: self.data,
+        }
+    }
+
     // https://github.com/nodejs/node/blob/8a8a6865c092637515b286cd9575ea592b5f501e/deps/v8/third_party/inspector_protocol/lib/DispatcherBase_cpp.template#L254
     pub fn invalid_message() -> Self {
         CdpError {
@@ -936,6 +1104,26 @@ impl<'a> CdpError<'a> {
             data: Some(Value::String(message)),
         }
     }
+
+    // Not part of Chromium's dispatcher; used when a message is rejected for
+    // going over the configured `ParseLimits`.
+    pub fn limit_exceeded(violation: ParseLimitViolation) -> Self {
+        CdpError {
+            kind: CdpErrorKind::InvalidRequest,
+            message: "Message exceeds parse limits".into(),
+            data: Some(Value::String(violation.to_string())),
+        }
+    }
+
+    // Not part of Chromium's dispatcher; used on the client side when a
+    // response doesn't have the shape expected for the command it answers.
+    pub fn invalid_response(message: String) -> Self {
+        CdpError {
+            kind: CdpErrorKind::InternalError,
+            message: "Invalid response".into(),
+            data: Some(Value::String(message)),
+        }
+    }
 }
 
 impl<'a> Error for CdpError<'a> {
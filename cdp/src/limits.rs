@@ -0,0 +1,386 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::{Map, Number, Value};
+use std::cell::Cell;
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+use CdpError;
+
+// All lengths are measured in bytes (for strings, of their UTF-8 encoding).
+// A limit of `None` means the corresponding dimension is unbounded.
+//
+// `max_string_len` is checked once serde_json has read a whole string, so it
+// bounds what ends up in the parsed message, not the memory used to parse it;
+// that's only bounded by `max_message_bytes`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ParseLimits {
+    pub max_message_bytes: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub max_string_len: Option<usize>,
+    pub max_array_len: Option<usize>,
+}
+
+impl ParseLimits {
+    pub fn unlimited() -> Self {
+        ParseLimits::default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ParseLimitViolation {
+    MessageBytes(usize),
+    Depth(usize),
+    StringLength(usize),
+    ArrayLength(usize),
+}
+
+impl Error for ParseLimitViolation {
+    fn description(&self) -> &str {
+        "message exceeds parse limits"
+    }
+}
+
+impl fmt::Display for ParseLimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseLimitViolation::MessageBytes(max) => {
+                write!(f, "message is longer than {} bytes", max)
+            }
+            ParseLimitViolation::Depth(max) => write!(f, "message is nested deeper than {}", max),
+            ParseLimitViolation::StringLength(max) => {
+                write!(f, "message contains a string longer than {} bytes", max)
+            }
+            ParseLimitViolation::ArrayLength(max) => {
+                write!(f, "message contains an array with more than {} items", max)
+            }
+        }
+    }
+}
+
+// A message that's too long is still parsed up to the limit, as a reader
+// would be, so that the violation is reported the same way: against the
+// request id if it comes before the limit, and as whichever violation is hit
+// first.
+pub fn check_message_len(
+    src: &[u8],
+    limits: &ParseLimits,
+) -> Result<(), (CdpError<'static>, Option<u64>)> {
+    let max = match limits.max_message_bytes {
+        Some(max) if src.len() > max => max,
+        _ => return Ok(()),
+    };
+    let state = LimitState::new(limits);
+    let _ = deserialize_value_with_state(
+        &mut ::serde_json::Deserializer::from_slice(&src[..max]),
+        &state,
+    );
+    let violation = state
+        .violation
+        .get()
+        .unwrap_or(ParseLimitViolation::MessageBytes(max));
+    Err((CdpError::limit_exceeded(violation), state.id.get()))
+}
+
+pub fn deserialize_value<'de, D>(
+    deserializer: D,
+    limits: &ParseLimits,
+) -> Result<Value, (CdpError<'static>, Option<u64>)>
+where
+    D: Deserializer<'de>,
+{
+    let state = LimitState::new(limits);
+    deserialize_value_with_state(deserializer, &state)
+}
+
+pub fn deserialize_value_from_reader<R>(
+    reader: R,
+    limits: &ParseLimits,
+) -> Result<Value, (CdpError<'static>, Option<u64>)>
+where
+    R: Read,
+{
+    let state = LimitState::new(limits);
+    match limits.max_message_bytes {
+        None => deserialize_value_with_state(
+            &mut ::serde_json::Deserializer::from_reader(reader),
+            &state,
+        ),
+        Some(max) => {
+            let limited_reader = LimitedReader {
+                inner: reader,
+                max: max,
+                remaining: max,
+                state: &state,
+            };
+            deserialize_value_with_state(
+                &mut ::serde_json::Deserializer::from_reader(limited_reader),
+                &state,
+            )
+        }
+    }
+}
+
+fn deserialize_value_with_state<'de, D>(
+    deserializer: D,
+    state: &LimitState,
+) -> Result<Value, (CdpError<'static>, Option<u64>)>
+where
+    D: Deserializer<'de>,
+{
+    let seed = ValueSeed {
+        state: state,
+        depth: 0,
+    };
+    seed.deserialize(deserializer)
+        .map_err(|_| match state.violation.get() {
+            None => (CdpError::invalid_message(), None),
+            Some(violation) => (CdpError::limit_exceeded(violation), state.id.get()),
+        })
+}
+
+struct LimitState {
+    limits: ParseLimits,
+    violation: Cell<Option<ParseLimitViolation>>,
+    id: Cell<Option<u64>>,
+}
+
+impl LimitState {
+    fn new(limits: &ParseLimits) -> Self {
+        LimitState {
+            limits: *limits,
+            violation: Cell::new(None),
+            id: Cell::new(None),
+        }
+    }
+
+    fn violate<E>(&self, violation: ParseLimitViolation) -> E
+    where
+        E: de::Error,
+    {
+        self.violation.set(Some(violation));
+        E::custom(violation)
+    }
+
+    fn check_string<E>(&self, s: &str) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        match self.limits.max_string_len {
+            Some(max) if s.len() > max => {
+                Err(self.violate(ParseLimitViolation::StringLength(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_depth<E>(&self, depth: usize) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        match self.limits.max_depth {
+            Some(max) if depth > max => Err(self.violate(ParseLimitViolation::Depth(max))),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct LimitedReader<'s, R> {
+    inner: R,
+    max: usize,
+    remaining: usize,
+    state: &'s LimitState,
+}
+
+impl<'s, R> Read for LimitedReader<'s, R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            // Probe for one more byte to tell a message that exactly fills the
+            // limit apart from one that goes over it.
+            let mut probe = [0; 1];
+            return match self.inner.read(&mut probe)? {
+                0 => Ok(0),
+                _ => {
+                    let violation = ParseLimitViolation::MessageBytes(self.max);
+                    self.state.violation.set(Some(violation));
+                    Err(io::Error::new(io::ErrorKind::InvalidData, violation))
+                }
+            };
+        }
+
+        let len = cmp::min(buf.len(), self.remaining);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ValueSeed<'s> {
+    state: &'s LimitState,
+    depth: usize,
+}
+
+impl<'de, 's> DeserializeSeed<'de> for ValueSeed<'s> {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 's> Visitor<'de> for ValueSeed<'s> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "any valid JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(Number::from_f64(value).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.state.check_string(value)?;
+        Ok(Value::String(value.into()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.state.check_string(&value)?;
+        Ok(Value::String(value))
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<V>(self, mut visitor: V) -> Result<Self::Value, V::Error>
+    where
+        V: SeqAccess<'de>,
+    {
+        let depth = self.depth + 1;
+        self.state.check_depth(depth)?;
+
+        let item_seed = ValueSeed {
+            state: self.state,
+            depth: depth,
+        };
+        let mut items = Vec::new();
+        while let Some(item) = visitor.next_element_seed(item_seed)? {
+            if let Some(max) = self.state.limits.max_array_len {
+                if items.len() == max {
+                    return Err(self.state.violate(ParseLimitViolation::ArrayLength(max)));
+                }
+            }
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<V>(self, mut visitor: V) -> Result<Self::Value, V::Error>
+    where
+        V: MapAccess<'de>,
+    {
+        let depth = self.depth + 1;
+        self.state.check_depth(depth)?;
+
+        let value_seed = ValueSeed {
+            state: self.state,
+            depth: depth,
+        };
+        let mut entries = Map::new();
+        while let Some(key) = visitor.next_key_seed(KeySeed { state: self.state })? {
+            let value = visitor.next_value_seed(value_seed)?;
+            // Remember the request id as soon as we see it, so that a limit
+            // violation later in the message can still be reported against it.
+            if depth == 1 && key == "id" {
+                self.state.id.set(value.as_u64());
+            }
+            entries.insert(key, value);
+        }
+        Ok(Value::Object(entries))
+    }
+}
+
+struct KeySeed<'s> {
+    state: &'s LimitState,
+}
+
+impl<'de, 's> DeserializeSeed<'de> for KeySeed<'s> {
+    type Value = String;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, 's> Visitor<'de> for KeySeed<'s> {
+    type Value = String;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string key")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.state.check_string(value)?;
+        Ok(value.into())
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.state.check_string(&value)?;
+        Ok(value)
+    }
+}
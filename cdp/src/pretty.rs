@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde_json::{self, Map, Value};
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use {CdpError, CdpIncoming, CdpOutgoing};
+
+const DEFAULT_MAX_BINARY_LEN: usize = 64;
+const DEFAULT_MAX_COMMAND_NAMES: usize = 256;
+const BINARY_PREVIEW_LEN: usize = 16;
+
+const ARROW_INCOMING: &'static str = "→";
+const ARROW_OUTGOING: &'static str = "←";
+
+const COLOR_DIM: &'static str = "2";
+const COLOR_NAME: &'static str = "1;36";
+const COLOR_OK: &'static str = "32";
+const COLOR_ERROR: &'static str = "31";
+
+// Renders frames as one-line summaries for logs, remembering which method
+// each command id belonged to so that its response can be labelled. Only the
+// most recent commands are remembered, so that responses that never show up
+// in the log don't pile up.
+#[derive(Clone, Debug)]
+pub struct FrameFormatter {
+    color: bool,
+    max_binary_len: usize,
+    max_command_names: usize,
+    // Each id maps to the sequence number it was remembered at, which tells
+    // its place in `command_order` apart from earlier uses of the same id.
+    command_names: HashMap<u64, (u64, String)>,
+    command_order: VecDeque<(u64, u64)>,
+    next_seq: u64,
+}
+
+impl FrameFormatter {
+    pub fn new() -> Self {
+        FrameFormatter {
+            color: false,
+            max_binary_len: DEFAULT_MAX_BINARY_LEN,
+            max_command_names: DEFAULT_MAX_COMMAND_NAMES,
+            command_names: HashMap::new(),
+            command_order: VecDeque::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn set_max_binary_len(&mut self, max_binary_len: usize) {
+        self.max_binary_len = max_binary_len;
+    }
+
+    pub fn set_max_command_names(&mut self, max_command_names: usize) {
+        self.max_command_names = max_command_names;
+        self.evict_command_names();
+    }
+
+    pub fn command_name_count(&self) -> usize {
+        self.command_names.len()
+    }
+
+    pub fn forget(&mut self, id: u64) -> bool {
+        self.command_names.remove(&id).is_some()
+    }
+
+    fn remember_command_name(&mut self, id: u64, command_name: &str) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.command_names.insert(id, (seq, command_name.into()));
+        self.command_order.push_back((id, seq));
+        self.evict_command_names();
+    }
+
+    fn evict_command_names(&mut self) {
+        while self.command_names.len() > self.max_command_names {
+            let (id, seq) = match self.command_order.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if self.command_names.get(&id).map(|&(id_seq, _)| id_seq) == Some(seq) {
+                self.command_names.remove(&id);
+            }
+        }
+
+        // Ids that have been answered (or reused) linger in the order until
+        // they'd reach the front, so sweep them out once they outnumber the
+        // ids still remembered.
+        if self.command_order.len() > 2 * self.command_names.len() + 16 {
+            let command_names = &self.command_names;
+            self.command_order.retain(|&(id, seq)| {
+                command_names.get(&id).map(|&(id_seq, _)| id_seq) == Some(seq)
+            });
+        }
+    }
+
+    pub fn format_incoming(&mut self, incoming: &CdpIncoming) -> String {
+        self.remember_command_name(incoming.id, &incoming.command_name);
+
+        let mut out = String::new();
+        out.push_str(ARROW_INCOMING);
+        out.push(' ');
+        out.push_str(&self.paint(COLOR_DIM, &format!("#{}", incoming.id)));
+        out.push(' ');
+        out.push_str(&self.paint(COLOR_NAME, &incoming.command_name));
+        if !incoming.command_params.is_empty() {
+            out.push(' ');
+            self.write_map(&mut out, &incoming.command_params);
+        }
+        out
+    }
+
+    pub fn format_outgoing(&mut self, outgoing: &CdpOutgoing) -> String {
+        let mut out = String::new();
+        out.push_str(ARROW_OUTGOING);
+        out.push(' ');
+        match *outgoing {
+            CdpOutgoing::Result { id, ref result } => {
+                out.push_str(&self.paint(COLOR_DIM, &format!("#{}", id)));
+                out.push(' ');
+                if let Some((_, command_name)) = self.command_names.remove(&id) {
+                    out.push_str(&self.paint(COLOR_NAME, &command_name));
+                    out.push(' ');
+                }
+                match *result {
+                    Ok(ref result) => {
+                        out.push_str(&self.paint(COLOR_OK, "ok"));
+                        if !result.is_empty() {
+                            out.push(' ');
+                            self.write_map(&mut out, result);
+                        }
+                    }
+                    Err(ref error) => self.write_error(&mut out, error),
+                }
+            }
+            CdpOutgoing::Event {
+                ref name,
+                ref params,
+            } => {
+                out.push_str(&self.paint(COLOR_NAME, name));
+                if !params.is_empty() {
+                    out.push(' ');
+                    self.write_map(&mut out, params);
+                }
+            }
+            CdpOutgoing::Error(ref error) => self.write_error(&mut out, error),
+        }
+        out
+    }
+
+    // Frames that fail to parse are logged verbatim rather than dropped, since
+    // those are usually the ones worth looking at.
+    pub fn format_incoming_str(&mut self, src: &str) -> String {
+        match CdpIncoming::parse_from_str(src) {
+            Ok(incoming) => self.format_incoming(&incoming),
+            Err(_) => format!("{} {}", ARROW_INCOMING, src),
+        }
+    }
+
+    pub fn format_outgoing_str(&mut self, src: &str) -> String {
+        match serde_json::from_str::<CdpOutgoing>(src) {
+            Ok(outgoing) => self.format_outgoing(&outgoing),
+            Err(_) => format!("{} {}", ARROW_OUTGOING, src),
+        }
+    }
+
+    fn write_error(&self, out: &mut String, error: &CdpError) {
+        let summary = format!("error {}", i32::from(error.kind));
+        out.push_str(&self.paint(COLOR_ERROR, &summary));
+        out.push(' ');
+        out.push_str(&error.message);
+        if let Some(ref data) = error.data {
+            out.push_str(": ");
+            self.write_value(out, data);
+        }
+    }
+
+    fn write_map(&self, out: &mut String, map: &Map<String, Value>) {
+        out.push('{');
+        for (i, (key, value)) in map.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            out.push_str(key);
+            out.push_str(": ");
+            self.write_value(out, value);
+        }
+        out.push('}');
+    }
+
+    fn write_value(&self, out: &mut String, value: &Value) {
+        match *value {
+            Value::Object(ref map) => self.write_map(out, map),
+            Value::Array(ref items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write_value(out, item);
+                }
+                out.push(']');
+            }
+            Value::String(ref s) if s.len() > self.max_binary_len && is_base64(s) => {
+                // Base64 is ASCII, so any byte offset is a char boundary.
+                let preview_len = cmp::min(s.len(), BINARY_PREVIEW_LEN);
+                let preview = Value::String(format!("{}…", &s[..preview_len]));
+                let _ = write!(out, "{} ({} bytes)", preview, s.len());
+            }
+            ref other => {
+                let _ = write!(out, "{}", other);
+            }
+        }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.into()
+        }
+    }
+}
+
+impl Default for FrameFormatter {
+    fn default() -> Self {
+        FrameFormatter::new()
+    }
+}
+
+fn is_base64(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'=')
+}
@@ -0,0 +1,229 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use futures::{future, Future};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+
+use {CdpCommand, CdpError, CdpIncoming, CdpOutgoing, CdpParams, HasCdpResponse, ParseLimits};
+
+type Handler = Box<FnMut(u64, CdpParams, &mut Write) -> Result<(), serde_json::Error>>;
+
+pub struct Dispatcher {
+    handlers: HashMap<&'static str, Handler>,
+    limits: ParseLimits,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Dispatcher::with_limits(ParseLimits::unlimited())
+    }
+
+    pub fn with_limits(limits: ParseLimits) -> Self {
+        Dispatcher {
+            handlers: HashMap::new(),
+            limits: limits,
+        }
+    }
+
+    pub fn limits(&self) -> &ParseLimits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: ParseLimits) {
+        self.limits = limits;
+    }
+
+    pub fn on<C, F>(&mut self, mut handler: F) -> &mut Self
+    where
+        C: 'static + CdpCommand + DeserializeOwned + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: Serialize,
+        F: 'static
+            + FnMut(C) -> Result<<C as HasCdpResponse<'static>>::Response, CdpError<'static>>,
+    {
+        let handler = move |id, params: CdpParams, writer: &mut Write| {
+            let command = match parse_params::<C>(params) {
+                Ok(command) => command,
+                Err(error) => {
+                    return CdpOutgoing::serialize_error_to_writer(writer, Some(id), &error);
+                }
+            };
+            match handler(command) {
+                Ok(response) => CdpOutgoing::serialize_response_to_writer(writer, id, &response),
+                Err(error) => CdpOutgoing::serialize_error_to_writer(writer, Some(id), &error),
+            }
+        };
+        self.handlers.insert(C::COMMAND_NAME, Box::new(handler));
+        self
+    }
+
+    pub fn handles(&self, command_name: &str) -> bool {
+        self.handlers.contains_key(command_name)
+    }
+
+    pub fn dispatch_to_writer<W>(
+        &mut self,
+        mut writer: W,
+        incoming: CdpIncoming,
+    ) -> Result<(), serde_json::Error>
+    where
+        W: Write,
+    {
+        let CdpIncoming {
+            id,
+            command_name,
+            command_params,
+        } = incoming;
+        match self.handlers.get_mut(command_name.as_ref()) {
+            Some(handler) => handler(id, command_params, &mut writer),
+            None => {
+                let error = CdpError::method_not_found(&command_name);
+                CdpOutgoing::serialize_error_to_writer(writer, Some(id), &error)
+            }
+        }
+    }
+
+    pub fn dispatch_to_string(
+        &mut self,
+        string: &mut String,
+        incoming: CdpIncoming,
+    ) -> Result<(), serde_json::Error> {
+        // serde_json won't produce invalid UTF-8.
+        self.dispatch_to_writer(unsafe { string.as_mut_vec() }, incoming)
+    }
+
+    pub fn dispatch_message_to_writer<'de, D, W>(
+        &mut self,
+        writer: W,
+        deserializer: D,
+    ) -> Result<(), serde_json::Error>
+    where
+        D: Deserializer<'de>,
+        W: Write,
+    {
+        let parsed = CdpIncoming::parse_with_limits(deserializer, &self.limits);
+        self.dispatch_parsed_to_writer(writer, parsed)
+    }
+
+    pub fn dispatch_reader_to_writer<R, W>(
+        &mut self,
+        writer: W,
+        reader: R,
+    ) -> Result<(), serde_json::Error>
+    where
+        R: Read,
+        W: Write,
+    {
+        let parsed = CdpIncoming::parse_from_reader_with_limits(reader, &self.limits);
+        self.dispatch_parsed_to_writer(writer, parsed)
+    }
+
+    pub fn dispatch_str_to_string(
+        &mut self,
+        string: &mut String,
+        src: &str,
+    ) -> Result<(), serde_json::Error> {
+        let parsed = CdpIncoming::parse_from_str_with_limits(src, &self.limits);
+        // serde_json won't produce invalid UTF-8.
+        self.dispatch_parsed_to_writer(unsafe { string.as_mut_vec() }, parsed)
+    }
+
+    pub fn dispatch_slice_to_writer<W>(
+        &mut self,
+        writer: W,
+        src: &[u8],
+    ) -> Result<(), serde_json::Error>
+    where
+        W: Write,
+    {
+        let parsed = CdpIncoming::parse_from_slice_with_limits(src, &self.limits);
+        self.dispatch_parsed_to_writer(writer, parsed)
+    }
+
+    fn dispatch_parsed_to_writer<W>(
+        &mut self,
+        writer: W,
+        parsed: Result<CdpIncoming, (CdpError<'static>, Option<u64>)>,
+    ) -> Result<(), serde_json::Error>
+    where
+        W: Write,
+    {
+        match parsed {
+            Ok(incoming) => self.dispatch_to_writer(writer, incoming),
+            Err((error, maybe_id)) => {
+                CdpOutgoing::serialize_error_to_writer(writer, maybe_id, &error)
+            }
+        }
+    }
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Dispatcher::new()
+    }
+}
+
+impl fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut command_names: Vec<&str> = self.handlers.keys().cloned().collect();
+        command_names.sort();
+        f.debug_struct("Dispatcher")
+            .field("handlers", &command_names)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+// Helpers for services derived with `cdp-derive`, which produce a frame for
+// every command. A response that fails to serialize is reported to the client
+// as an internal error instead. Handler errors end up in the frame, so a frame
+// future never fails.
+
+pub type HandlerFuture<R> = Box<Future<Item = R, Error = CdpError<'static>>>;
+
+pub type FrameFuture = Box<Future<Item = String, Error = ()>>;
+
+pub fn parse_params<'de, T>(params: CdpParams) -> Result<T, CdpError<'static>>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(params).map_err(|err| CdpError::invalid_params(err.to_string()))
+}
+
+pub fn result_frame<R>(id: u64, result: Result<R, CdpError>) -> String
+where
+    R: Serialize,
+{
+    let response = match result {
+        Ok(response) => response,
+        Err(error) => return error_frame(Some(id), &error),
+    };
+    let mut frame = String::new();
+    match CdpOutgoing::serialize_response_to_string(&mut frame, id, &response) {
+        Ok(()) => frame,
+        Err(err) => error_frame(Some(id), &CdpError::internal_error(err.to_string())),
+    }
+}
+
+pub fn handler_frame<R>(id: u64, handler: HandlerFuture<R>) -> FrameFuture
+where
+    R: 'static + Serialize,
+{
+    Box::new(handler.then(move |result| Ok(result_frame(id, result))))
+}
+
+pub fn ready_frame(frame: String) -> FrameFuture {
+    Box::new(future::ok(frame))
+}
+
+pub fn error_frame(maybe_id: Option<u64>, error: &CdpError) -> String {
+    let mut frame = String::new();
+    CdpOutgoing::serialize_error_to_string(&mut frame, maybe_id, error)
+        .expect("error serializing CDP error");
+    frame
+}
@@ -16,8 +16,12 @@ use std::str::FromStr;
 
 extern crate cdp;
 
-use cdp::{page, CdpCommand, CdpError, CdpEvent, CdpIncoming, CdpOutgoing, DeserializeCdpCommand,
-          DeserializeCdpEvent, Empty, ParseEnumError, SerializeCdpCommand, SerializeCdpEvent};
+use cdp::{page, CdpCommand, CdpError, CdpErrorKind, CdpEvent, CdpIncoming, CdpOutgoing,
+          DeserializeCdpCommand, DeserializeCdpEvent, Empty, ParseEnumError, ParseLimitViolation,
+          ParseLimits, SerializeCdpCommand, SerializeCdpEvent};
+use cdp::client::{CommandBatch, Correlator, Routed};
+use cdp::pretty::FrameFormatter;
+use cdp::server::Dispatcher;
 
 #[test]
 fn test_empty() {
@@ -358,6 +362,101 @@ fn test_parse_incoming_incoming_extra_field() {
     assert_eq!(CdpIncoming::parse_from_str(json), Ok(rust));
 }
 
+#[test]
+fn test_parse_incoming_within_limits() {
+    let json = r#"{"id":0,"method":"Page.enable","params":{"a":[1,2],"b":"cd"}}"#;
+    let limits = ParseLimits {
+        max_message_bytes: Some(json.len()),
+        max_depth: Some(3),
+        max_string_len: Some(11),
+        max_array_len: Some(2),
+    };
+    let rust = CdpIncoming::parse_from_str(json).expect("parse error");
+    assert_eq!(CdpIncoming::parse_from_str_with_limits(json, &limits), Ok(rust.clone()));
+    assert_eq!(
+        CdpIncoming::parse_from_slice_with_limits(json.as_bytes(), &limits),
+        Ok(rust.clone())
+    );
+    assert_eq!(CdpIncoming::parse_from_reader_with_limits(json.as_bytes(), &limits), Ok(rust));
+}
+
+#[test]
+fn test_parse_incoming_message_too_long() {
+    let json = r#"{"id":0,"method":"Page.enable"}"#;
+    let limits = ParseLimits {
+        max_message_bytes: Some(json.len() - 1),
+        ..ParseLimits::unlimited()
+    };
+    let violation = ParseLimitViolation::MessageBytes(json.len() - 1);
+    let err = (CdpError::limit_exceeded(violation), Some(0));
+    assert_eq!(CdpIncoming::parse_from_str_with_limits(json, &limits), Err(err.clone()));
+    assert_eq!(
+        CdpIncoming::parse_from_slice_with_limits(json.as_bytes(), &limits),
+        Err(err.clone())
+    );
+    assert_eq!(CdpIncoming::parse_from_reader_with_limits(json.as_bytes(), &limits), Err(err));
+
+    // An id past the limit isn't seen, and a violation before it wins.
+    let json = r#"{"method":"Page.navigate","params":{"a":{}},"id":0}"#;
+    let limits = ParseLimits {
+        max_message_bytes: Some(json.len() - 6),
+        ..ParseLimits::unlimited()
+    };
+    let violation = ParseLimitViolation::MessageBytes(json.len() - 6);
+    let err = (CdpError::limit_exceeded(violation), None);
+    assert_eq!(CdpIncoming::parse_from_str_with_limits(json, &limits), Err(err.clone()));
+    assert_eq!(CdpIncoming::parse_from_reader_with_limits(json.as_bytes(), &limits), Err(err));
+    let limits = ParseLimits {
+        max_depth: Some(1),
+        ..limits
+    };
+    let err = (CdpError::limit_exceeded(ParseLimitViolation::Depth(1)), None);
+    assert_eq!(
+        CdpIncoming::parse_from_slice_with_limits(json.as_bytes(), &limits),
+        Err(err.clone())
+    );
+    assert_eq!(CdpIncoming::parse_from_reader_with_limits(json.as_bytes(), &limits), Err(err));
+}
+
+#[test]
+fn test_parse_incoming_too_deep() {
+    let json = r#"{"id":7,"method":"Page.enable","params":{"a":{"b":{}}}}"#;
+    let limits = ParseLimits {
+        max_depth: Some(3),
+        ..ParseLimits::unlimited()
+    };
+    assert_eq!(
+        CdpIncoming::parse_from_str_with_limits(json, &limits),
+        Err((CdpError::limit_exceeded(ParseLimitViolation::Depth(3)), Some(7)))
+    );
+}
+
+#[test]
+fn test_parse_incoming_string_too_long() {
+    let json = r#"{"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#;
+    let limits = ParseLimits {
+        max_string_len: Some(16),
+        ..ParseLimits::unlimited()
+    };
+    assert_eq!(
+        CdpIncoming::parse_from_str_with_limits(json, &limits),
+        Err((CdpError::limit_exceeded(ParseLimitViolation::StringLength(16)), None))
+    );
+}
+
+#[test]
+fn test_parse_incoming_array_too_long() {
+    let json = r#"{"id":2,"method":"DOM.foo","params":{"nodeIds":[1,2,3]}}"#;
+    let limits = ParseLimits {
+        max_array_len: Some(2),
+        ..ParseLimits::unlimited()
+    };
+    assert_eq!(
+        CdpIncoming::parse_from_reader_with_limits(json.as_bytes(), &limits),
+        Err((CdpError::limit_exceeded(ParseLimitViolation::ArrayLength(2)), Some(2)))
+    );
+}
+
 #[test]
 fn test_outgoing_success_result() {
     let json = r#"{"id":1,"result":{"frameId":"0"}}"#;
@@ -466,6 +565,447 @@ fn test_outgoing_error() {
     assert_eq!(json, &serialized);
 }
 
+#[test]
+fn test_correlator_response() {
+    let mut correlator = Correlator::new();
+
+    let command = page::NavigateCommand {
+        url: "https://www.mozilla.org".into(),
+        referrer: None,
+        transition_type: None,
+    };
+    let mut serialized = String::new();
+    let id = correlator
+        .serialize_command_to_string(&mut serialized, &command)
+        .expect("serialize error");
+    assert_eq!(
+        r#"{"id":1,"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#,
+        &serialized
+    );
+    assert_eq!(Some("Page.navigate"), correlator.pending_command_name(id));
+
+    let response = match correlator.route_str(r#"{"id":1,"result":{"frameId":"0"}}"#) {
+        Ok(Routed::Response(response)) => response,
+        other => panic!("expected a correlated response: {:#?}", other),
+    };
+    assert_eq!(1, response.id);
+    assert_eq!("Page.navigate", response.command_name);
+    assert_eq!(
+        page::NavigateResponse {
+            frame_id: "0".into(),
+        },
+        response
+            .downcast::<page::NavigateResponse>()
+            .expect("downcast error")
+            .expect("response error")
+    );
+    assert_eq!(0, correlator.pending_count());
+}
+
+#[test]
+fn test_correlator_error_response() {
+    let mut correlator = Correlator::new();
+    let id = correlator.register(&page::EnableCommand);
+
+    let json = r#"{"id":1,"error":{"code":-32601,"message":"'Page.enable' wasn't found"}}"#;
+    let response = match correlator.route_str(json) {
+        Ok(Routed::Response(response)) => response,
+        other => panic!("expected a correlated response: {:#?}", other),
+    };
+    assert_eq!(id, response.id);
+    assert_eq!(
+        Err(CdpError::method_not_found("Page.enable")),
+        response
+            .downcast::<page::EnableResponse>()
+            .expect("downcast error")
+            .map(|_| ())
+    );
+}
+
+#[test]
+fn test_correlator_invalid_response() {
+    let mut correlator = Correlator::new();
+    correlator.register(&page::NavigateCommand {
+        url: "https://www.mozilla.org".into(),
+        referrer: None,
+        transition_type: None,
+    });
+
+    let response = match correlator.route_str(r#"{"id":1,"result":{"frameId":7}}"#) {
+        Ok(Routed::Response(response)) => response,
+        other => panic!("expected a correlated response: {:#?}", other),
+    };
+    let err = response
+        .downcast::<page::NavigateResponse>()
+        .expect("downcast error")
+        .expect_err("expected a decode error");
+    assert_eq!("Invalid response", err.message);
+}
+
+#[test]
+fn test_correlator_wrong_downcast() {
+    let mut correlator = Correlator::new();
+    correlator.register(&page::EnableCommand);
+
+    let response = match correlator.route_str(r#"{"id":1,"result":{}}"#) {
+        Ok(Routed::Response(response)) => response,
+        other => panic!("expected a correlated response: {:#?}", other),
+    };
+    let response = response
+        .downcast::<page::NavigateResponse>()
+        .expect_err("expected a downcast error");
+    assert!(response.downcast::<page::EnableResponse>().is_ok());
+}
+
+#[test]
+fn test_correlator_unmatched() {
+    let mut correlator = Correlator::new();
+    let id = correlator.register_raw("Foo.bar");
+    assert!(correlator.cancel(id));
+
+    let json = r#"{"method":"Page.domContentEventFired","params":{"timestamp":12.7}}"#;
+    match correlator.route_str(json) {
+        Ok(Routed::Event(CdpOutgoing::Event { name, .. })) => {
+            assert_eq!("Page.domContentEventFired", name);
+        }
+        other => panic!("expected an event: {:#?}", other),
+    }
+
+    match correlator.route_str(r#"{"id":1,"result":{}}"#) {
+        Ok(Routed::Event(CdpOutgoing::Result { id: 1, .. })) => (),
+        other => panic!("expected an unmatched result: {:#?}", other),
+    }
+}
+
+#[test]
+fn test_correlator_skips_pending_ids() {
+    let mut correlator = Correlator::with_first_id(u64::max_value());
+    assert_eq!(u64::max_value(), correlator.register_raw("Foo.bar"));
+    assert_eq!(0, correlator.register_raw("Foo.bar"));
+    assert_eq!(1, correlator.register_raw("Foo.bar"));
+
+    // 0 and 1 are still pending when the counter wraps around again.
+    correlator.cancel(u64::max_value());
+    correlator.set_next_id(u64::max_value() - 1);
+    assert_eq!(u64::max_value() - 1, correlator.register_raw("Foo.bar"));
+    assert_eq!(u64::max_value(), correlator.register_raw("Foo.bar"));
+    assert_eq!(2, correlator.register_raw("Foo.bar"));
+    assert!(correlator.is_pending(0));
+    assert!(correlator.is_pending(1));
+
+    let mut correlator = Correlator::new();
+    assert_eq!(1, correlator.register_raw("Foo.bar"));
+    assert_eq!(2, correlator.register_raw("Foo.bar"));
+    correlator.cancel(1);
+    assert_eq!(3, correlator.register_raw("Foo.bar"));
+}
+
+fn make_sample_dispatcher() -> Dispatcher {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher
+        .on(|command: page::NavigateCommand| if command.url == "about:crash" {
+            Err(CdpError::server_error("Cannot navigate to invalid URL".into()))
+        } else {
+            Ok(page::NavigateResponse {
+                frame_id: "0".into(),
+            })
+        })
+        .on(|_: page::EnableCommand| Ok(page::EnableResponse));
+    dispatcher
+}
+
+#[test]
+fn test_dispatcher_response() {
+    let mut dispatcher = make_sample_dispatcher();
+    assert!(dispatcher.handles("Page.navigate"));
+    assert!(!dispatcher.handles("Page.reload"));
+
+    let json = r#"{"id":1,"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#;
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, json)
+        .expect("dispatch error");
+    assert_eq!(r#"{"id":1,"result":{"frameId":"0"}}"#, &serialized);
+
+    let json = r#"{"id":2,"method":"Page.enable"}"#;
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_to_string(&mut serialized, CdpIncoming::parse_from_str(json).unwrap())
+        .expect("dispatch error");
+    assert_eq!(r#"{"id":2,"result":{}}"#, &serialized);
+}
+
+#[test]
+fn test_dispatcher_handler_error() {
+    let mut dispatcher = make_sample_dispatcher();
+
+    let json = r#"{"id":3,"method":"Page.navigate","params":{"url":"about:crash"}}"#;
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, json)
+        .expect("dispatch error");
+    assert_eq!(
+        r#"{"id":3,"error":{"code":-32000,"message":"Cannot navigate to invalid URL"}}"#,
+        &serialized
+    );
+}
+
+#[test]
+fn test_dispatcher_method_not_found() {
+    let mut dispatcher = make_sample_dispatcher();
+
+    let json = r#"{"id":1,"method":"Foo.bar","params":{}}"#;
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, json)
+        .expect("dispatch error");
+    assert_eq!(
+        r#"{"id":1,"error":{"code":-32601,"message":"'Foo.bar' wasn't found"}}"#,
+        &serialized
+    );
+}
+
+#[test]
+fn test_dispatcher_invalid_params() {
+    let mut dispatcher = make_sample_dispatcher();
+
+    let json = r#"{"id":4,"method":"Page.navigate","params":{"url":7}}"#;
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, json)
+        .expect("dispatch error");
+    match serde_json::from_str(&serialized).expect("parse error") {
+        CdpOutgoing::Result { id: 4, result: Err(error) } => {
+            assert_eq!(CdpErrorKind::InvalidParams, error.kind);
+            assert_eq!("Invalid parameters", error.message);
+        }
+        other => panic!("expected an invalid params error: {:#?}", other),
+    }
+}
+
+#[test]
+fn test_dispatcher_invalid_message() {
+    let mut dispatcher = make_sample_dispatcher();
+
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, "hello")
+        .expect("dispatch error");
+    assert_eq!(
+        r#"{"error":{"code":-32700,"message":"Message must be a valid JSON"}}"#,
+        &serialized
+    );
+
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, r#"{"id":5}"#)
+        .expect("dispatch error");
+    assert_eq!(
+        r#"{"id":5,"error":{"code":-32600,"message":"Message must have string 'method' porperty"}}"#,
+        &serialized
+    );
+}
+
+#[test]
+fn test_dispatcher_limits() {
+    let mut dispatcher = make_sample_dispatcher();
+    dispatcher.set_limits(ParseLimits {
+        max_string_len: Some(16),
+        ..ParseLimits::unlimited()
+    });
+
+    let json = r#"{"id":6,"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#;
+    let mut serialized = String::new();
+    dispatcher
+        .dispatch_str_to_string(&mut serialized, json)
+        .expect("dispatch error");
+    assert_eq!(
+        r#"{"id":6,"error":{"code":-32600,"message":"Message exceeds parse limits","data":"message contains a string longer than 16 bytes"}}"#,
+        &serialized
+    );
+}
+
+#[test]
+fn test_frame_formatter() {
+    let mut formatter = FrameFormatter::new();
+    assert_eq!(
+        formatter.format_incoming_str(
+            r#"{"id":12,"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#
+        ),
+        r#"→ #12 Page.navigate {url: "https://www.mozilla.org"}"#
+    );
+    assert_eq!(
+        formatter.format_outgoing_str(r#"{"id":12,"result":{"frameId":"1.1"}}"#),
+        r#"← #12 Page.navigate ok {frameId: "1.1"}"#
+    );
+    assert_eq!(
+        formatter.format_outgoing_str(r#"{"id":12,"result":{}}"#),
+        "← #12 ok"
+    );
+    assert_eq!(
+        formatter.format_outgoing_str(
+            r#"{"method":"Page.frameNavigated","params":{"frame":{"id":"1.1","urls":[1,true]}}}"#
+        ),
+        r#"← Page.frameNavigated {frame: {id: "1.1", urls: [1, true]}}"#
+    );
+    assert_eq!(formatter.format_incoming_str("hello"), "→ hello");
+}
+
+#[test]
+fn test_frame_formatter_error() {
+    let mut formatter = FrameFormatter::new();
+    formatter.format_incoming_str(r#"{"id":3,"method":"Page.navigate"}"#);
+    assert_eq!(
+        formatter.format_outgoing(&CdpOutgoing::Result {
+            id: 3,
+            result: Err(CdpError::invalid_params("url: missing field".into())),
+        }),
+        r#"← #3 Page.navigate error -32602 Invalid parameters: "url: missing field""#
+    );
+    assert_eq!(
+        formatter.format_outgoing(&CdpOutgoing::Error(CdpError::invalid_message())),
+        "← error -32700 Message must be a valid JSON"
+    );
+}
+
+#[test]
+fn test_frame_formatter_eviction() {
+    let mut formatter = FrameFormatter::new();
+    for id in 0..1000 {
+        formatter.format_incoming_str(&format!(r#"{{"id":{},"method":"Page.enable"}}"#, id));
+    }
+    assert_eq!(256, formatter.command_name_count());
+
+    formatter.set_max_command_names(2);
+    assert_eq!(2, formatter.command_name_count());
+    formatter.format_incoming_str(r#"{"id":1000,"method":"Page.reload"}"#);
+    assert_eq!(2, formatter.command_name_count());
+    assert_eq!(formatter.format_outgoing_str(r#"{"id":998,"result":{}}"#), "← #998 ok");
+    assert_eq!(
+        formatter.format_outgoing_str(r#"{"id":999,"result":{}}"#),
+        "← #999 Page.enable ok"
+    );
+
+    // A reused id is remembered under its latest command.
+    formatter.format_incoming_str(r#"{"id":7,"method":"Page.enable"}"#);
+    formatter.format_incoming_str(r#"{"id":7,"method":"Page.disable"}"#);
+    formatter.format_incoming_str(r#"{"id":8,"method":"Page.stopLoading"}"#);
+    assert_eq!(
+        formatter.format_outgoing_str(r#"{"id":7,"result":{}}"#),
+        "← #7 Page.disable ok"
+    );
+    assert_eq!(
+        formatter.format_outgoing_str(r#"{"id":1000,"result":{}}"#),
+        "← #1000 ok"
+    );
+}
+
+#[test]
+fn test_frame_formatter_binary() {
+    let mut formatter = FrameFormatter::new();
+    formatter.set_max_binary_len(20);
+    assert_eq!(
+        formatter.format_outgoing_str(
+            r#"{"id":1,"result":{"data":"iVBORw0KGgoAAAANSUhEUgAAAAEAAAAB","mimeType":"image/png"}}"#
+        ),
+        r#"← #1 ok {data: "iVBORw0KGgoAAAAN…" (32 bytes), mimeType: "image/png"}"#
+    );
+
+    formatter.set_color(true);
+    assert_eq!(
+        formatter.format_incoming_str(r#"{"id":2,"method":"Page.enable"}"#),
+        "→ \x1b[2m#2\x1b[0m \x1b[1;36mPage.enable\x1b[0m"
+    );
+}
+
+#[test]
+fn test_command_batch() {
+    let mut batch = CommandBatch::new(10);
+    let enable = batch.push(&page::EnableCommand).expect("serialize error");
+    let navigate = batch
+        .push(&page::NavigateCommand {
+            url: "https://www.mozilla.org".into(),
+            referrer: None,
+            transition_type: None,
+        })
+        .expect("serialize error");
+    let raw = batch
+        .push_raw(&page::ReloadCommand {
+            ignore_cache: Some(true),
+            script_to_evaluate_on_load: None,
+        })
+        .expect("serialize error");
+    assert_eq!(vec![10, 11, 12], batch.ids().collect::<Vec<_>>());
+    assert_eq!((10, 11, 12), (enable.id(), navigate.id(), raw.id()));
+    assert_eq!(
+        vec![
+            r#"{"id":10,"method":"Page.enable","params":{}}"#,
+            r#"{"id":11,"method":"Page.navigate","params":{"url":"https://www.mozilla.org"}}"#,
+            r#"{"id":12,"method":"Page.reload","params":{"ignoreCache":true}}"#,
+        ],
+        batch.frames().collect::<Vec<_>>()
+    );
+
+    let mut collector = batch.collector();
+    let unmatched = r#"{"method":"Page.loadEventFired","params":{}}"#;
+    assert!(collector.collect_str(unmatched).unwrap().is_some());
+    assert!(collector.collect_str(r#"{"id":11,"result":{"frameId":"0"}}"#).unwrap().is_none());
+    assert!(collector.collect_str(r#"{"id":13,"result":{}}"#).unwrap().is_some());
+    assert!(collector.collect_str(r#"{"id":11,"result":{}}"#).unwrap().is_some());
+    assert_eq!(2, collector.remaining());
+    assert_eq!(None, collector.take(enable));
+    assert!(collector
+        .collect_str(r#"{"id":10,"error":{"code":-32000,"message":"Oops"}}"#)
+        .unwrap()
+        .is_none());
+    assert!(collector.collect_str(r#"{"id":12,"result":{"x":1}}"#).unwrap().is_none());
+    assert!(collector.is_complete());
+
+    assert_eq!(
+        Some(Err(CdpError::server_error("Oops".into()))),
+        collector.take(enable)
+    );
+    assert_eq!(
+        Some(Ok(page::NavigateResponse {
+            frame_id: "0".into(),
+        })),
+        collector.take(navigate)
+    );
+    assert_eq!(
+        Some(Ok(serde_json::from_str(r#"{"x":1}"#).unwrap())),
+        collector.take(raw)
+    );
+    assert_eq!(None, collector.take(navigate));
+
+    // A repeated response doesn't refill a slot that's already been taken.
+    assert!(collector.collect_str(r#"{"id":11,"result":{"frameId":"1"}}"#).unwrap().is_some());
+    assert!(collector.is_complete());
+    assert_eq!(None, collector.take(navigate));
+
+    batch.reset(20);
+    assert!(batch.is_empty());
+    assert_eq!(20, batch.push(&page::EnableCommand).expect("serialize error").id());
+    assert_eq!(Some(r#"{"id":20,"method":"Page.enable","params":{}}"#), batch.frame(0));
+    assert_eq!(None, batch.frame(1));
+}
+
+#[test]
+fn test_command_batch_wrapping_ids() {
+    let mut batch = CommandBatch::new(u64::max_value() - 1);
+    let first = batch.push(&page::EnableCommand).expect("serialize error");
+    let second = batch.push(&page::EnableCommand).expect("serialize error");
+    let third = batch.push(&page::EnableCommand).expect("serialize error");
+    assert_eq!(vec![u64::max_value() - 1, u64::max_value(), 0], batch.ids().collect::<Vec<_>>());
+    assert_eq!((u64::max_value() - 1, u64::max_value(), 0), (first.id(), second.id(), third.id()));
+    assert_eq!(Some(r#"{"id":0,"method":"Page.enable","params":{}}"#), batch.frame(2));
+
+    let mut collector = batch.collector();
+    assert!(collector.collect_str(r#"{"id":0,"result":{}}"#).unwrap().is_none());
+    assert!(collector.collect_str(r#"{"id":1,"result":{}}"#).unwrap().is_some());
+    assert_eq!(2, collector.remaining());
+    assert_eq!(Some(Ok(page::EnableResponse)), collector.take(third));
+}
+
 pub fn do_test_json<T>(json: &str, rust: &T)
 where
     for<'de> T: Deserialize<'de> + Serialize + PartialEq + Debug,
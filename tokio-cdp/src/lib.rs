@@ -8,6 +8,7 @@
 #![cfg_attr(feature = "clippy", plugin(clippy))]
 #![doc(html_root_url = "https://docs.rs/tokio-cdp/0.1.0")]
 
+extern crate cdp;
 #[macro_use]
 extern crate futures;
 extern crate hyper;
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use futures::{Async, Future, Poll, Sink, StartSend, Stream};
+use futures::future;
+use std::fmt;
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+use websocket::ClientBuilder;
+use websocket::async::Client as WsClient;
+use websocket::client::async::ClientNew;
+use websocket::result::WebSocketError;
+use websocket_transport::WsTransport;
+
+use common::Omitted;
+use greeter::{Target, WsEndpoint};
+
+pub struct ToolsClient<T> {
+    ws_transport: WsTransport<WsClient<T>>,
+}
+
+impl ToolsClient<TcpStream> {
+    pub fn connect(handle: &Handle, ws_url: &str) -> ToolsClientConnect {
+        let future = match ClientBuilder::new(ws_url) {
+            Ok(builder) => builder.async_connect_insecure(handle),
+            Err(err) => Box::new(future::err(WebSocketError::from(err))),
+        };
+        ToolsClientConnect { future: future }
+    }
+
+    pub fn connect_to_target<'a>(handle: &Handle, target: &Target<'a>) -> ToolsClientConnect {
+        ToolsClient::connect(handle, &target.ws_url)
+    }
+
+    pub fn connect_to_endpoint(
+        handle: &Handle,
+        server_addr: &str,
+        endpoint: &WsEndpoint,
+    ) -> ToolsClientConnect {
+        ToolsClient::connect(handle, &endpoint.url(server_addr))
+    }
+}
+
+impl<T> fmt::Debug for ToolsClient<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ToolsClient")
+            .field("ws_transport", &Omitted)
+            .finish()
+    }
+}
+
+impl<T> Stream for ToolsClient<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = String;
+    type Error = WebSocketError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.ws_transport.poll()
+    }
+}
+
+impl<T> Sink for ToolsClient<T>
+where
+    T: AsyncWrite,
+{
+    type SinkItem = String;
+    type SinkError = WebSocketError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.ws_transport.start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.ws_transport.poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.ws_transport.close()
+    }
+}
+
+pub struct ToolsClientConnect {
+    future: ClientNew<TcpStream>,
+}
+
+impl fmt::Debug for ToolsClientConnect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ToolsClientConnect")
+            .field("future", &Omitted)
+            .finish()
+    }
+}
+
+impl Future for ToolsClientConnect {
+    type Item = ToolsClient<TcpStream>;
+    type Error = WebSocketError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (ws, _) = try_ready!(self.future.poll());
+        let ws_transport = WsTransport::new(ws);
+        let client = ToolsClient {
+            ws_transport: ws_transport,
+        };
+        Ok(Async::Ready(client))
+    }
+}
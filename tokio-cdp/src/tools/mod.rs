@@ -2,5 +2,6 @@
 // v. 2.0. If a copy of the MPL was not distributed with this file, You can
 // obtain one at http://mozilla.org/MPL/2.0/.
 
-// pub mod client;
+pub mod client;
 pub mod server;
+pub mod session;
@@ -0,0 +1,670 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use cdp::{CdpError, CdpOutgoing, CdpParams, DeserializeCdpEvent, HasCdpResponse,
+          SerializeCdpCommand};
+use cdp::client::{CorrelatedResponse, Correlator, Routed};
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::sync::oneshot;
+use futures::task::{self, Task};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Map, Value};
+use std::any::Any;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_io::{AsyncRead, AsyncWrite};
+use websocket::result::WebSocketError;
+
+use common::Omitted;
+use tools::client::ToolsClient;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ToolsCallError {
+        CdpError(inner: CdpError<'static>) {
+            from()
+            description("CDP error response")
+            display(me) -> ("{}: {}", me.description(), inner)
+            cause(inner)
+        }
+        JsonError(inner: serde_json::Error) {
+            from()
+            description("JSON serialization error")
+            display(me) -> ("{}: {}", me.description(), inner)
+            cause(inner)
+        }
+        IoError(inner: io::Error) {
+            from()
+            description("I/O error")
+            display(me) -> ("{}: {}", me.description(), inner)
+            cause(inner)
+        }
+        TimedOut {
+            description("timed out waiting for a response")
+        }
+        Disconnected {
+            description("disconnected before receiving a response")
+        }
+        // Shared by every call that was in flight when the connection failed.
+        ConnectionError(inner: Rc<WebSocketError>) {
+            description("connection error")
+            display(me) -> ("{}: {}", me.description(), inner)
+            cause(&**inner)
+        }
+    }
+}
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ToolsEventError {
+        JsonError(inner: serde_json::Error) {
+            from()
+            description("JSON parse error")
+            display(me) -> ("{}: {}", me.description(), inner)
+            cause(inner)
+        }
+        Lagged(count: u64) {
+            description("fell behind and missed events")
+            display(me) -> ("{} ({} missed)", me.description(), count)
+        }
+    }
+}
+
+// How many events a subscriber can have waiting, and what happens when it
+// falls behind. A lagging subscriber drops its oldest events to make room,
+// and then reports how many it dropped as a `ToolsEventError::Lagged` before
+// going on with the rest. A blocking subscriber stops the session from reading
+// any more messages until it catches up, which holds up responses and every
+// other subscriber as well. Either buffer holds at least one event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum EventBuffer {
+    Lagging(usize),
+    Blocking(usize),
+}
+
+impl EventBuffer {
+    fn capacity(&self) -> usize {
+        match *self {
+            EventBuffer::Lagging(capacity) | EventBuffer::Blocking(capacity) => {
+                cmp::max(capacity, 1)
+            }
+        }
+    }
+}
+
+impl Default for EventBuffer {
+    fn default() -> Self {
+        EventBuffer::Lagging(128)
+    }
+}
+
+// A session sends commands over a `ToolsClient` and matches up the responses
+// by id, so any number of calls can be in flight on the same connection.
+// Events are handed to every subscriber that recognizes them. The connection
+// itself is driven by a task spawned on the session's reactor, which closes it
+// once every handle to the session has been dropped; calls that are still in
+// flight at that point fail with `Disconnected` (or `ConnectionError`, if the
+// connection failed instead), and event streams end.
+#[derive(Clone)]
+pub struct ToolsSession {
+    handle: Handle,
+    shared: Rc<RefCell<SessionShared>>,
+    outgoing: UnboundedSender<String>,
+    maybe_timeout: Option<Duration>,
+}
+
+impl ToolsSession {
+    pub fn new<T>(handle: &Handle, client: ToolsClient<T>) -> Self
+    where
+        T: 'static + AsyncRead + AsyncWrite,
+    {
+        let shared = Rc::new(RefCell::new(SessionShared {
+            correlator: Correlator::new(),
+            waiters: HashMap::new(),
+            maybe_error: None,
+            subscribers: Vec::new(),
+            maybe_driver_task: None,
+            disconnected: false,
+        }));
+        let (outgoing, outgoing_receiver) = mpsc::unbounded();
+
+        handle.spawn(SessionDriver {
+            client: client,
+            shared: Rc::clone(&shared),
+            outgoing: outgoing_receiver,
+            maybe_frame: None,
+        });
+
+        ToolsSession {
+            handle: handle.clone(),
+            shared: shared,
+            outgoing: outgoing,
+            maybe_timeout: None,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.maybe_timeout
+    }
+
+    // Applies to calls made through this handle (and clones made from it
+    // afterward) that don't give their own timeout.
+    pub fn set_timeout(&mut self, maybe_timeout: Option<Duration>) {
+        self.maybe_timeout = maybe_timeout;
+    }
+
+    pub fn call<C>(&self, command: C) -> ToolsCall<<C as HasCdpResponse<'static>>::Response>
+    where
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        self.start_call(&command, self.maybe_timeout)
+    }
+
+    pub fn call_with_timeout<C>(
+        &self,
+        command: C,
+        timeout: Duration,
+    ) -> ToolsCall<<C as HasCdpResponse<'static>>::Response>
+    where
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        self.start_call(&command, Some(timeout))
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.shared.borrow().waiters.len()
+    }
+
+    pub fn events<T>(&self) -> ToolsEvents<T>
+    where
+        T: 'static + DeserializeCdpEvent<'static>,
+    {
+        self.events_with_buffer(EventBuffer::default())
+    }
+
+    pub fn events_with_buffer<T>(&self, buffer: EventBuffer) -> ToolsEvents<T>
+    where
+        T: 'static + DeserializeCdpEvent<'static>,
+    {
+        let mut shared = self.shared.borrow_mut();
+        let queue = Rc::new(RefCell::new(EventQueue {
+            events: VecDeque::new(),
+            buffer: buffer,
+            lagged: 0,
+            maybe_task: None,
+            closed: shared.disconnected,
+        }));
+        shared.subscribers.push(Box::new(Rc::downgrade(&queue)));
+        ToolsEvents {
+            queue: queue,
+            shared: Rc::downgrade(&self.shared),
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        let shared = self.shared.borrow();
+        shared
+            .subscribers
+            .iter()
+            .filter(|subscriber| !subscriber.is_gone())
+            .count()
+    }
+
+    fn start_call<C>(
+        &self,
+        command: &C,
+        maybe_timeout: Option<Duration>,
+    ) -> ToolsCall<<C as HasCdpResponse<'static>>::Response>
+    where
+        C: SerializeCdpCommand + HasCdpResponse<'static>,
+        <C as HasCdpResponse<'static>>::Response: DeserializeOwned + Any,
+    {
+        let maybe_timeout = match maybe_timeout {
+            None => None,
+            Some(duration) => match Timeout::new(duration, &self.handle) {
+                Ok(timeout) => Some(timeout),
+                Err(err) => return ToolsCall::failed(ToolsCallError::from(err)),
+            },
+        };
+
+        let mut shared = self.shared.borrow_mut();
+        let mut frame = String::new();
+        let id = match shared
+            .correlator
+            .serialize_command_to_string(&mut frame, command)
+        {
+            Ok(id) => id,
+            Err(err) => return ToolsCall::failed(ToolsCallError::from(err)),
+        };
+        if self.outgoing.unbounded_send(frame).is_err() {
+            shared.correlator.cancel(id);
+            return ToolsCall::failed(shared.disconnected_error());
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        shared.waiters.insert(id, sender);
+
+        ToolsCall {
+            state: ToolsCallState::Pending {
+                id: id,
+                receiver: receiver,
+                maybe_timeout: maybe_timeout,
+                shared: Rc::downgrade(&self.shared),
+            },
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl fmt::Debug for ToolsSession {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ToolsSession")
+            .field("correlator", &self.shared.borrow().correlator)
+            .field("maybe_timeout", &self.maybe_timeout)
+            .finish()
+    }
+}
+
+struct SessionShared {
+    correlator: Correlator,
+    waiters: HashMap<u64, oneshot::Sender<Result<CorrelatedResponse, ToolsCallError>>>,
+    maybe_error: Option<Rc<WebSocketError>>,
+    subscribers: Vec<Box<Subscriber>>,
+    maybe_driver_task: Option<Task>,
+    disconnected: bool,
+}
+
+impl SessionShared {
+    fn route(&mut self, frame: &str) {
+        match self.correlator.route_str(frame) {
+            Ok(Routed::Response(response)) => {
+                if let Some(waiter) = self.waiters.remove(&response.id) {
+                    let _ = waiter.send(Ok(response));
+                }
+            }
+            Ok(Routed::Event(CdpOutgoing::Event { name, params })) => self.publish(&name, params),
+            // Responses to calls that have since been cancelled, errors that
+            // aren't attached to a call, and anything that doesn't parse are
+            // dropped.
+            _ => {}
+        }
+    }
+
+    fn publish(&mut self, name: &str, params: Map<String, Value>) {
+        self.subscribers.retain(|subscriber| !subscriber.is_gone());
+
+        // A subscriber that takes the event uses up its params, so the next
+        // one needs a fresh copy.
+        let mut maybe_params = None;
+        for subscriber in &self.subscribers {
+            let event_params = maybe_params
+                .take()
+                .unwrap_or_else(|| CdpParams::from(params.clone()));
+            if let Err(event_params) = subscriber.offer(name, event_params) {
+                maybe_params = Some(event_params);
+            }
+        }
+    }
+
+    // Holds off reading the next message while a blocking subscriber is
+    // full, until it takes an event (or goes away).
+    fn park_if_blocked(&mut self) -> bool {
+        let blocked = self.subscribers
+            .iter()
+            .any(|subscriber| subscriber.is_blocking());
+        if blocked {
+            self.maybe_driver_task = Some(task::current());
+        }
+        blocked
+    }
+
+    fn unpark_driver(&mut self) {
+        if let Some(task) = self.maybe_driver_task.take() {
+            task.notify();
+        }
+    }
+
+    fn disconnected_error(&self) -> ToolsCallError {
+        match self.maybe_error {
+            Some(ref err) => ToolsCallError::ConnectionError(Rc::clone(err)),
+            None => ToolsCallError::Disconnected,
+        }
+    }
+
+    fn cancel(&mut self, id: u64) {
+        self.correlator.cancel(id);
+        self.waiters.remove(&id);
+    }
+
+    fn disconnect(&mut self, maybe_error: Option<WebSocketError>) {
+        self.disconnected = true;
+        self.maybe_error = maybe_error.map(Rc::new);
+        let waiters = mem::replace(&mut self.waiters, HashMap::new());
+        for (_, waiter) in waiters {
+            let _ = waiter.send(Err(self.disconnected_error()));
+        }
+        for subscriber in self.subscribers.drain(..) {
+            subscriber.close();
+        }
+    }
+}
+
+struct SessionDriver<T> {
+    client: ToolsClient<T>,
+    shared: Rc<RefCell<SessionShared>>,
+    outgoing: UnboundedReceiver<String>,
+    maybe_frame: Option<String>,
+}
+
+impl<T> SessionDriver<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    fn poll_client(&mut self) -> Poll<(), WebSocketError> {
+        loop {
+            let frame = match self.maybe_frame.take() {
+                Some(frame) => frame,
+                None => match self.outgoing.poll() {
+                    Ok(Async::Ready(Some(frame))) => frame,
+                    Ok(Async::Ready(None)) | Err(()) => return self.client.close(),
+                    Ok(Async::NotReady) => break,
+                },
+            };
+            if let AsyncSink::NotReady(frame) = self.client.start_send(frame)? {
+                self.maybe_frame = Some(frame);
+                break;
+            }
+        }
+        self.client.poll_complete()?;
+
+        while !self.shared.borrow_mut().park_if_blocked() {
+            match try_ready!(self.client.poll()) {
+                Some(frame) => self.shared.borrow_mut().route(&frame),
+                None => return Ok(Async::Ready(())),
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl<T> Future for SessionDriver<T>
+where
+    T: AsyncRead + AsyncWrite,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (result, maybe_error) = match self.poll_client() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(())) => (Ok(Async::Ready(())), None),
+            Err(err) => (Err(()), Some(err)),
+        };
+        self.shared.borrow_mut().disconnect(maybe_error);
+        result
+    }
+}
+
+pub struct ToolsCall<R> {
+    state: ToolsCallState,
+    phantom: PhantomData<R>,
+}
+
+enum ToolsCallState {
+    Failed(Option<ToolsCallError>),
+    Pending {
+        id: u64,
+        receiver: oneshot::Receiver<Result<CorrelatedResponse, ToolsCallError>>,
+        maybe_timeout: Option<Timeout>,
+        shared: Weak<RefCell<SessionShared>>,
+    },
+    Done,
+}
+
+impl<R> ToolsCall<R> {
+    fn failed(err: ToolsCallError) -> Self {
+        ToolsCall {
+            state: ToolsCallState::Failed(Some(err)),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        match self.state {
+            ToolsCallState::Pending { id, .. } => Some(id),
+            _ => None,
+        }
+    }
+
+    // Stops waiting on the response, which is dropped if it arrives later.
+    fn finish(&mut self) {
+        if let ToolsCallState::Pending { id, shared, .. } =
+            mem::replace(&mut self.state, ToolsCallState::Done)
+        {
+            if let Some(shared) = shared.upgrade() {
+                shared.borrow_mut().cancel(id);
+            }
+        }
+    }
+}
+
+impl<R> Future for ToolsCall<R>
+where
+    R: Any,
+{
+    type Item = R;
+    type Error = ToolsCallError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = match self.state {
+            ToolsCallState::Failed(ref mut err) => {
+                return Err(err.take()
+                    .expect("tokio-cdp: ToolsCall polled after error"))
+            }
+            ToolsCallState::Pending {
+                ref mut receiver,
+                ref mut maybe_timeout,
+                ..
+            } => match receiver.poll() {
+                Ok(Async::Ready(Ok(response))) => response
+                    .downcast::<R>()
+                    .expect("tokio-cdp: ToolsCall response decoded to the wrong type")
+                    .map_err(ToolsCallError::from),
+                Ok(Async::Ready(Err(err))) => Err(err),
+                Err(oneshot::Canceled) => Err(ToolsCallError::Disconnected),
+                Ok(Async::NotReady) => match maybe_timeout.as_mut().map(Future::poll) {
+                    None | Some(Ok(Async::NotReady)) => return Ok(Async::NotReady),
+                    Some(Ok(Async::Ready(()))) => Err(ToolsCallError::TimedOut),
+                    Some(Err(err)) => Err(ToolsCallError::from(err)),
+                },
+            },
+            ToolsCallState::Done => panic!("tokio-cdp: ToolsCall polled after completion"),
+        };
+        self.finish();
+        result.map(Async::Ready)
+    }
+}
+
+// Dropping a call before it completes cancels it.
+impl<R> Drop for ToolsCall<R> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl<R> fmt::Debug for ToolsCall<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ToolsCall")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl fmt::Debug for ToolsCallState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ToolsCallState::Failed(ref maybe_err) => {
+                f.debug_tuple("Failed").field(maybe_err).finish()
+            }
+            ToolsCallState::Pending {
+                id,
+                ref maybe_timeout,
+                ..
+            } => f.debug_struct("Pending")
+                .field("id", &id)
+                .field("receiver", &Omitted)
+                .field("maybe_timeout", maybe_timeout)
+                .field("shared", &Omitted)
+                .finish(),
+            ToolsCallState::Done => f.debug_tuple("Done").finish(),
+        }
+    }
+}
+
+trait Subscriber {
+    fn is_gone(&self) -> bool;
+    fn is_blocking(&self) -> bool;
+    // Gives the params back if the subscriber doesn't recognize the event.
+    fn offer(&self, name: &str, params: CdpParams) -> Result<(), CdpParams>;
+    fn close(&self);
+}
+
+struct EventQueue<T> {
+    events: VecDeque<Result<T, serde_json::Error>>,
+    buffer: EventBuffer,
+    lagged: u64,
+    maybe_task: Option<Task>,
+    closed: bool,
+}
+
+impl<T> EventQueue<T> {
+    fn push(&mut self, event: Result<T, serde_json::Error>) {
+        if let EventBuffer::Lagging(_) = self.buffer {
+            if self.events.len() >= self.buffer.capacity() {
+                self.events.pop_front();
+                self.lagged += 1;
+            }
+        }
+        self.events.push_back(event);
+        self.notify();
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.maybe_task.take() {
+            task.notify();
+        }
+    }
+}
+
+impl<T> Subscriber for Weak<RefCell<EventQueue<T>>>
+where
+    T: 'static + DeserializeCdpEvent<'static>,
+{
+    fn is_gone(&self) -> bool {
+        self.upgrade().is_none()
+    }
+
+    fn is_blocking(&self) -> bool {
+        match self.upgrade() {
+            None => false,
+            Some(queue) => {
+                let queue = queue.borrow();
+                match queue.buffer {
+                    EventBuffer::Lagging(_) => false,
+                    EventBuffer::Blocking(_) => queue.events.len() >= queue.buffer.capacity(),
+                }
+            }
+        }
+    }
+
+    fn offer(&self, name: &str, params: CdpParams) -> Result<(), CdpParams> {
+        let queue = match self.upgrade() {
+            None => return Err(params),
+            Some(queue) => queue,
+        };
+        let event = T::deserialize_event(name, params)?;
+        queue.borrow_mut().push(event);
+        Ok(())
+    }
+
+    fn close(&self) {
+        if let Some(queue) = self.upgrade() {
+            let mut queue = queue.borrow_mut();
+            queue.closed = true;
+            queue.notify();
+        }
+    }
+}
+
+pub struct ToolsEvents<T> {
+    queue: Rc<RefCell<EventQueue<T>>>,
+    shared: Weak<RefCell<SessionShared>>,
+}
+
+impl<T> ToolsEvents<T> {
+    fn unpark_driver(&self) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.borrow_mut().unpark_driver();
+        }
+    }
+}
+
+impl<T> Stream for ToolsEvents<T> {
+    type Item = T;
+    type Error = ToolsEventError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let maybe_event = {
+            let mut queue = self.queue.borrow_mut();
+            if queue.lagged > 0 {
+                let lagged = mem::replace(&mut queue.lagged, 0);
+                return Err(ToolsEventError::Lagged(lagged));
+            }
+            match queue.events.pop_front() {
+                Some(event) => Some(event),
+                None if queue.closed => None,
+                None => {
+                    queue.maybe_task = Some(task::current());
+                    return Ok(Async::NotReady);
+                }
+            }
+        };
+        self.unpark_driver();
+        match maybe_event {
+            Some(event) => event.map(|event| Async::Ready(Some(event))).map_err(From::from),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+// A blocking subscriber that goes away can't hold up the session anymore.
+impl<T> Drop for ToolsEvents<T> {
+    fn drop(&mut self) {
+        self.unpark_driver();
+    }
+}
+
+impl<T> fmt::Debug for ToolsEvents<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let queue = self.queue.borrow();
+        f.debug_struct("ToolsEvents")
+            .field("waiting", &queue.events.len())
+            .field("buffer", &queue.buffer)
+            .field("lagged", &queue.lagged)
+            .field("closed", &queue.closed)
+            .finish()
+    }
+}
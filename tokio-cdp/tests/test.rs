@@ -16,21 +16,28 @@ extern crate tokio_service;
 
 extern crate tokio_cdp;
 
-use futures::{Future, Stream};
-use futures::future::{self, FutureResult};
+use cdp::{page, CdpError};
+use cdp::server::Dispatcher;
+use futures::{Future, Sink, Stream};
+use futures::future::{self, Either, FutureResult};
+use futures::stream;
 use hyper::{Method, Request, StatusCode, Uri};
 use std::cell::RefCell;
 use std::io;
 use std::net::{Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use tokio_core::net::TcpListener;
-use tokio_core::reactor::{Core, Handle};
+use std::time::Duration;
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::{Core, Handle, Timeout};
 use tokio_service::Service;
 
 use tokio_cdp::greeter::{ActivateResponse, BrowserInfo, CloseResponse, Target, TargetKind,
                          WsEndpoint};
 use tokio_cdp::greeter::client::{GreeterClient, GreeterClientError};
 use tokio_cdp::greeter::server::{GreeterError, GreeterRequest, GreeterResponse, GreeterServer};
+use tokio_cdp::tools::client::ToolsClient;
+use tokio_cdp::tools::server::ToolsServerStart;
+use tokio_cdp::tools::session::{EventBuffer, ToolsCallError, ToolsEventError, ToolsSession};
 
 fn make_sample_browser_info() -> BrowserInfo<'static> {
     BrowserInfo {
@@ -124,6 +131,83 @@ impl Service for SampleGreeterService {
     }
 }
 
+fn make_sample_dispatcher() -> Dispatcher {
+    let mut dispatcher = Dispatcher::new();
+    dispatcher
+        .on(|command: page::NavigateCommand| if command.url == "about:crash" {
+            Err(CdpError::server_error("Cannot navigate to invalid URL".into()))
+        } else {
+            Ok(page::NavigateResponse {
+                frame_id: command.url,
+            })
+        })
+        .on(|_: page::EnableCommand| Ok(page::EnableResponse));
+    dispatcher
+}
+
+// The "cdp" browser endpoint answers commands with `make_sample_dispatcher`,
+// except for `Page.stopLoading`, which it never answers. The "drop" browser
+// endpoint closes the connection without reading anything. Every other endpoint
+// replies to each message with the endpoint's name followed by the message.
+fn serve_tools(
+    handle: &Handle,
+    endpoint: WsEndpoint,
+    tools_start: ToolsServerStart<TcpStream>,
+) -> Box<Future<Item = (), Error = ()>> {
+    if let WsEndpoint::Browser(Some(ref id)) = endpoint {
+        if id == "drop" {
+            // Closing the socket while the client's messages are still unread
+            // resets the connection, so the client's next read fails.
+            let handle = handle.clone();
+            let future = tools_start.accept().then(move |result| {
+                let server = result.expect("tools server error");
+                let timeout = Timeout::new(Duration::from_millis(100), &handle)
+                    .expect("timeout creation error");
+                timeout.then(move |_| {
+                    drop(server);
+                    Ok(())
+                })
+            });
+            return Box::new(future);
+        }
+    }
+
+    let mut dispatcher = make_sample_dispatcher();
+    let future = tools_start.accept().and_then(move |server| {
+        let (sink, messages) = server.split();
+        let sink = sink.with_flat_map(|frames: Vec<String>| stream::iter_ok(frames));
+        let replies = messages.map(move |message| match endpoint {
+            WsEndpoint::Browser(Some(ref id)) if id == "cdp" => {
+                if message.contains(r#""method":"Page.stopLoading""#) {
+                    return vec![];
+                }
+                // Navigating fires a couple of events ahead of the response.
+                let mut frames = vec![];
+                if message.contains(r#""method":"Page.navigate""#) {
+                    frames.push(
+                        r#"{"method":"Page.domContentEventFired","params":{"timestamp":12.7}}"#
+                            .to_string(),
+                    );
+                    frames.push(
+                        r#"{"method":"Page.loadEventFired","params":{"timestamp":12.8}}"#
+                            .to_string(),
+                    );
+                }
+                let mut reply = String::new();
+                dispatcher
+                    .dispatch_str_to_string(&mut reply, &message)
+                    .expect("dispatch error");
+                frames.push(reply);
+                frames
+            }
+            _ => vec![format!("{:?}: {}", endpoint, message)],
+        });
+        sink.send_all(replies).map(|_| ())
+    });
+    // The connection ends with an error if the client goes away first.
+    Box::new(future.then(|_| Ok(())))
+}
+
 fn start_server() -> (Core, Handle, String, Arc<RefCell<Vec<Target<'static>>>>) {
     let core = Core::new().expect("core creation error");
     let handle = core.handle();
@@ -143,11 +227,14 @@ fn start_server() -> (Core, Handle, String, Arc<RefCell<Vec<Target<'static>>>>)
         listener
             .incoming()
             .for_each(move |(tcp, remote_addr)| {
+                let tools_handle = handle.clone();
                 handle.spawn(
                     GreeterServer::bind(&handle, tcp, remote_addr, greeter_service.clone()).then(
-                        |result| {
-                            result.expect("server error");
-                            Ok(())
+                        move |result| match result.expect("server error") {
+                            None => Either::A(future::ok(())),
+                            Some((endpoint, tools_start)) => {
+                                Either::B(serve_tools(&tools_handle, endpoint, tools_start))
+                            }
                         },
                     ),
                 );
@@ -371,3 +458,303 @@ fn test_greeter_server_error() {
         _ => panic!("expected GreeterClientError::BadStatus: {:#?}", err),
     }
 }
+
+#[test]
+fn test_tools_client_target() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+    let client = GreeterClient::new(&handle, server_addr.clone());
+    let target = core.run(client.open(None)).expect("client error");
+
+    let test = ToolsClient::connect_to_target(&handle, &target)
+        .and_then(|tools| tools.send("Page.enable".into()))
+        .and_then(|tools| tools.into_future().map_err(|(err, _)| err))
+        .map(|(maybe_message, _)| {
+            assert_eq!(Some(r#"Page("about:blank"): Page.enable"#.into()), maybe_message);
+        });
+    core.run(test).expect("tools client error");
+}
+
+#[test]
+fn test_tools_client_endpoint() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+
+    let endpoint = WsEndpoint::Browser(None);
+    let test = ToolsClient::connect_to_endpoint(&handle, &server_addr, &endpoint)
+        .and_then(|tools| tools.send("Browser.getVersion".into()))
+        .and_then(|tools| tools.send("Target.getTargets".into()))
+        .and_then(|tools| tools.take(2).collect())
+        .map(|messages| {
+            assert_eq!(
+                vec![
+                    "Browser(None): Browser.getVersion".to_string(),
+                    "Browser(None): Target.getTargets".to_string(),
+                ],
+                messages
+            );
+        });
+    core.run(test).expect("tools client error");
+}
+
+#[test]
+fn test_tools_client_bad_endpoint() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+
+    let ws_url = format!("ws://{server_addr}/devtools/foo", server_addr = server_addr);
+    let test = ToolsClient::connect(&handle, &ws_url);
+    core.run(test).expect_err("expected tools client error");
+}
+
+#[test]
+fn test_tools_client_bad_url() {
+    let (mut core, handle, _server_addr, _targets) = start_server();
+
+    let test = ToolsClient::connect(&handle, "not a url");
+    core.run(test).expect_err("expected tools client error");
+}
+
+fn connect_session(core: &mut Core, handle: &Handle, server_addr: &str) -> ToolsSession {
+    let endpoint = WsEndpoint::Browser(Some("cdp".into()));
+    let test = ToolsClient::connect_to_endpoint(handle, server_addr, &endpoint);
+    let client = core.run(test).expect("tools client error");
+    ToolsSession::new(handle, client)
+}
+
+fn make_navigate_command(url: &str) -> page::NavigateCommand<'static> {
+    page::NavigateCommand {
+        url: url.to_string().into(),
+        referrer: None,
+        transition_type: None,
+    }
+}
+
+#[test]
+fn test_tools_session_call() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let test = session.call(make_navigate_command("https://www.mozilla.org"));
+    let response = core.run(test).expect("call error");
+    assert_eq!("https://www.mozilla.org", response.frame_id);
+    assert_eq!(0, session.pending_count());
+}
+
+#[test]
+fn test_tools_session_concurrent_calls() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let navigate = session.call(make_navigate_command("https://www.mozilla.org"));
+    let enable = session.call(page::EnableCommand);
+    let crash = session.call(make_navigate_command("about:crash"));
+    assert_eq!(3, session.pending_count());
+
+    // Responses that arrive for the other calls wait for them to be polled.
+    match core.run(crash) {
+        Err(ToolsCallError::CdpError(error)) => assert_eq!(
+            CdpError::server_error("Cannot navigate to invalid URL".into()),
+            error
+        ),
+        other => panic!("expected ToolsCallError::CdpError: {:#?}", other),
+    }
+    assert_eq!(page::EnableResponse, core.run(enable).expect("call error"));
+    let response = core.run(navigate).expect("call error");
+    assert_eq!("https://www.mozilla.org", response.frame_id);
+    assert_eq!(0, session.pending_count());
+}
+
+#[test]
+fn test_tools_session_timeout() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+    let mut session = connect_session(&mut core, &handle, &server_addr);
+
+    let test = session.call_with_timeout(page::StopLoadingCommand, Duration::from_millis(50));
+    match core.run(test) {
+        Err(ToolsCallError::TimedOut) => {}
+        other => panic!("expected ToolsCallError::TimedOut: {:#?}", other),
+    }
+    assert_eq!(0, session.pending_count());
+
+    session.set_timeout(Some(Duration::from_millis(50)));
+    match core.run(session.call(page::StopLoadingCommand)) {
+        Err(ToolsCallError::TimedOut) => {}
+        other => panic!("expected ToolsCallError::TimedOut: {:#?}", other),
+    }
+
+    let test = session.call(page::EnableCommand);
+    assert_eq!(page::EnableResponse, core.run(test).expect("call error"));
+}
+
+#[test]
+fn test_tools_session_cancel() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let call = session.call(page::StopLoadingCommand);
+    assert!(call.id().is_some());
+    assert_eq!(1, session.pending_count());
+    drop(call);
+    assert_eq!(0, session.pending_count());
+
+    let test = session.call(page::EnableCommand);
+    assert_eq!(page::EnableResponse, core.run(test).expect("call error"));
+}
+
+#[test]
+fn test_tools_session_connection_error() {
+    let (mut core, handle, server_addr, _targets) = start_server();
+    let endpoint = WsEndpoint::Browser(Some("drop".into()));
+    let test = ToolsClient::connect_to_endpoint(&handle, &server_addr, &endpoint);
+    let client = core.run(test).expect("tools client error");
+    let session = ToolsSession::new(&handle, client);
+
+    let enable = session.call(page::EnableCommand);
+    let stop_loading = session.call(page::StopLoadingCommand);
+    match core.run(enable) {
+        Err(ToolsCallError::ConnectionError(_)) => {}
+        other => panic!("expected ToolsCallError::ConnectionError: {:#?}", other),
+    }
+    match core.run(stop_loading) {
+        Err(ToolsCallError::ConnectionError(_)) => {}
+        other => panic!("expected ToolsCallError::ConnectionError: {:#?}", other),
+    }
+    assert_eq!(0, session.pending_count());
+
+    // Calls made after the failure report it too.
+    match core.run(session.call(page::EnableCommand)) {
+        Err(ToolsCallError::ConnectionError(_)) => {}
+        other => panic!("expected ToolsCallError::ConnectionError: {:#?}", other),
+    }
+}
+
+#[test]
+fn test_tools_session_events() {
+    let (mut core, handle, server_addr, _) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let loads = session.events::<page::LoadEventFiredEvent>();
+    let more_loads = session.events::<page::LoadEventFiredEvent>();
+    let dom_contents = session.events::<page::DomContentEventFiredEvent>();
+    assert_eq!(3, session.subscriber_count());
+
+    let test = session.call(make_navigate_command("https://example.com"));
+    core.run(test).expect("call error");
+
+    let load = page::LoadEventFiredEvent { timestamp: 12.8 };
+    let test = loads.take(1).collect();
+    assert_eq!(vec![load.clone()], core.run(test).expect("event error"));
+    let test = more_loads.take(1).collect();
+    assert_eq!(vec![load], core.run(test).expect("event error"));
+    let test = dom_contents.take(1).collect();
+    assert_eq!(
+        vec![page::DomContentEventFiredEvent { timestamp: 12.7 }],
+        core.run(test).expect("event error")
+    );
+    assert_eq!(0, session.subscriber_count());
+}
+
+#[test]
+fn test_tools_session_events_dropped_subscriber() {
+    let (mut core, handle, server_addr, _) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let loads = session.events::<page::LoadEventFiredEvent>();
+    drop(session.events::<page::LoadEventFiredEvent>());
+    assert_eq!(1, session.subscriber_count());
+
+    let test = session.call(make_navigate_command("https://example.com"));
+    core.run(test).expect("call error");
+    let test = loads.take(1).collect();
+    assert_eq!(
+        vec![page::LoadEventFiredEvent { timestamp: 12.8 }],
+        core.run(test).expect("event error")
+    );
+}
+
+#[test]
+fn test_tools_session_events_lagging() {
+    let (mut core, handle, server_addr, _) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let loads = session.events_with_buffer::<page::LoadEventFiredEvent>(EventBuffer::Lagging(1));
+    for url in &["https://example.com", "https://example.org"] {
+        let test = session.call(make_navigate_command(url));
+        core.run(test).expect("call error");
+    }
+
+    let loads = match core.run(loads.into_future()) {
+        Err((ToolsEventError::Lagged(1), loads)) => loads,
+        other => panic!("expected a lagged error: {:?}", other),
+    };
+    let (maybe_load, _loads) = core.run(loads.into_future())
+        .map_err(|(err, _)| err)
+        .expect("event error");
+    assert_eq!(Some(page::LoadEventFiredEvent { timestamp: 12.8 }), maybe_load);
+}
+
+#[test]
+fn test_tools_session_events_blocking() {
+    let (mut core, handle, server_addr, _) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let loads = session.events_with_buffer::<page::LoadEventFiredEvent>(EventBuffer::Blocking(1));
+
+    // The load event fills the buffer ahead of the response, so the response
+    // isn't read until the event is taken.
+    let command = make_navigate_command("https://example.com");
+    let test = session.call_with_timeout(command, Duration::from_millis(100));
+    match core.run(test) {
+        Err(ToolsCallError::TimedOut) => {}
+        other => panic!("expected the call to time out: {:?}", other),
+    }
+
+    let (maybe_load, _loads) = core.run(loads.into_future())
+        .map_err(|(err, _)| err)
+        .expect("event error");
+    assert_eq!(Some(page::LoadEventFiredEvent { timestamp: 12.8 }), maybe_load);
+
+    let test = session.call(page::EnableCommand);
+    assert_eq!(page::EnableResponse, core.run(test).expect("call error"));
+}
+
+#[test]
+fn test_tools_session_events_zero_capacity() {
+    let (mut core, handle, server_addr, _) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    // An empty buffer holds one event all the same, rather than blocking the
+    // session for good (or lagging every event).
+    let loads = session.events_with_buffer::<page::LoadEventFiredEvent>(EventBuffer::Blocking(0));
+    let dom_contents =
+        session.events_with_buffer::<page::DomContentEventFiredEvent>(EventBuffer::Lagging(0));
+    let test = session.call(page::EnableCommand);
+    assert_eq!(page::EnableResponse, core.run(test).expect("call error"));
+
+    let command = make_navigate_command("https://example.com");
+    let test = session.call_with_timeout(command, Duration::from_millis(100));
+    match core.run(test) {
+        Err(ToolsCallError::TimedOut) => {}
+        other => panic!("expected the call to time out: {:?}", other),
+    }
+    let (maybe_load, _loads) = core.run(loads.into_future())
+        .map_err(|(err, _)| err)
+        .expect("event error");
+    assert_eq!(Some(page::LoadEventFiredEvent { timestamp: 12.8 }), maybe_load);
+    let (maybe_dom_content, _dom_contents) = core.run(dom_contents.into_future())
+        .map_err(|(err, _)| err)
+        .expect("event error");
+    assert_eq!(
+        Some(page::DomContentEventFiredEvent { timestamp: 12.7 }),
+        maybe_dom_content
+    );
+}
+
+#[test]
+fn test_tools_session_events_end() {
+    let (mut core, handle, server_addr, _) = start_server();
+    let session = connect_session(&mut core, &handle, &server_addr);
+
+    let loads = session.events::<page::LoadEventFiredEvent>();
+    drop(session);
+    let test = loads.collect();
+    assert_eq!(Vec::<page::LoadEventFiredEvent>::new(), core.run(test).expect("event error"));
+}